@@ -1,6 +1,7 @@
 use std::iter::{Iterator, Peekable};
 use std::str::FromStr;
 
+use crate::generator::{GeneratorOptions, Material};
 use log::LevelFilter;
 
 fn try_parse_option<I: Iterator<Item = String>>(
@@ -99,9 +100,17 @@ pub struct CMDOptions {
   pub depth: usize,
   pub depth_extend: usize,
   pub skip: usize,
+  pub max_nodes: u64,
   pub cache_memory_bytes: usize,
+  pub seed: u64,
+  pub puzzles: usize,
+  pub games: usize,
+  pub generator_options: GeneratorOptions,
+  pub tablebases: Vec<String>,
+  pub expected_filename: String,
   pub output_filename: String,
   pub format_target: bool,
+  pub verify: bool,
   pub level_filter: LevelFilter,
   pub args: Vec<String>,
 }
@@ -110,12 +119,20 @@ impl CMDOptions {
   pub fn new<I: Iterator<Item = String>>(it: I) -> Self {
     let mut depth = 0;
     let mut skip = 0;
+    let mut max_nodes = 0;
     let mut depth_extend = 0;
     let mut p = it.peekable();
     let mut format_target = false;
+    let mut verify = false;
     let mut level_filter = LevelFilter::Error;
     let mut output_filename = String::new();
     let mut cache_memory_bytes = 128 << 20;
+    let mut seed = 1;
+    let mut puzzles = 100;
+    let mut games = 100;
+    let mut generator_options = GeneratorOptions::default();
+    let mut tablebases = Vec::new();
+    let mut expected_filename = String::new();
     loop {
       if let Some(d) = try_parse_arg_option::<usize, _>(&mut p, "d", "depth") {
         depth = d;
@@ -129,10 +146,50 @@ impl CMDOptions {
         skip = n;
         continue;
       }
+      if let Some(n) = try_parse_arg_option::<u64, _>(&mut p, "", "max-nodes") {
+        max_nodes = n;
+        continue;
+      }
       if let Some(n) = try_parse_arg_option::<usize, _>(&mut p, "c", "cache-memory-mib") {
         cache_memory_bytes = n << 20;
         continue;
       }
+      if let Some(n) = try_parse_arg_option::<u64, _>(&mut p, "", "seed") {
+        seed = n;
+        continue;
+      }
+      if let Some(n) = try_parse_arg_option::<usize, _>(&mut p, "", "puzzles") {
+        puzzles = n;
+        continue;
+      }
+      if let Some(n) = try_parse_arg_option::<usize, _>(&mut p, "", "games") {
+        games = n;
+        continue;
+      }
+      if let Some(m) = try_parse_arg_option::<Material, _>(&mut p, "", "attacker-hand") {
+        generator_options.attacker_hand = m;
+        continue;
+      }
+      if let Some(m) = try_parse_arg_option::<Material, _>(&mut p, "", "attacker") {
+        generator_options.attacker = m;
+        continue;
+      }
+      if let Some(m) = try_parse_arg_option::<Material, _>(&mut p, "", "defender") {
+        generator_options.defender = m;
+        continue;
+      }
+      if let Some(n) = try_parse_arg_option::<usize, _>(&mut p, "", "radius") {
+        generator_options.radius = n;
+        continue;
+      }
+      if let Some(f) = try_parse_arg_option::<String, _>(&mut p, "", "tablebase") {
+        tablebases.push(f);
+        continue;
+      }
+      if let Some(f) = try_parse_arg_option::<String, _>(&mut p, "", "expected") {
+        expected_filename = f;
+        continue;
+      }
       if let Some(o) = try_parse_arg_option::<String, _>(&mut p, "o", "output") {
         output_filename = o;
         continue;
@@ -157,15 +214,27 @@ impl CMDOptions {
         format_target = true;
         continue;
       }
+      if try_parse_option(&mut p, "", "verify") {
+        verify = true;
+        continue;
+      }
       break;
     }
     CMDOptions {
       depth,
       depth_extend,
       skip,
+      max_nodes,
       cache_memory_bytes,
+      seed,
+      puzzles,
+      games,
+      generator_options,
+      tablebases,
+      expected_filename,
       output_filename,
       format_target,
+      verify,
       level_filter,
       args: p.collect(),
     }
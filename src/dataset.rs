@@ -0,0 +1,98 @@
+//mate labels of game positions for training data: mate in N for the side to move
+//(with the first move of the principal variation), no mate within the depth or aborted search
+use super::json;
+use super::search::Search;
+use super::shogi::moves::Move;
+use super::shogi::packed::{Payload, DM_ABORTED};
+use super::shogi::{validation, Position};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Label {
+  //the move is None if the mate isn't unique
+  Mate(u8, Option<Move>),
+  NoMate(u8),
+  //reason: side to move is in check, invalid position or node limit
+  Aborted(String),
+}
+
+//search is aborted after max_nodes (0 is unlimited) nodes
+pub fn label(s: &mut Search, pos: &Position, max_depth: u8, max_nodes: u64) -> Label {
+  let mut pos = pos.clone();
+  let swapped = pos.side < 0;
+  if swapped {
+    pos.swap_sides();
+  }
+  if pos.is_check() {
+    return Label::Aborted(String::from("side to move is in check"));
+  }
+  let problems = pos.validate();
+  if !problems.is_empty() {
+    return Label::Aborted(format!(
+      "invalid position ({})",
+      validation::describe(&problems)
+    ));
+  }
+  pos.move_no = 1;
+  s.set_max_nodes(max_nodes);
+  let (res, pv) = s.search(&mut pos, max_depth);
+  s.set_max_nodes(0);
+  s.hashes_clear();
+  if s.is_aborted() {
+    return Label::Aborted(format!("node limit {}", max_nodes));
+  }
+  match res {
+    Some(res) => Label::Mate(
+      res,
+      pv.and_then(|pv| pv.into_iter().next()).map(|mut m| {
+        if swapped {
+          m.swap_side();
+        }
+        m
+      }),
+    ),
+    None => Label::NoMate(max_depth),
+  }
+}
+
+impl Label {
+  fn name(&self) -> &'static str {
+    match self {
+      Label::Mate(_, _) => "mate",
+      Label::NoMate(_) => "nomate",
+      Label::Aborted(_) => "aborted",
+    }
+  }
+  //one line of JSON lines output, ply is the move number of the position
+  pub fn to_json(&self, pos: &Position, game_no: usize, ply: u32) -> json::Value {
+    let mut a = vec![
+      ("sfen", json::Value::from(pos.to_string())),
+      ("game", json::Value::from(game_no as u32)),
+      ("ply", json::Value::from(ply)),
+      ("label", json::Value::from(self.name())),
+    ];
+    match self {
+      Label::Mate(res, m) => {
+        a.push(("dm", json::Value::from(*res as u32)));
+        if let Some(m) = m {
+          a.push(("move", json::Value::from(m.to_usi())));
+        }
+      }
+      Label::NoMate(depth) => a.push(("depth", json::Value::from(*depth as u32))),
+      Label::Aborted(reason) => a.push(("reason", json::Value::from(reason.as_str()))),
+    }
+    json::object(a)
+  }
+  //packed SFEN record payload: dm is mate length, 0 without mate and DM_ABORTED for aborted search
+  pub fn to_payload(&self, ply: u32) -> Payload {
+    let (best_move, dm) = match self {
+      Label::Mate(res, m) => (m.as_ref().map_or(0, u32::from), *res as u16),
+      Label::NoMate(_) => (0, 0),
+      Label::Aborted(_) => (0, DM_ABORTED),
+    };
+    Payload {
+      best_move,
+      dm,
+      move_no: ply as u16,
+    }
+  }
+}
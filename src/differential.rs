@@ -0,0 +1,215 @@
+//differential testing of the fast move generator against the reference one
+use super::generator::Rng;
+use super::shogi::alloc::PositionMovesAllocator;
+use super::shogi::between::Between;
+use super::shogi::moves::{Move, PSNMove};
+use super::shogi::{perft, piece, Position};
+
+fn keys(moves: &[Move]) -> Vec<u32> {
+  let mut v: Vec<u32> = moves.iter().map(u32::from).collect();
+  v.sort_unstable();
+  v
+}
+
+fn legal(pos: &mut Position, moves: Vec<Move>) -> Vec<Move> {
+  moves
+    .into_iter()
+    .filter(|m| {
+      let u = pos.do_move(m);
+      let r = pos.is_legal();
+      pos.undo_move(m, &u);
+      r
+    })
+    .collect()
+}
+
+fn diff_moves(pos: &Position, what: &str, fast: &[Move], reference: &[Move]) -> Option<String> {
+  let (a, b) = (keys(fast), keys(reference));
+  if a == b {
+    return None;
+  }
+  let f = |v: &[u32], w: &[u32]| -> Vec<String> {
+    v.iter()
+      .filter(|x| !w.contains(x))
+      .map(|&x| PSNMove::new(pos, &Move::from(x)).to_string())
+      .collect()
+  };
+  Some(format!(
+    "{}: extra [{}], missing [{}]{}",
+    what,
+    f(&a, &b).join(" "),
+    f(&b, &a).join(" "),
+    if a.len() != b.len() && f(&a, &b).is_empty() && f(&b, &a).is_empty() {
+      ", duplicates"
+    } else {
+      ""
+    }
+  ))
+}
+
+pub struct Harness {
+  allocator: PositionMovesAllocator,
+  b: Between,
+  rng: Rng,
+  pub positions: u64,
+  pub checks: u64,
+  pub mates: u64,
+}
+
+impl Harness {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      allocator: PositionMovesAllocator::default(),
+      b: Between::default(),
+      rng: Rng::new(seed),
+      positions: 0,
+      checks: 0,
+      mates: 0,
+    }
+  }
+  //descriptions of disagreements between the fast and the reference generators
+  pub fn compare(&mut self, pos: &mut Position) -> Vec<String> {
+    self.positions += 1;
+    let mut r = Vec::new();
+    let fast = perft::legal_moves(pos, &mut self.allocator);
+    let reference = pos.reference_legal_moves();
+    r.extend(diff_moves(pos, "legal moves", &fast, &reference));
+    let mut fast_checks: Vec<usize> = pos.compute_checks().attacking_pieces.collect();
+    fast_checks.sort_unstable();
+    let reference_checks = pos.reference_checks();
+    if fast_checks != reference_checks {
+      r.push(format!(
+        "checks: fast {:?}, reference {:?}",
+        fast_checks, reference_checks
+      ));
+      return r;
+    }
+    let checks = pos.compute_checks();
+    if checks.is_check() {
+      self.checks += 1;
+      let moves = pos.compute_moves_after_check(&mut self.allocator, &checks, &mut self.b);
+      let fast = legal(pos, moves);
+      let reference: Vec<Move> = reference.into_iter().filter(|m| !m.is_drop()).collect();
+      r.extend(diff_moves(pos, "moves after check", &fast, &reference));
+      let mate = pos
+        .is_checkmate_after_check(&mut self.allocator, &checks, &mut self.b)
+        .is_none();
+      if mate {
+        self.mates += 1;
+      }
+      if mate != pos.reference_is_checkmate() {
+        r.push(format!(
+          "checkmate: fast {}, reference {}",
+          mate,
+          pos.reference_is_checkmate()
+        ));
+      }
+    } else {
+      let mut moves = pos.compute_check_candidates(&mut self.allocator, &checks);
+      moves.extend(pos.compute_drops_with_check(&mut self.allocator, true));
+      let mut fast = legal(pos, moves);
+      fast.sort_by_key(|m| u32::from(m));
+      fast.dedup();
+      r.extend(diff_moves(
+        pos,
+        "checking moves",
+        &fast,
+        &pos.reference_checking_moves(),
+      ));
+    }
+    r
+  }
+  //greedy removal of pieces while the disagreement persists
+  pub fn shrink(&mut self, pos: &Position) -> Position {
+    shrink(pos, |q| !self.compare(q).is_empty())
+  }
+  //walks random games from pos comparing generators in every position and its children,
+  //prefers checks and captures to reach tactical positions, returns first failing positions
+  pub fn walk(&mut self, pos: &Position, plies: usize) -> Option<(Position, Vec<String>)> {
+    let mut pos = pos.clone();
+    for _ in 0..plies {
+      let e = self.compare(&mut pos);
+      if !e.is_empty() {
+        return Some((pos, e));
+      }
+      let moves = perft::legal_moves(&mut pos, &mut self.allocator);
+      if moves.is_empty() {
+        break;
+      }
+      let mut tactical = Vec::new();
+      for m in &moves {
+        let u = pos.do_move(m);
+        let e = self.compare(&mut pos);
+        if !e.is_empty() {
+          return Some((pos, e));
+        }
+        if u.is_take() || pos.is_check() {
+          tactical.push(m.clone());
+        }
+        pos.undo_move(m, &u);
+      }
+      let m = if !tactical.is_empty() && self.rng.chance(1, 2) {
+        tactical[self.rng.below(tactical.len())].clone()
+      } else {
+        moves[self.rng.below(moves.len())].clone()
+      };
+      pos.do_move(&m);
+    }
+    None
+  }
+}
+
+//greedy removal of pieces (keeping legal positions) while fails holds
+pub fn shrink(pos: &Position, mut fails: impl FnMut(&mut Position) -> bool) -> Position {
+  let mut pos = pos.clone();
+  loop {
+    let mut found = false;
+    for c in candidates(&pos) {
+      let mut q = c;
+      if !q.is_legal() {
+        continue;
+      }
+      if fails(&mut q) {
+        pos = q;
+        found = true;
+        break;
+      }
+    }
+    if !found {
+      return pos;
+    }
+  }
+}
+
+//positions with one piece less (on board or in hand), kings are kept
+fn candidates(pos: &Position) -> Vec<Position> {
+  let mut r = Vec::new();
+  let mut board = [piece::NONE; 81];
+  for (k, v) in board.iter_mut().enumerate() {
+    *v = pos.piece_at(k);
+  }
+  let (black, white) = (*pos.pockets(1), *pos.pockets(-1));
+  for k in 0..81 {
+    let v = board[k];
+    if v == piece::NONE || v.abs() == piece::KING {
+      continue;
+    }
+    let mut b = board;
+    b[k] = piece::NONE;
+    r.push(Position::from_board(b, black, white, pos.side, pos.move_no));
+  }
+  for p in piece::PAWN..piece::KING {
+    let p = p as usize;
+    if black[p] > 0 {
+      let mut b = black;
+      b[p] -= 1;
+      r.push(Position::from_board(board, b, white, pos.side, pos.move_no));
+    }
+    if white[p] > 0 {
+      let mut w = white;
+      w[p] -= 1;
+      r.push(Position::from_board(board, black, w, pos.side, pos.move_no));
+    }
+  }
+  r
+}
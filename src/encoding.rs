@@ -0,0 +1,142 @@
+//text decoding of game records: UTF-8 and Shift_JIS (CP932) used by desktop KIF software
+mod cp932;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+  Utf8,
+  ShiftJis,
+}
+
+fn is_lead_byte(b: u8) -> bool {
+  (0x81..=0x9f).contains(&b) || (0xe0..=0xfc).contains(&b)
+}
+
+//unmapped and truncated codes are replaced by U+FFFD
+pub fn decode_cp932(bytes: &[u8]) -> String {
+  let mut r = String::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let b = bytes[i];
+    i += 1;
+    if b < 0x80 {
+      r.push(b as char);
+    } else if (0xa1..=0xdf).contains(&b) {
+      //half-width katakana
+      r.push(char::from_u32(0xff61 + (b - 0xa1) as u32).unwrap());
+    } else if is_lead_byte(b) && i < bytes.len() && (0x40..=0xfc).contains(&bytes[i]) {
+      let lead = (if b < 0xa0 { b - 0x81 } else { b - 0xe0 + 31 }) as usize;
+      let code = cp932::CP932[189 * lead + (bytes[i] - 0x40) as usize];
+      i += 1;
+      r.push(
+        char::from_u32(code as u32)
+          .filter(|_| code != 0)
+          .unwrap_or('\u{fffd}'),
+      );
+    } else {
+      r.push('\u{fffd}');
+    }
+  }
+  r
+}
+
+//"encoding=" declaration in the first lines of KIF file ("#KIF version=2.0 encoding=Shift_JIS")
+fn declared_encoding(bytes: &[u8]) -> Option<Encoding> {
+  let head = &bytes[..bytes.len().min(1024)];
+  let key = b"encoding=";
+  let k = head.windows(key.len()).position(|w| w == key)?;
+  let value: Vec<u8> = head[k + key.len()..]
+    .iter()
+    .take_while(|c| c.is_ascii_alphanumeric() || **c == b'-' || **c == b'_')
+    .map(|c| c.to_ascii_lowercase())
+    .collect();
+  match value.as_slice() {
+    b"utf-8" | b"utf8" => Some(Encoding::Utf8),
+    b"shift_jis" | b"sjis" | b"shift-jis" | b"cp932" | b"windows-31j" => Some(Encoding::ShiftJis),
+    _ => None,
+  }
+}
+
+//BOM, KIF encoding declaration, file extension (.kifu and .ki2u are UTF-8) and finally UTF-8 validity
+pub fn detect(filename: &str, bytes: &[u8]) -> Encoding {
+  if bytes.starts_with(b"\xef\xbb\xbf") {
+    return Encoding::Utf8;
+  }
+  if let Some(e) = declared_encoding(bytes) {
+    return e;
+  }
+  if filename.ends_with(".kifu") || filename.ends_with(".ki2u") {
+    return Encoding::Utf8;
+  }
+  //bytes could be the beginning of the file cut in the middle of a character
+  if std::str::from_utf8(bytes).map_or_else(|err| err.error_len().is_none(), |_| true) {
+    Encoding::Utf8
+  } else {
+    Encoding::ShiftJis
+  }
+}
+
+pub fn decode(bytes: &[u8], encoding: Encoding) -> String {
+  match encoding {
+    Encoding::Utf8 => {
+      let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+      String::from_utf8_lossy(bytes).into_owned()
+    }
+    Encoding::ShiftJis => decode_cp932(bytes),
+  }
+}
+
+pub fn read_to_string(filename: &str) -> std::io::Result<String> {
+  let bytes = std::fs::read(filename)?;
+  let encoding = detect(filename, &bytes);
+  log::debug!("{}: {:?}", filename, encoding);
+  Ok(decode(&bytes, encoding))
+}
+
+//number of bytes at the beginning of the file used by open() for encoding detection
+const HEAD_SIZE: u64 = 64 << 10;
+
+//decodes the text line by line ('\n' isn't a part of multibyte CP932 characters)
+struct Decoder<R> {
+  inner: R,
+  encoding: Encoding,
+  raw: Vec<u8>,
+  line: Vec<u8>,
+  pos: usize,
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    if self.pos == self.line.len() {
+      self.raw.clear();
+      if self.inner.read_until(b'\n', &mut self.raw)? == 0 {
+        return Ok(0);
+      }
+      self.line = decode(&self.raw, self.encoding).into_bytes();
+      self.pos = 0;
+    }
+    let n = buf.len().min(self.line.len() - self.pos);
+    buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+    self.pos += n;
+    Ok(n)
+  }
+}
+
+//UTF-8 stream of the file, large collections of games aren't read into memory,
+//encoding is detected by the beginning of the file
+pub fn open(filename: &str) -> std::io::Result<Box<dyn BufRead>> {
+  let mut file = File::open(filename)?;
+  let mut head = Vec::new();
+  (&mut file).take(HEAD_SIZE).read_to_end(&mut head)?;
+  let encoding = detect(filename, &head);
+  log::debug!("{}: {:?}", filename, encoding);
+  Ok(Box::new(BufReader::new(Decoder {
+    inner: BufReader::new(Cursor::new(head).chain(file)),
+    encoding,
+    raw: Vec::new(),
+    line: Vec::new(),
+    pos: 0,
+  })))
+}
@@ -0,0 +1,21 @@
+#!/usr/bin/python3
+#CP932 (Microsoft Shift_JIS) double byte characters table, 0 for unmapped codes
+import sys
+
+filename = sys.argv[0].removesuffix('.py') + '.rs'
+f = open(filename, 'w')
+
+leads = list(range(0x81, 0xa0)) + list(range(0xe0, 0xfd))
+trails = range(0x40, 0xfd)
+a = []
+for lead in leads:
+  for trail in trails:
+    try:
+      s = bytes([lead, trail]).decode('cp932')
+      assert(len(s) == 1)
+      a.append(ord(s))
+    except UnicodeDecodeError:
+      a.append(0)
+
+f.write('//Do not edit. Machine generated.\n')
+f.write('pub static CP932: [u16; {}] = {};\n'.format(len(a), str(a)))
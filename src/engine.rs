@@ -0,0 +1,314 @@
+//USI protocol loop of the tsume engine: "go mate" is answered with "checkmate <moves>"
+//(some mating line if the mate isn't unique), "checkmate nomate" or "checkmate timeout"
+use super::search::Search;
+use super::shogi::usi::{moves_to_usi, parse_usi_position};
+use super::shogi::Position;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_DEPTH: usize = 31;
+//search is recursive, default stack of spawned threads (2 MiB) isn't enough for deep mates
+const WORKER_STACK_SIZE: usize = 64 << 20;
+
+enum Event {
+  Command(String),
+  Eof,
+  //search id, search back from the worker and reply (None if search was stopped)
+  Done(u32, Box<Search>, Option<String>),
+}
+
+//search which result is awaited
+struct Current {
+  id: u32,
+  stop: Arc<AtomicBool>,
+  deadline: Option<Instant>,
+}
+
+//None if search was stopped
+fn solve(
+  s: &mut Search,
+  pos: &Position,
+  max_depth: usize,
+  stop: Arc<AtomicBool>,
+) -> Option<String> {
+  let mut pos = pos.clone();
+  let swapped = pos.side < 0;
+  if swapped {
+    pos.swap_sides();
+  }
+  //only checks are searched, so the side in check has no tsume
+  if pos.is_check() {
+    return Some(String::from("checkmate nomate"));
+  }
+  pos.move_no = 1;
+  s.set_stop(Some(stop));
+  let pv = match s.search(&mut pos, max_depth.min(u8::MAX as usize) as u8) {
+    (Some(_), Some(pv)) => Some(Some(pv)),
+    (Some(res), None) => {
+      log::info!("Tsume in {} moves isn't unique, sfen: {}", res, pos);
+      Some(s.mating_line(&mut pos, res))
+    }
+    _ => None,
+  };
+  s.set_stop(None);
+  if s.is_aborted() {
+    return None;
+  }
+  Some(match pv {
+    Some(Some(mut pv)) => {
+      if swapped {
+        for m in pv.iter_mut() {
+          m.swap_side();
+        }
+      }
+      format!("checkmate {}", moves_to_usi(&pv))
+    }
+    Some(None) => {
+      log::error!("Mating line isn't extracted, sfen: {}", pos);
+      String::from("checkmate notimplemented")
+    }
+    None => String::from("checkmate nomate"),
+  })
+}
+
+pub struct Engine<F: FnMut(usize) -> std::io::Result<Search>> {
+  new_search: F,
+  //boxed, because search is moved between threads and it's large
+  search: Option<Box<Search>>,
+  //search is moved to the worker thread
+  busy: bool,
+  search_memory_bytes: usize,
+  cache_memory_bytes: usize,
+  max_depth: usize,
+  pos: Position,
+  current: Option<Current>,
+  //"go mate" which waits for the worker of stopped search
+  pending: Option<(u32, Position)>,
+  searches: u32,
+  tx: mpsc::Sender<Event>,
+  rx: mpsc::Receiver<Event>,
+}
+
+impl<F: FnMut(usize) -> std::io::Result<Search>> Engine<F> {
+  //new_search creates search with given cache memory in bytes (USI_Hash option)
+  pub fn new(max_depth: usize, cache_memory_bytes: usize, new_search: F) -> Self {
+    let (tx, rx) = mpsc::channel();
+    Self {
+      new_search,
+      search: None,
+      busy: false,
+      search_memory_bytes: 0,
+      cache_memory_bytes,
+      max_depth,
+      pos: Position::default(),
+      current: None,
+      pending: None,
+      searches: 0,
+      tx,
+      rx,
+    }
+  }
+  fn ready(&mut self) -> std::io::Result<()> {
+    if self.search.is_none() || self.search_memory_bytes != self.cache_memory_bytes {
+      self.search = Some(Box::new((self.new_search)(self.cache_memory_bytes)?));
+      self.search_memory_bytes = self.cache_memory_bytes;
+    }
+    Ok(())
+  }
+  fn start(&mut self, id: u32, pos: Position) -> std::io::Result<()> {
+    self.ready()?;
+    let mut s = self.search.take().unwrap();
+    self.busy = true;
+    let stop = self.current.as_ref().unwrap().stop.clone();
+    let max_depth = self.max_depth;
+    let tx = self.tx.clone();
+    std::thread::Builder::new()
+      .stack_size(WORKER_STACK_SIZE)
+      .spawn(move || {
+        let reply = solve(&mut s, &pos, max_depth, stop);
+        let _ = tx.send(Event::Done(id, s, reply));
+      })?;
+    Ok(())
+  }
+  fn go_mate<W: Write>(&mut self, out: &mut W, args: &[&str]) -> std::io::Result<()> {
+    let deadline = match args.first() {
+      None | Some(&"infinite") => None,
+      Some(t) => match t.parse::<u64>() {
+        Ok(ms) => Some(Instant::now() + Duration::from_millis(ms)),
+        Err(_) => {
+          writeln!(out, "info string invalid time limit {}", t)?;
+          return Ok(());
+        }
+      },
+    };
+    self.searches += 1;
+    let id = self.searches;
+    self.current = Some(Current {
+      id,
+      stop: Arc::new(AtomicBool::new(false)),
+      deadline,
+    });
+    if self.busy {
+      //stopped search is still running
+      self.pending = Some((id, self.pos.clone()));
+      Ok(())
+    } else {
+      self.start(id, self.pos.clone())
+    }
+  }
+  fn stop<W: Write>(&mut self, out: &mut W) -> std::io::Result<()> {
+    if let Some(c) = self.current.take() {
+      c.stop.store(true, Ordering::Relaxed);
+      if self.pending.as_ref().map(|p| p.0) == Some(c.id) {
+        self.pending = None;
+      }
+      writeln!(out, "checkmate timeout")?;
+    }
+    Ok(())
+  }
+  //false on "quit"
+  fn command<W: Write>(&mut self, out: &mut W, line: &str) -> std::io::Result<bool> {
+    log::debug!("USI command: {}", line);
+    let a: Vec<&str> = line.split_ascii_whitespace().collect();
+    match a.first() {
+      Some(&"usi") => {
+        writeln!(
+          out,
+          "id name tsumeshogi-check {}",
+          env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(out, "id author tsumeshogi-check")?;
+        writeln!(
+          out,
+          "option name USI_Hash type spin default {} min 1 max 65536",
+          self.cache_memory_bytes >> 20
+        )?;
+        writeln!(out, "usiok")?;
+      }
+      Some(&"isready") => {
+        if !self.busy {
+          self.ready()?;
+        }
+        writeln!(out, "readyok")?;
+      }
+      Some(&"setoption") => {
+        //setoption name USI_Hash value 256
+        if let (Some(&"name"), Some(&"USI_Hash"), Some(&"value"), Some(v)) =
+          (a.get(1), a.get(2), a.get(3), a.get(4))
+        {
+          match v.parse::<usize>() {
+            Ok(mib) if mib > 0 => self.cache_memory_bytes = mib << 20,
+            _ => writeln!(out, "info string invalid USI_Hash value {}", v)?,
+          }
+        }
+      }
+      Some(&"position") => match parse_usi_position(line) {
+        Ok((mut pos, moves)) => {
+          for m in &moves {
+            pos.do_move(m);
+          }
+          self.pos = pos;
+        }
+        Err(err) => writeln!(out, "info string {}", err)?,
+      },
+      Some(&"go") => {
+        if a.get(1) == Some(&"mate") {
+          self.go_mate(out, &a[2..])?;
+        } else {
+          //only tsume is solved
+          writeln!(out, "bestmove resign")?;
+        }
+      }
+      Some(&"stop") => self.stop(out)?,
+      Some(&"quit") => return Ok(false),
+      _ => (),
+    }
+    Ok(true)
+  }
+  //commands are read until "quit" or the end of input (awaited search is finished first)
+  pub fn run<R: BufRead + Send + 'static, W: Write>(
+    &mut self,
+    input: R,
+    out: &mut W,
+  ) -> std::io::Result<()> {
+    let tx = self.tx.clone();
+    std::thread::spawn(move || {
+      for line in input.lines() {
+        match line {
+          Ok(line) => {
+            if tx.send(Event::Command(line)).is_err() {
+              return;
+            }
+          }
+          Err(_) => break,
+        }
+      }
+      let _ = tx.send(Event::Eof);
+    });
+    let mut eof = false;
+    //commands which came during the search (besides "stop") are executed after it,
+    //so scripted input could be piped
+    let mut queue: std::collections::VecDeque<String> = std::collections::VecDeque::new();
+    while !eof || self.current.is_some() || !queue.is_empty() {
+      if self.current.is_none() {
+        if let Some(line) = queue.pop_front() {
+          if !self.command(out, &line)? {
+            break;
+          }
+          out.flush()?;
+          continue;
+        }
+      }
+      let deadline = self.current.as_ref().and_then(|c| c.deadline);
+      let ev = match deadline {
+        Some(deadline) => {
+          match self
+            .rx
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+          {
+            Ok(ev) => ev,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+              self.stop(out)?;
+              out.flush()?;
+              continue;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+          }
+        }
+        None => match self.rx.recv() {
+          Ok(ev) => ev,
+          Err(_) => break,
+        },
+      };
+      match ev {
+        Event::Eof => eof = true,
+        Event::Done(id, s, reply) => {
+          self.search = Some(s);
+          self.busy = false;
+          if self.current.as_ref().map(|c| c.id) == Some(id) {
+            self.current = None;
+            if let Some(reply) = reply {
+              writeln!(out, "{}", reply)?;
+            }
+          }
+          if let Some((id, pos)) = self.pending.take() {
+            self.start(id, pos)?;
+          }
+        }
+        Event::Command(line) => {
+          let line = line.trim();
+          if self.current.is_some() && line != "stop" {
+            queue.push_back(line.to_owned());
+          } else if !self.command(out, line)? {
+            out.flush()?;
+            break;
+          }
+        }
+      }
+      out.flush()?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,253 @@
+pub mod retro;
+
+use super::search::Search;
+use super::shogi::{moves::Move, piece, Position};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+//splitmix64
+pub struct Rng(u64);
+
+impl Rng {
+  pub fn new(seed: u64) -> Self {
+    Self(seed)
+  }
+  pub fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+  pub fn below(&mut self, n: usize) -> usize {
+    assert!(n > 0);
+    (self.next_u64() % n as u64) as usize
+  }
+  pub fn chance(&mut self, num: u64, den: u64) -> bool {
+    self.next_u64() % den < num
+  }
+}
+
+//number of pieces of each kind (indexed by unpromoted piece), kings aren't allowed
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Material(pub [u8; 8]);
+
+impl Material {
+  pub fn len(&self) -> u32 {
+    self.0.iter().map(|&c| c as u32).sum()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl FromStr for Material {
+  type Err = String;
+  //same notation as pockets in SFEN ("2GS", "-" for nothing), case insensitive
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut m = Material::default();
+    if s == "-" {
+      return Ok(m);
+    }
+    //count before the piece, None if it isn't given
+    let mut cnt: Option<u8> = None;
+    let too_many = || format!("too many pieces in material \"{}\"", s);
+    for c in s.chars() {
+      if let Some(d) = c.to_digit(10) {
+        cnt = Some(
+          cnt
+            .unwrap_or(0)
+            .checked_mul(10)
+            .and_then(|n| n.checked_add(d as u8))
+            .ok_or_else(too_many)?,
+        );
+        continue;
+      }
+      let p = piece::from_char(c.to_ascii_uppercase());
+      if p == piece::NONE || p == piece::KING {
+        return Err(format!("invalid piece '{}' in material \"{}\"", c, s));
+      }
+      let n = match cnt.take() {
+        Some(0) => return Err(format!("zero count of '{}' in material \"{}\"", c, s)),
+        Some(n) => n,
+        None => 1,
+      };
+      m.0[p as usize] = m.0[p as usize].checked_add(n).ok_or_else(too_many)?;
+    }
+    if cnt.is_some() {
+      return Err(format!("trailing count in material \"{}\"", s));
+    }
+    Ok(m)
+  }
+}
+
+impl fmt::Display for Material {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_empty() {
+      return write!(f, "-");
+    }
+    for p in (piece::PAWN..piece::KING).rev() {
+      let c = self.0[p as usize];
+      if c > 1 {
+        write!(f, "{}", c)?;
+      }
+      if c > 0 {
+        write!(f, "{}", piece::to_string(p, true))?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratorOptions {
+  //budgets: every piece is used with probability 1/2
+  pub attacker: Material,
+  pub attacker_hand: Material,
+  pub defender: Material,
+  //pieces are placed not further than radius cells from defender king
+  pub radius: usize,
+}
+
+impl Default for GeneratorOptions {
+  fn default() -> Self {
+    Self {
+      attacker: Material::from_str("RBGSN").unwrap(),
+      attacker_hand: Material::from_str("GS").unwrap(),
+      defender: Material::from_str("GSP").unwrap(),
+      radius: 2,
+    }
+  }
+}
+
+impl GeneratorOptions {
+  pub fn validate(&self) -> Result<(), String> {
+    for p in piece::PAWN..piece::KING {
+      let i = p as usize;
+      let t =
+        self.attacker.0[i] as u32 + self.attacker_hand.0[i] as u32 + self.defender.0[i] as u32;
+      let e = piece::expected_number_of_pieces(p);
+      if t > e {
+        return Err(format!(
+          "{} {}s in material budgets, expected number of theese pieces are {}",
+          t,
+          piece::to_human_string(p),
+          e
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+pub struct Puzzle {
+  pub pos: Position,
+  pub pv: Vec<Move>,
+  pub nodes: u64,
+}
+
+pub struct Generator {
+  rng: Rng,
+  opts: GeneratorOptions,
+  seen: HashSet<u64>,
+  pub tries: u64,
+}
+
+impl Generator {
+  //material budgets exceeding the number of pieces in the set are rejected
+  pub fn new(seed: u64, opts: GeneratorOptions) -> Result<Self, String> {
+    opts.validate()?;
+    Ok(Self {
+      rng: Rng::new(seed),
+      opts,
+      seen: HashSet::new(),
+      tries: 0,
+    })
+  }
+  fn sample(&mut self, m: &Material) -> Vec<i8> {
+    let mut r = Vec::new();
+    for p in (piece::PAWN..piece::KING).rev() {
+      for _ in 0..m.0[p as usize] {
+        if self.rng.chance(1, 2) {
+          r.push(p);
+        }
+      }
+    }
+    r
+  }
+  fn place(&mut self, board: &mut [i8; 81], king_pos: usize, abs_piece: i8, side: i8) -> bool {
+    let r = self.opts.radius as isize;
+    let (king_row, king_col) = (king_pos / 9, king_pos % 9);
+    let cells: Vec<usize> = (0..81)
+      .filter(|&k| {
+        board[k] == piece::NONE
+          && (k / 9) as isize >= king_row as isize - r
+          && (k / 9) as isize <= king_row as isize + r
+          && (k % 9) as isize >= king_col as isize - r
+          && (k % 9) as isize <= king_col as isize + r
+      })
+      .collect();
+    if cells.is_empty() {
+      return false;
+    }
+    let k = cells[self.rng.below(cells.len())];
+    let mut v = abs_piece * side;
+    let nifu =
+      abs_piece == piece::PAWN && (0..9).any(|row| board[9 * row + k % 9] == piece::PAWN * side);
+    //promotion fixes pawns, lances and knights on dead squares and nifu
+    if piece::could_promoted(v) && (nifu || !piece::could_unpromoted(v, k) || self.rng.chance(1, 4))
+    {
+      v = piece::promote(v);
+    }
+    board[k] = v;
+    true
+  }
+  pub fn random_position(&mut self) -> Option<Position> {
+    let mut board = [piece::NONE; 81];
+    let king_pos = 9 * self.rng.below(3) + self.rng.below(9);
+    board[king_pos] = piece::WHITE_KING;
+    let mut white_pockets = [0u8; 8];
+    for p in piece::PAWN..piece::KING {
+      white_pockets[p as usize] = piece::expected_number_of_pieces(p) as u8;
+    }
+    let opts = self.opts.clone();
+    for (m, side) in [(&opts.attacker, 1), (&opts.defender, -1)] {
+      for p in self.sample(m) {
+        if self.place(&mut board, king_pos, p, side) {
+          white_pockets[p as usize] -= 1;
+        }
+      }
+    }
+    let mut black_pockets = [0u8; 8];
+    for p in self.sample(&opts.attacker_hand) {
+      black_pockets[p as usize] += 1;
+      white_pockets[p as usize] -= 1;
+    }
+    let pos = Position::from_board(board, black_pockets, white_pockets, 1, 1);
+    if pos.is_legal() {
+      Some(pos)
+    } else {
+      None
+    }
+  }
+  //one attempt: random position filtered by the solver (unique mate in exactly depth moves)
+  pub fn try_next(&mut self, s: &mut Search, depth: u8) -> Option<Puzzle> {
+    self.tries += 1;
+    let mut pos = self.random_position()?;
+    if !self.seen.insert(pos.hash) {
+      return None;
+    }
+    let nodes = s.nodes;
+    let (res, pv) = s.search(&mut pos, depth);
+    s.hashes_clear();
+    if res != Some(depth) {
+      return None;
+    }
+    pv.map(|pv| Puzzle {
+      pos,
+      pv,
+      nodes: s.nodes - nodes,
+    })
+  }
+}
@@ -0,0 +1,67 @@
+use super::Puzzle;
+use crate::search::Search;
+use crate::shogi::Position;
+use std::collections::HashSet;
+
+pub struct RetroGenerator {
+  allow_uncaptures: bool,
+  seen: HashSet<u64>,
+  pub positions: u64,
+}
+
+impl RetroGenerator {
+  pub fn new(allow_uncaptures: bool) -> Self {
+    Self {
+      allow_uncaptures,
+      seen: HashSet::new(),
+      positions: 0,
+    }
+  }
+  //attacker positions before last check
+  fn attacker_predecessors(&self, pos: &Position) -> Vec<Position> {
+    debug_assert!(pos.side < 0);
+    if !pos.is_check() {
+      return Vec::new();
+    }
+    pos
+      .compute_retro_moves(self.allow_uncaptures)
+      .into_iter()
+      .map(|(_, p)| p)
+      .collect()
+  }
+  //pos is checkmated position (gote to move) for depth 1
+  //or position with mate in (depth - 2) moves (sente to move)
+  pub fn search(&mut self, s: &mut Search, pos: &Position, depth: u8) -> Vec<Puzzle> {
+    let candidates = if pos.side < 0 {
+      self.attacker_predecessors(pos)
+    } else {
+      pos
+        .compute_retro_moves(self.allow_uncaptures)
+        .into_iter()
+        .flat_map(|(_, p)| self.attacker_predecessors(&p))
+        .collect()
+    };
+    let mut r = Vec::new();
+    for mut p in candidates {
+      if !self.seen.insert(p.hash) {
+        continue;
+      }
+      self.positions += 1;
+      p.move_no = 1;
+      let nodes = s.nodes;
+      let (res, pv) = s.search(&mut p, depth);
+      s.hashes_clear();
+      if res != Some(depth) {
+        continue;
+      }
+      if let Some(pv) = pv {
+        r.push(Puzzle {
+          pos: p,
+          pv,
+          nodes: s.nodes - nodes,
+        });
+      }
+    }
+    r
+  }
+}
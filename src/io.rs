@@ -1,6 +1,8 @@
+use super::encoding;
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Lines, Write};
+use std::io::{BufRead, Cursor, Lines, Write};
+use std::path::PathBuf;
 
 pub fn open_destination_file(filename: &str, overwrite: bool) -> std::io::Result<File> {
   if overwrite {
@@ -27,7 +29,7 @@ impl<'a> PoolOfDestinationFiles<'a> {
       m: BTreeMap::new(),
     }
   }
-  pub fn write_str(&mut self, x: u32, s: &str) -> std::io::Result<()> {
+  fn get_file(&mut self, x: u32) -> std::io::Result<&mut File> {
     if !self.m.contains_key(&x) {
       let f = open_destination_file(
         &format!("{}{}.{}", self.prefix, x, self.suffix),
@@ -35,15 +37,39 @@ impl<'a> PoolOfDestinationFiles<'a> {
       )?;
       self.m.insert(x, f);
     }
-    let w = self.m.get_mut(&x).unwrap();
-    write!(w, "{}", s)
+    Ok(self.m.get_mut(&x).unwrap())
+  }
+  pub fn write_str(&mut self, x: u32, s: &str) -> std::io::Result<()> {
+    write!(self.get_file(x)?, "{}", s)
+  }
+  pub fn write_bytes(&mut self, x: u32, a: &[u8]) -> std::io::Result<()> {
+    self.get_file(x)?.write_all(a)
   }
 }
 
+//what a line of the file means for the game being read
+pub enum Split {
+  Push,
+  Skip,
+  //game ends, the line is dropped (separator)
+  End,
+  //game ends, the line starts the next one
+  EndBefore,
+}
+
+//splits lines of game collections into games, format specific
+pub trait Splitter {
+  //game contains the lines pushed so far (empty at the start of the next game)
+  fn split(&mut self, line: &str, game: &[String]) -> Split;
+}
+
+//games of the files (in the given order, games don't continue in the next file),
+//lines are trimmed and games without nonempty lines are skipped
 pub struct FileIterator {
-  it: Lines<BufReader<File>>,
-  separator: String,
-  lines: usize,
+  files: std::vec::IntoIter<PathBuf>,
+  it: Option<Lines<Box<dyn BufRead>>>,
+  pending: Option<String>,
+  splitter: Box<dyn Splitter>,
   failed: bool,
 }
 
@@ -53,42 +79,75 @@ impl Iterator for FileIterator {
     if self.failed {
       return None;
     }
-    let mut r = Vec::new();
-    let mut lines_read = 0;
-    while let Some(t) = self.it.next() {
-      if t.is_err() {
-        self.failed = true;
-        return Some(Err(t.err().unwrap()));
-      }
-      lines_read += 1;
-      self.lines += 1;
-      let t = t.unwrap();
-      let t = t.trim();
-      if t == self.separator {
-        if self.lines > 1 {
-          break;
+    let mut r: Vec<String> = Vec::new();
+    loop {
+      let t = match self.pending.take() {
+        Some(t) => t,
+        None => match self.it.as_mut().and_then(|it| it.next()) {
+          Some(Ok(t)) => t.trim().to_owned(),
+          Some(Err(err)) => {
+            self.failed = true;
+            return Some(Err(err));
+          }
+          None => {
+            if r.iter().any(|t| !t.is_empty()) {
+              return Some(Ok(r));
+            }
+            r.clear();
+            let filename = self.files.next()?;
+            match encoding::open(&filename.to_string_lossy()) {
+              Ok(reader) => self.it = Some(reader.lines()),
+              Err(err) => {
+                self.failed = true;
+                return Some(Err(err));
+              }
+            }
+            continue;
+          }
+        },
+      };
+      match self.splitter.split(&t, &r) {
+        Split::Push => r.push(t),
+        Split::Skip => (),
+        Split::End if r.iter().any(|t| !t.is_empty()) => return Some(Ok(r)),
+        Split::EndBefore if r.iter().any(|t| !t.is_empty()) => {
+          self.pending = Some(t);
+          return Some(Ok(r));
+        }
+        //there is no game to end yet
+        Split::End => r.clear(),
+        Split::EndBefore => {
+          r.clear();
+          r.push(t);
         }
-      } else {
-        r.push(t.to_owned());
       }
     }
-    if lines_read == 0 {
-      None
-    } else {
-      Some(Ok(r))
-    }
   }
 }
 
 impl FileIterator {
-  pub fn new(filename: &str, separator: &str) -> std::io::Result<Self> {
-    let file = File::open(filename)?;
-    let it = BufReader::new(file).lines();
-    Ok(Self {
-      it,
-      separator: separator.to_owned(),
-      lines: 0,
+  pub fn new(filename: &str, splitter: Box<dyn Splitter>) -> Self {
+    Self::from_files(vec![PathBuf::from(filename)], splitter)
+  }
+  //files are opened lazily, errors are reported by the iterator
+  pub fn from_files(files: Vec<PathBuf>, splitter: Box<dyn Splitter>) -> Self {
+    Self {
+      files: files.into_iter(),
+      it: None,
+      pending: None,
+      splitter,
       failed: false,
-    })
+    }
+  }
+  //already decoded text
+  pub fn from_string(text: String, splitter: Box<dyn Splitter>) -> Self {
+    let reader: Box<dyn BufRead> = Box::new(Cursor::new(text.into_bytes()));
+    Self {
+      files: Vec::new().into_iter(),
+      it: Some(reader.lines()),
+      pending: None,
+      splitter,
+      failed: false,
+    }
   }
 }
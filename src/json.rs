@@ -0,0 +1,357 @@
+//minimal JSON reader and writer for JKF game records, object keys keep their order
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Value>),
+  Object(Vec<(String, Value)>),
+}
+
+impl Value {
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    match self {
+      Value::Object(a) => a.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      _ => None,
+    }
+  }
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::String(s) => Some(s),
+      _ => None,
+    }
+  }
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Value::Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+  //None for fractional numbers
+  pub fn as_i64(&self) -> Option<i64> {
+    match self {
+      Value::Number(x) if x.fract() == 0.0 && x.abs() < 9.0e15 => Some(*x as i64),
+      _ => None,
+    }
+  }
+  pub fn as_array(&self) -> Option<&[Value]> {
+    match self {
+      Value::Array(a) => Some(a),
+      _ => None,
+    }
+  }
+  pub fn as_object(&self) -> Option<&[(String, Value)]> {
+    match self {
+      Value::Object(a) => Some(a),
+      _ => None,
+    }
+  }
+}
+
+impl From<&str> for Value {
+  fn from(s: &str) -> Self {
+    Value::String(s.to_owned())
+  }
+}
+
+impl From<String> for Value {
+  fn from(s: String) -> Self {
+    Value::String(s)
+  }
+}
+
+impl From<bool> for Value {
+  fn from(b: bool) -> Self {
+    Value::Bool(b)
+  }
+}
+
+impl From<u32> for Value {
+  fn from(x: u32) -> Self {
+    Value::Number(x as f64)
+  }
+}
+
+impl From<Vec<Value>> for Value {
+  fn from(a: Vec<Value>) -> Self {
+    Value::Array(a)
+  }
+}
+
+//object with the keys in the given order
+pub fn object(a: Vec<(&str, Value)>) -> Value {
+  Value::Object(a.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
+
+fn write_str(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+  f.write_str("\"")?;
+  for c in s.chars() {
+    match c {
+      '"' => f.write_str("\\\"")?,
+      '\\' => f.write_str("\\\\")?,
+      '\n' => f.write_str("\\n")?,
+      '\r' => f.write_str("\\r")?,
+      '\t' => f.write_str("\\t")?,
+      c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+      c => write!(f, "{}", c)?,
+    }
+  }
+  f.write_str("\"")
+}
+
+//compact form without spaces, non-ASCII characters aren't escaped
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Value::Null => f.write_str("null"),
+      Value::Bool(b) => write!(f, "{}", b),
+      Value::Number(x) => match self.as_i64() {
+        Some(n) => write!(f, "{}", n),
+        None if x.is_finite() => write!(f, "{}", x),
+        None => f.write_str("null"),
+      },
+      Value::String(s) => write_str(f, s),
+      Value::Array(a) => {
+        f.write_str("[")?;
+        for (i, v) in a.iter().enumerate() {
+          if i > 0 {
+            f.write_str(",")?;
+          }
+          write!(f, "{}", v)?;
+        }
+        f.write_str("]")
+      }
+      Value::Object(a) => {
+        f.write_str("{")?;
+        for (i, (k, v)) in a.iter().enumerate() {
+          if i > 0 {
+            f.write_str(",")?;
+          }
+          write_str(f, k)?;
+          write!(f, ":{}", v)?;
+        }
+        f.write_str("}")
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct ParseJSONError {
+  pub msg: String,
+  //1-based line of the error
+  pub line: usize,
+}
+
+impl fmt::Display for ParseJSONError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at line {}", self.msg, self.line)
+  }
+}
+
+//nested arrays and objects deeper than this are rejected (parser is recursive)
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+  s: &'a [u8],
+  pos: usize,
+  depth: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn error(&self, msg: String) -> ParseJSONError {
+    let line = 1
+      + self.s[..self.pos.min(self.s.len())]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count();
+    ParseJSONError { msg, line }
+  }
+  fn skip_whitespace(&mut self) {
+    while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+      self.pos += 1;
+    }
+  }
+  fn peek(&mut self) -> Option<u8> {
+    self.skip_whitespace();
+    self.s.get(self.pos).copied()
+  }
+  fn expect(&mut self, b: u8) -> Result<(), ParseJSONError> {
+    if self.peek() == Some(b) {
+      self.pos += 1;
+      Ok(())
+    } else {
+      Err(self.error(format!("expected '{}'", b as char)))
+    }
+  }
+  fn literal(&mut self, word: &str, v: Value) -> Result<Value, ParseJSONError> {
+    if self.s[self.pos..].starts_with(word.as_bytes()) {
+      self.pos += word.len();
+      Ok(v)
+    } else {
+      Err(self.error(String::from("unexpected character")))
+    }
+  }
+  fn hex4(&mut self) -> Result<u32, ParseJSONError> {
+    let t = self
+      .s
+      .get(self.pos..self.pos + 4)
+      .and_then(|t| std::str::from_utf8(t).ok())
+      .and_then(|t| u32::from_str_radix(t, 16).ok())
+      .ok_or_else(|| self.error(String::from("invalid \\u escape")))?;
+    self.pos += 4;
+    Ok(t)
+  }
+  fn string(&mut self) -> Result<String, ParseJSONError> {
+    self.expect(b'"')?;
+    let mut r: Vec<u8> = Vec::new();
+    loop {
+      let b = *self
+        .s
+        .get(self.pos)
+        .ok_or_else(|| self.error(String::from("unterminated string")))?;
+      self.pos += 1;
+      match b {
+        b'"' => break,
+        b'\\' => {
+          let e = *self
+            .s
+            .get(self.pos)
+            .ok_or_else(|| self.error(String::from("unterminated string")))?;
+          self.pos += 1;
+          let c = match e {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+              let mut code = self.hex4()?;
+              //surrogate pair
+              if (0xd800..0xdc00).contains(&code) && self.s[self.pos..].starts_with(b"\\u") {
+                self.pos += 2;
+                let low = self.hex4()?;
+                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+              }
+              char::from_u32(code).unwrap_or('\u{fffd}')
+            }
+            _ => return Err(self.error(format!("invalid escape '\\{}'", e as char))),
+          };
+          let mut buf = [0u8; 4];
+          r.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+        _ => r.push(b),
+      }
+    }
+    //input is &str, so the bytes between escapes are valid UTF-8
+    Ok(String::from_utf8(r).unwrap())
+  }
+  fn number(&mut self) -> Result<Value, ParseJSONError> {
+    let start = self.pos;
+    while self.pos < self.s.len() && b"+-0123456789.eE".contains(&self.s[self.pos]) {
+      self.pos += 1;
+    }
+    let t = std::str::from_utf8(&self.s[start..self.pos]).unwrap();
+    t.parse::<f64>()
+      .map(Value::Number)
+      .map_err(|_| self.error(format!("invalid number \"{}\"", t)))
+  }
+  fn value(&mut self) -> Result<Value, ParseJSONError> {
+    if self.depth >= MAX_DEPTH {
+      return Err(self.error(String::from("too deep nesting")));
+    }
+    match self.peek() {
+      None => Err(self.error(String::from("unexpected end of input"))),
+      Some(b'{') => {
+        self.pos += 1;
+        self.depth += 1;
+        let mut a = Vec::new();
+        if self.peek() == Some(b'}') {
+          self.pos += 1;
+        } else {
+          loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            let v = self.value()?;
+            a.push((key, v));
+            match self.peek() {
+              Some(b',') => self.pos += 1,
+              Some(b'}') => {
+                self.pos += 1;
+                break;
+              }
+              _ => return Err(self.error(String::from("expected ',' or '}'"))),
+            }
+          }
+        }
+        self.depth -= 1;
+        Ok(Value::Object(a))
+      }
+      Some(b'[') => {
+        self.pos += 1;
+        self.depth += 1;
+        let mut a = Vec::new();
+        if self.peek() == Some(b']') {
+          self.pos += 1;
+        } else {
+          loop {
+            a.push(self.value()?);
+            match self.peek() {
+              Some(b',') => self.pos += 1,
+              Some(b']') => {
+                self.pos += 1;
+                break;
+              }
+              _ => return Err(self.error(String::from("expected ',' or ']'"))),
+            }
+          }
+        }
+        self.depth -= 1;
+        Ok(Value::Array(a))
+      }
+      Some(b'"') => self.string().map(Value::String),
+      Some(b't') => self.literal("true", Value::Bool(true)),
+      Some(b'f') => self.literal("false", Value::Bool(false)),
+      Some(b'n') => self.literal("null", Value::Null),
+      Some(b'-' | b'0'..=b'9') => self.number(),
+      Some(_) => Err(self.error(String::from("unexpected character"))),
+    }
+  }
+}
+
+impl<'a> Parser<'a> {
+  //leading BOM is skipped
+  fn new(s: &'a str) -> Self {
+    let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+    Self {
+      s: s.as_bytes(),
+      pos: 0,
+      depth: 0,
+    }
+  }
+}
+
+//sequence of JSON values separated by whitespace (e.g. JSON lines)
+pub fn parse_values(s: &str) -> Result<Vec<Value>, ParseJSONError> {
+  let mut p = Parser::new(s);
+  let mut r = Vec::new();
+  while p.peek().is_some() {
+    r.push(p.value()?);
+  }
+  Ok(r)
+}
+
+pub fn parse(s: &str) -> Result<Value, ParseJSONError> {
+  let mut p = Parser::new(s);
+  let v = p.value()?;
+  if p.peek().is_some() {
+    return Err(p.error(String::from("unexpected data after the value")));
+  }
+  Ok(v)
+}
@@ -1,6 +1,14 @@
 pub mod cmd_options;
+pub mod dataset;
+pub mod differential;
+pub mod encoding;
+pub mod engine;
+pub mod generator;
 pub mod io;
+pub mod json;
 pub mod search;
 pub mod shogi;
 pub mod stats;
+pub mod tablebase;
 pub mod timer;
+pub mod verify;
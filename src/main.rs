@@ -4,9 +4,12 @@ use std::io::{BufReader, BufWriter};
 use std::iter;
 
 use game::Game;
-use shogi::{game, moves, pgn, psn, Position};
+use shogi::epd::EPD;
+use shogi::{game, moves, packed, perft, pgn, psn, svg, validation, Position};
 use tsumeshogi_check::cmd_options::CMDOptions;
-use tsumeshogi_check::{io, search, shogi, timer};
+use tsumeshogi_check::{
+  dataset, differential, engine, generator, io, search, shogi, tablebase, timer, verify,
+};
 
 use log::{debug, error, info, warn};
 
@@ -18,6 +21,28 @@ fn open_destination_writer(dst: &str) -> std::io::Result<BufWriter<File>> {
   Ok(BufWriter::with_capacity(BUF_SIZE, f))
 }
 
+fn new_search(opts: &CMDOptions) -> std::io::Result<search::Search> {
+  new_search_with_cache(opts, opts.cache_memory_bytes)
+}
+
+fn new_search_with_cache(
+  opts: &CMDOptions,
+  cache_memory_bytes: usize,
+) -> std::io::Result<search::Search> {
+  let mut s = search::Search::new(cache_memory_bytes);
+  for filename in &opts.tablebases {
+    let tb = tablebase::Tablebase::load_from_file(filename)?;
+    info!(
+      "Tablebase {} (depth {}, {} entries) was loaded",
+      filename,
+      tb.depth,
+      tb.len()
+    );
+    s.add_tablebase(tb);
+  }
+  Ok(s)
+}
+
 fn process_psn(filename: &str) -> std::io::Result<()> {
   let dst = filename.strip_suffix("psn").unwrap();
   let mut dst = String::from(dst);
@@ -76,6 +101,7 @@ fn process_pgn(filename: &str) -> std::io::Result<()> {
 
 struct OutputStream<'a> {
   kb: shogi::kif::KIFBuilder,
+  output_filename: &'a str,
   writers: io::PoolOfDestinationFiles<'a>,
   output_format: Format,
   puzzles: u32,
@@ -90,11 +116,21 @@ impl<'a> OutputStream<'a> {
         error!("unknown output format for '{}'", output_filename);
         return None;
       }
-      Format::Sfen | Format::Kif => (),
+      Format::Sfen
+      | Format::PackedSfen
+      | Format::PackedSfenValue
+      | Format::Kif
+      | Format::Ki2
+      | Format::Csa
+      | Format::Jkf
+      | Format::Psn
+      | Format::Pgn
+      | Format::Svg => (),
     }
     let writers = io::PoolOfDestinationFiles::new(&output_filename, OVERWRITE_DESTINATION_FILE);
     Some(Self {
       kb,
+      output_filename,
       writers,
       output_format,
       puzzles: 0,
@@ -109,10 +145,19 @@ impl<'a> OutputStream<'a> {
     swapped: bool,
     nodes: u64,
   ) -> std::io::Result<()> {
+    if matches!(
+      self.output_format,
+      Format::PackedSfen | Format::PackedSfenValue
+    ) {
+      if let Err(err) = pos.to_packed_sfen() {
+        error!("Puzzle isn't written. {} in {}", err, pos);
+        return Ok(());
+      }
+    }
     self.puzzles += 1;
     log::info!("Output #{} puzzle (tsume in {} moves)", self.puzzles, res);
     match self.output_format {
-      Format::Kif => {
+      Format::Kif | Format::Ki2 | Format::Csa | Format::Jkf | Format::Psn | Format::Pgn => {
         let mut game = Game::default();
         let (sente, gote) = if swapped {
           ("gote", "sente")
@@ -127,20 +172,23 @@ impl<'a> OutputStream<'a> {
         if let Some(u) = g.get_header(&gote) {
           game.set_header("gote".to_owned(), u.clone());
         }
-        for key in vec!["event", "date", "location", "control", "handicap"] {
+        //puzzle starts from the board diagram, so handicap isn't copied
+        for key in vec!["event", "date", "location", "control"] {
           game.copy_header(&g, key);
         }
         game.moves = pv;
         assert!(pos.side > 0);
-        let s = self.kb.game_to_kif(&game, Some(&pos));
+        let s = game_to_string(&self.kb, &self.output_format, &game, Some(pos));
         self.writers.write_str(res as u32, &s)
       }
       Format::Sfen => {
         //https://www.chessprogramming.org/Extended_Position_Description
         let mut s = format!(
-          "{} c0 \"{}\"; acn {};",
+          "{} dm {}; c0 \"{}\"; pv {}; acn {};",
           pos,
+          res,
           moves::moves_to_kif(&pv, 1),
+          shogi::usi::moves_to_usi(&pv),
           nodes
         );
         if let Some(u) = g.get_header(&"id".to_owned()) {
@@ -149,6 +197,25 @@ impl<'a> OutputStream<'a> {
         s.push('\n');
         self.writers.write_str(res as u32, &s)
       }
+      Format::PackedSfen | Format::PackedSfenValue => {
+        let payload = packed::Payload {
+          best_move: u32::from(&pv[0]),
+          dm: res as u16,
+          move_no: pos.move_no as u16,
+        };
+        let mut a = Vec::with_capacity(packed::PACKED_SFEN_SIZE + packed::PAYLOAD_SIZE);
+        let mut w =
+          packed::PackedSFENWriter::new(&mut a, self.output_format == Format::PackedSfenValue);
+        w.write(pos, Some(&payload))?;
+        self.writers.write_bytes(res as u32, &a)
+      }
+      //one diagram per file (out3-1.svg, out3-2.svg, ...)
+      Format::Svg => {
+        let dst = numbered_filename(self.output_filename, &format!("{}-{}", res, self.puzzles));
+        let mut f = open_destination_writer(&dst)?;
+        write!(f, "{}", svg::position_to_svg(pos, None, &[]))?;
+        f.flush()
+      }
       _ => panic!("unhandled output format {:?}", self.output_format),
     }
   }
@@ -158,71 +225,187 @@ impl<'a> OutputStream<'a> {
 enum Format {
   Unknown,
   Kif,
+  Ki2,
+  Csa,
+  Jkf,
+  Psn,
+  Pgn,
   Sfen,
+  PackedSfen,
+  PackedSfenValue,
+  Svg,
 }
 
 fn get_file_format(filename: &str) -> Format {
-  if filename.ends_with(".kif") {
+  if filename.ends_with(".kif") || filename.ends_with(".kifu") {
     Format::Kif
+  } else if filename.ends_with(".ki2") || filename.ends_with(".ki2u") {
+    Format::Ki2
+  } else if filename.ends_with(".csa") {
+    Format::Csa
+  } else if filename.ends_with(".jkf") {
+    Format::Jkf
+  } else if filename.ends_with(".psn") {
+    Format::Psn
+  } else if filename.ends_with(".pgn") {
+    Format::Pgn
   } else if filename.ends_with(".sfen") {
     Format::Sfen
+  } else if filename.ends_with(".psfen") {
+    Format::PackedSfen
+  } else if filename.ends_with(".psfenv") {
+    Format::PackedSfenValue
+  } else if filename.ends_with(".svg") {
+    Format::Svg
   } else {
     Format::Unknown
   }
 }
 
+fn is_game_format(format: &Format) -> bool {
+  !matches!(
+    format,
+    Format::Unknown | Format::Sfen | Format::PackedSfen | Format::PackedSfenValue | Format::Svg
+  )
+}
+
+//"out.svg" with "3" gives "out3.svg"
+fn numbered_filename(filename: &str, n: &str) -> String {
+  match filename.rsplit_once('.') {
+    Some((prefix, suffix)) => format!("{}{}.{}", prefix, n, suffix),
+    None => format!("{}{}", filename, n),
+  }
+}
+
+//positions files (one position per line or record)
+fn is_positions_file(filename: &str) -> bool {
+  matches!(
+    get_file_format(filename),
+    Format::Sfen | Format::PackedSfen | Format::PackedSfenValue
+  )
+}
+
+//position number (line number of SFEN file) and the position
+type NumberedEPD = std::io::Result<(usize, Result<EPD, String>)>;
+
+//numbered positions of SFEN (EPD lines) or packed SFEN files, Err for records which couldn't be parsed
+fn epd_iterator(filename: &str) -> std::io::Result<Box<dyn Iterator<Item = NumberedEPD>>> {
+  let format = get_file_format(filename);
+  let reader = BufReader::new(File::open(filename)?);
+  Ok(match format {
+    Format::PackedSfen | Format::PackedSfenValue => Box::new(
+      packed::PackedSFENReader::new(reader, format == Format::PackedSfenValue)
+        .enumerate()
+        .map(|(i, r)| match r {
+          Ok((pos, payload)) => Ok((i + 1, Ok(EPD::from_packed(pos, payload.as_ref())))),
+          Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+            Ok((i + 1, Err(err.to_string())))
+          }
+          Err(err) => Err(err),
+        }),
+    ),
+    _ => Box::new(
+      reader
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| match line {
+          Ok(line) if line.trim().is_empty() => None,
+          Ok(line) => Some(Ok((
+            i + 1,
+            EPD::parse(&line).map_err(|err| err.to_string()),
+          ))),
+          Err(err) => Some(Err(err)),
+        }),
+    ),
+  })
+}
+
+//returns false if some line doesn't match its dm or pv operation
+fn process_verify(filename: &str, opts: &CMDOptions) -> std::io::Result<bool> {
+  let tt = timer::Timer::new();
+  let mut s = new_search(opts)?;
+  let (mut checked, mut failed) = (0, 0);
+  for r in epd_iterator(filename)? {
+    let (test, epd) = r?;
+    if test < opts.skip {
+      continue;
+    }
+    let epd = match epd {
+      Ok(epd) => epd,
+      Err(err) => {
+        error!("Test #{}: fail to parse SFEN. {}", test, err);
+        failed += 1;
+        continue;
+      }
+    };
+    if epd.dm().is_none() && epd.pv().is_none() {
+      warn!("Test #{}: no dm or pv operation, sfen: {}", test, epd.pos);
+      continue;
+    }
+    checked += 1;
+    let mismatches = verify::verify(&mut s, &epd);
+    if !mismatches.is_empty() {
+      failed += 1;
+      error!(
+        "Test #{}{}: {}, sfen: {}",
+        test,
+        epd.id().map(|id| format!(" ({})", id)).unwrap_or_default(),
+        mismatches.join(", "),
+        epd.pos
+      );
+    }
+  }
+  info!(
+    "{} positions were verified in {:.3}s",
+    checked,
+    tt.elapsed()
+  );
+  if failed > 0 {
+    println!(
+      "{} of {} positions don't match {}",
+      failed, checked, filename
+    );
+  } else {
+    println!("all {} positions match {}", checked, filename);
+  }
+  Ok(failed == 0)
+}
+
 fn process_file(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
   let tt = timer::Timer::new();
   let depth = opts.depth;
-  let mut output_stream = OutputStream::new(&opts.output_filename).unwrap();
-  let id = filename.strip_suffix(".sfen").unwrap();
-  let file = File::open(filename)?;
-  let reader = BufReader::new(file);
-  let mut s = search::Search::new(opts.cache_memory_bytes);
+  //unknown output format is reported by OutputStream::new()
+  let mut output_stream = match OutputStream::new(&opts.output_filename) {
+    Some(output_stream) => output_stream,
+    None => return Ok(()),
+  };
+  let id = filename.rsplit_once('.').map_or(filename, |(id, _)| id);
+  let mut s = new_search(opts)?;
   let mut g = Game::default();
-  for (test, line) in reader.lines().enumerate() {
-    let line = line?;
-    let test = test + 1;
+  for r in epd_iterator(filename)? {
+    let (test, epd) = r?;
     if test < opts.skip {
       continue;
     }
-    log::debug!("Test #{}: fen = {}", test, line);
-    let pos = Position::parse_sfen(&line);
-    if pos.is_err() {
-      error!("Test #{}: fail to parse SFEN. {}", test, pos.err().unwrap());
-      continue;
-    }
-    let mut pos = pos.unwrap();
-    if pos.side < 0 {
-      pos.swap_sides();
-    }
-    assert!(pos.side > 0);
-    pos.move_no = 1;
-    let nodes = s.nodes;
-    let (res, pv) = s.search(&mut pos, depth as u8);
-    s.hashes_clear();
-    if res.is_some() {
-      let res = res.unwrap();
-      if res < depth as u8 {
-        warn!(
-          "Found faster mate in {} move(s). Test #{}, sfen: {}",
-          res, test, line
-        );
-      }
-      if let Some(p) = pv {
-        let swapped = false;
-        g.set_header(String::from("id"), format!("{}-{}", id, test));
-        output_stream.write_puzzle(res, &g, &pos, p, swapped, s.nodes - nodes)?;
-      } else {
-        warn!(
-          "Tsume in {} moves isn't unique. Test #{}, sfen: {}",
-          res, test, pos,
-        );
+    let epd = match epd {
+      Ok(epd) => epd,
+      Err(err) => {
+        error!("Test #{}: fail to parse SFEN. {}", test, err);
+        continue;
       }
-    } else {
-      error!(
-        "Mate in {} moves is not found. Test #{}, sfen: {}",
-        depth, test, line
+    };
+    log::debug!("Test #{}: sfen = {}", test, epd.pos);
+    let id = match epd.id() {
+      Some(id) => id.to_owned(),
+      None => format!("{}-{}", id, test),
+    };
+    g.set_header(String::from("id"), id);
+    let place = format!("Test #{}", test);
+    let res = solve_position(&mut s, &mut output_stream, &g, &epd.pos, depth, &place)?;
+    if let Some(res) = res.filter(|&res| res < depth as u8) {
+      warn!(
+        "Found faster mate in {} move(s). {}, sfen: {}",
+        res, place, epd.pos
       );
     }
     //nodes += s.nodes;
@@ -237,12 +420,245 @@ fn process_file(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
   Ok(())
 }
 
+//games of the file (or directory of KIF files) as lines
+fn game_iterator(
+  filename: &str,
+  format: &Format,
+) -> std::io::Result<Box<dyn Iterator<Item = std::io::Result<Vec<String>>>>> {
+  Ok(match format {
+    Format::Csa => Box::new(shogi::csa::csa_file_iterator(filename)?),
+    Format::Jkf => Box::new(shogi::jkf::jkf_file_iterator(filename)?),
+    Format::Psn => Box::new(psn::PSNFileIterator::new(filename)?),
+    Format::Pgn => Box::new(pgn::PGNFileIterator::new(filename)?),
+    _ => Box::new(shogi::kif::kif_file_iterator(filename)?),
+  })
+}
+
+fn parse_game(
+  kb: &mut shogi::kif::KIFBuilder,
+  format: &Format,
+  a: &Vec<String>,
+) -> Result<Game, String> {
+  match format {
+    Format::Ki2 => kb.parse_ki2_game(a).map_err(|err| format!("{:?}", err)),
+    Format::Csa => shogi::csa::parse_csa_game(a).map_err(|err| format!("{:?}", err)),
+    Format::Jkf => kb
+      .parse_jkf_game(&a.join("\n"))
+      .map_err(|err| format!("{:?}", err)),
+    Format::Psn => psn::parse_psn_game(a).map_err(|err| format!("{:?}", err)),
+    Format::Pgn => pgn::parse_pgn_game(a).map_err(|err| format!("{:?}", err)),
+    _ => kb.parse_kif_game(a).map_err(|err| format!("{:?}", err)),
+  }
+}
+
+fn game_to_string(
+  kb: &shogi::kif::KIFBuilder,
+  format: &Format,
+  game: &Game,
+  start_pos: Option<&Position>,
+) -> String {
+  match format {
+    Format::Ki2 => kb.game_to_ki2(game, start_pos),
+    Format::Csa => shogi::csa::game_to_csa(game, start_pos),
+    //one game per line (JSON lines)
+    Format::Jkf => kb.game_to_jkf(game, start_pos) + "\n",
+    Format::Psn => psn::game_to_psn(game, start_pos),
+    Format::Pgn => pgn::game_to_pgn(game, start_pos),
+    _ => kb.game_to_kif(game, start_pos),
+  }
+}
+
+//SFEN lines to packed SFEN records and back, dm and the first pv (or bm) move go to the payload
+fn process_convert_positions(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
+  let output_format = get_file_format(&opts.output_filename);
+  let mut allocator = shogi::alloc::PositionMovesAllocator::default();
+  let mut f = open_destination_writer(&opts.output_filename)?;
+  let mut positions = 0;
+  for r in epd_iterator(filename)? {
+    let (test, epd) = r?;
+    let mut epd = match epd {
+      Ok(epd) => epd,
+      Err(err) => {
+        error!("Test #{}: fail to parse SFEN. {}", test, err);
+        continue;
+      }
+    };
+    if output_format == Format::Sfen {
+      writeln!(f, "{}", epd)?;
+    } else {
+      let best_move = epd
+        .pv()
+        .and_then(|pv| pv.first())
+        .or_else(|| epd.operands("bm").and_then(|a| a.first()))
+        .cloned();
+      let best_move = best_move
+        .and_then(|m| epd.pos.parse_usi_move(&mut allocator, &m))
+        .map_or(0, |m| u32::from(&m));
+      let payload = packed::Payload {
+        best_move,
+        dm: epd.dm().unwrap_or_default() as u16,
+        move_no: epd.pos.move_no as u16,
+      };
+      let mut w = packed::PackedSFENWriter::new(&mut f, output_format == Format::PackedSfenValue);
+      if let Err(err) = w.write(&epd.pos, Some(&payload)) {
+        error!("Test #{}: {}", test, err);
+        continue;
+      }
+    }
+    positions += 1;
+  }
+  f.flush()?;
+  info!(
+    "{} positions were written to {}",
+    positions, opts.output_filename
+  );
+  Ok(())
+}
+
+//diagram per position (SFEN line or packed record) or per game (its final position with the last move),
+//checkmated king and checking pieces are marked
+fn process_convert_svg(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
+  let format = get_file_format(filename);
+  let mut diagrams = 0;
+  let mut write_diagram = |n: usize, pos: &Position, last_move: Option<&moves::Move>| {
+    let dst = numbered_filename(&opts.output_filename, &n.to_string());
+    let mut f = open_destination_writer(&dst)?;
+    write!(
+      f,
+      "{}",
+      svg::position_to_svg(pos, last_move, &svg::mate_squares(pos))
+    )?;
+    diagrams += 1;
+    f.flush()
+  };
+  if is_positions_file(filename) {
+    for r in epd_iterator(filename)? {
+      match r? {
+        (test, Ok(epd)) => write_diagram(test, &epd.pos, None)?,
+        (test, Err(err)) => error!("Test #{}: fail to parse SFEN. {}", test, err),
+      }
+    }
+  } else if is_game_format(&format) {
+    let mut kb = shogi::kif::KIFBuilder::default();
+    for (game_no, a) in game_iterator(filename, &format)?.enumerate() {
+      let a = a?;
+      let g = match parse_game(&mut kb, &format, &a) {
+        Ok(g) => g,
+        Err(err) => {
+          error!("Game #{}: {}", game_no + 1, err);
+          continue;
+        }
+      };
+      match g.start_position() {
+        Ok(mut pos) => {
+          for m in &g.moves {
+            pos.do_move(m);
+          }
+          write_diagram(game_no + 1, &pos, g.moves.last())?;
+        }
+        Err(err) => error!("Game #{}: {}", game_no + 1, err),
+      }
+    }
+  } else {
+    error!("unknown format of '{}'", filename);
+    return Ok(());
+  }
+  info!("{} diagrams were written", diagrams);
+  Ok(())
+}
+
+//all games of the input file are written to the output file in its format
+fn process_convert(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
+  let format = get_file_format(filename);
+  let output_format = get_file_format(&opts.output_filename);
+  if is_positions_file(filename) && is_positions_file(&opts.output_filename) {
+    return process_convert_positions(filename, opts);
+  }
+  if output_format == Format::Svg {
+    return process_convert_svg(filename, opts);
+  }
+  if !is_game_format(&format) {
+    error!("unknown game format of '{}'", filename);
+    return Ok(());
+  }
+  if !is_game_format(&output_format) {
+    error!("unknown game format of '{}'", opts.output_filename);
+    return Ok(());
+  }
+  let mut kb = shogi::kif::KIFBuilder::default();
+  let mut f = open_destination_writer(&opts.output_filename)?;
+  let mut games = 0;
+  for (game_no, a) in game_iterator(filename, &format)?.enumerate() {
+    let a = a?;
+    match parse_game(&mut kb, &format, &a) {
+      Ok(g) => {
+        write!(f, "{}", game_to_string(&kb, &output_format, &g, None))?;
+        games += 1;
+      }
+      Err(err) => error!("Game #{}: {}", game_no + 1, err),
+    }
+  }
+  f.flush()?;
+  info!("{} games were written to {}", games, opts.output_filename);
+  Ok(())
+}
+
+//position (either side to move) of SFEN file or problem collection is solved and written as a puzzle,
+//place ("Test #1", "Game #1") is for messages, returns the found mate length
+fn solve_position(
+  s: &mut search::Search,
+  output_stream: &mut OutputStream,
+  g: &Game,
+  position: &Position,
+  depth: usize,
+  place: &str,
+) -> std::io::Result<Option<u8>> {
+  let mut pos = position.clone();
+  let swapped = pos.side < 0;
+  if swapped {
+    pos.swap_sides();
+  }
+  let problems = pos.validate();
+  if !problems.is_empty() {
+    error!(
+      "{}: invalid position ({}), sfen: {}",
+      place,
+      validation::describe(&problems),
+      position
+    );
+    return Ok(None);
+  }
+  pos.move_no = 1;
+  let nodes = s.nodes;
+  let (res, pv) = s.search(&mut pos, depth as u8);
+  s.hashes_clear();
+  match (res, pv) {
+    (Some(res), Some(pv)) => {
+      output_stream.write_puzzle(res, g, &pos, pv, swapped, s.nodes - nodes)?
+    }
+    (Some(res), None) => warn!(
+      "Tsume in {} moves isn't unique. {}, sfen: {}",
+      res, place, position
+    ),
+    _ => error!(
+      "Mate in {} moves is not found. {}, sfen: {}",
+      depth, place, position
+    ),
+  }
+  Ok(res)
+}
+
 fn process_kif(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
   let tt = timer::Timer::new();
   let depth = opts.depth;
-  let mut output_stream = OutputStream::new(&opts.output_filename).unwrap();
-  let mut s = search::Search::new(opts.cache_memory_bytes);
-  let it = shogi::kif::kif_file_iterator(filename)?;
+  //unknown output format is reported by OutputStream::new()
+  let mut output_stream = match OutputStream::new(&opts.output_filename) {
+    Some(output_stream) => output_stream,
+    None => return Ok(()),
+  };
+  let mut s = new_search(opts)?;
+  let format = get_file_format(filename);
+  let it = game_iterator(filename, &format)?;
   for (game_no, a) in it.enumerate() {
     let game_no = game_no + 1;
     if a.is_err() {
@@ -253,7 +669,7 @@ fn process_kif(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
       continue;
     }
     let a = a.unwrap();
-    let g = output_stream.kb.parse_kif_game(&a);
+    let g = parse_game(&mut output_stream.kb, &format, &a);
     match g {
       Err(err) => {
         error!("Game #{}: {:?}", game_no, err);
@@ -266,13 +682,38 @@ fn process_kif(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
           g.to_short_string(),
           g.moves.len()
         );
+        //problems without moves (PSN puzzle collections) are solved from the start position
+        if g.moves.is_empty() && g.get_header(&"sfen".to_owned()).is_some() {
+          match g.start_position() {
+            Ok(pos) => {
+              let place = format!("Game #{}", game_no);
+              solve_position(&mut s, &mut output_stream, &g, &pos, depth, &place)?;
+            }
+            Err(err) => error!("Game #{}: {}", game_no, err),
+          }
+          continue;
+        }
+        //games from board diagrams (puzzles, mid-game records) are examined from the first move
+        let first_move_no = if g.get_header(&"sfen".to_owned()).is_some()
+          && g.get_header(&"handicap".to_owned()).is_none()
+        {
+          1
+        } else {
+          20
+        };
         for current_side in iter::once(1i8).chain(iter::once(-1i8)) {
           s.hashes_clear();
-          let mut pos = Position::default();
+          let mut pos = match g.start_position() {
+            Ok(pos) => pos,
+            Err(err) => {
+              error!("Game #{}: {}", game_no, err);
+              break;
+            }
+          };
           let mut examined_positions_hashes = std::collections::HashSet::new();
-          for mv in &g.moves {
+          for (move_index, mv) in g.moves.iter().enumerate() {
             let move_no = pos.move_no;
-            if move_no >= 20 && pos.side == current_side {
+            if move_no >= first_move_no && pos.side == current_side {
               if !examined_positions_hashes.insert(pos.hash) {
                 info!(
                   "Position after move {} has been already examined (skipping)",
@@ -310,6 +751,21 @@ fn process_kif(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
                         res, pos, game_no, move_no
                       );
                     } else {
+                      if let Some(used) = g.time_used_before(move_index) {
+                        let left = g
+                          .time_left_before(move_index)
+                          .map(|t| format!(", {}:{:02} left", t / 60, t % 60))
+                          .unwrap_or_default();
+                        info!(
+                          "Tsume in {} moves was missed after {}:{:02} used{}, game: {}, move: {}",
+                          res,
+                          used / 60,
+                          used % 60,
+                          left,
+                          game_no,
+                          move_no
+                        );
+                      }
                       output_stream.write_puzzle(res, &g, &pos, p, swapped, s.nodes - nodes)?;
                     }
                   } else {
@@ -336,6 +792,354 @@ fn process_kif(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
   Ok(())
 }
 
+//every position of the games (before each move and the final one) is labelled
+//and written as JSON lines (.jsonl) or packed SFEN records with payload
+fn process_dataset(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
+  let tt = timer::Timer::new();
+  let format = get_file_format(filename);
+  if !is_game_format(&format) && !std::path::Path::new(filename).is_dir() {
+    error!("unknown game format of '{}'", filename);
+    return Ok(());
+  }
+  let output_format = get_file_format(&opts.output_filename);
+  let jsonl = opts.output_filename.ends_with(".jsonl");
+  if !jsonl && output_format != Format::PackedSfen && output_format != Format::PackedSfenValue {
+    error!(
+      "unknown dataset format of '{}' (.jsonl, .psfen or .psfenv expected)",
+      opts.output_filename
+    );
+    return Ok(());
+  }
+  if opts.depth == 0 {
+    error!("expected search depth (-d) for dataset mode");
+    return Ok(());
+  }
+  let depth = opts.depth.min(u8::MAX as usize) as u8;
+  let mut s = new_search(opts)?;
+  let mut kb = shogi::kif::KIFBuilder::default();
+  let mut f = open_destination_writer(&opts.output_filename)?;
+  let (mut positions, mut mates, mut aborted) = (0, 0, 0);
+  for (game_no, a) in game_iterator(filename, &format)?.enumerate() {
+    let game_no = game_no + 1;
+    let a = a?;
+    if game_no < opts.skip {
+      continue;
+    }
+    let g = match parse_game(&mut kb, &format, &a) {
+      Ok(g) => g,
+      Err(err) => {
+        error!("Game #{}: {}", game_no, err);
+        continue;
+      }
+    };
+    let mut pos = match g.start_position() {
+      Ok(pos) => pos,
+      Err(err) => {
+        error!("Game #{}: {}", game_no, err);
+        continue;
+      }
+    };
+    info!(
+      "Game #{}: {}, {} moves",
+      game_no,
+      g.to_short_string(),
+      g.moves.len()
+    );
+    //repeated positions (sennichite) are labelled once per game
+    let mut examined_positions_hashes = std::collections::HashSet::new();
+    for mv in g.moves.iter().map(Some).chain(iter::once(None)) {
+      if examined_positions_hashes.insert(pos.hash) {
+        let label = dataset::label(&mut s, &pos, depth, opts.max_nodes);
+        let written = if jsonl {
+          writeln!(f, "{}", label.to_json(&pos, game_no, pos.move_no))?;
+          true
+        } else {
+          let mut w =
+            packed::PackedSFENWriter::new(&mut f, output_format == Format::PackedSfenValue);
+          match w.write(&pos, Some(&label.to_payload(pos.move_no))) {
+            Ok(()) => true,
+            Err(err) => {
+              warn!("Game #{}: position isn't written. {}", game_no, err);
+              false
+            }
+          }
+        };
+        if written {
+          positions += 1;
+          match &label {
+            dataset::Label::Mate(_, _) => mates += 1,
+            dataset::Label::Aborted(reason) => {
+              debug!(
+                "Search is aborted ({}), sfen: {}, game: {}, move: {}",
+                reason, pos, game_no, pos.move_no
+              );
+              aborted += 1;
+            }
+            dataset::Label::NoMate(_) => (),
+          }
+        }
+      }
+      if let Some(mv) = mv {
+        pos.do_move(mv);
+      }
+    }
+  }
+  f.flush()?;
+  info!(
+    "{} positions ({} mates, {} aborted) were written to {} in {:.3}s",
+    positions,
+    mates,
+    aborted,
+    opts.output_filename,
+    tt.elapsed()
+  );
+  Ok(())
+}
+
+fn process_usi(opts: &CMDOptions) -> std::io::Result<()> {
+  let depth = if opts.depth > 0 {
+    opts.depth
+  } else {
+    engine::DEFAULT_MAX_DEPTH
+  };
+  let mut e = engine::Engine::new(depth, opts.cache_memory_bytes, |bytes| {
+    new_search_with_cache(opts, bytes)
+  });
+  e.run(BufReader::new(std::io::stdin()), &mut std::io::stdout())
+}
+
+const MAX_TRIES_PER_PUZZLE: u64 = 1_000_000;
+
+fn process_gen(opts: &CMDOptions) -> std::io::Result<()> {
+  let tt = timer::Timer::new();
+  let depth = opts.depth as u8;
+  if depth % 2 != 1 {
+    error!("expected odd depth for generated puzzles, got {}", depth);
+    return Ok(());
+  }
+  let mut gen = match generator::Generator::new(opts.seed, opts.generator_options.clone()) {
+    Ok(gen) => gen,
+    Err(err) => {
+      error!("{}", err);
+      return Ok(());
+    }
+  };
+  //unknown output format is reported by OutputStream::new()
+  let mut output_stream = match OutputStream::new(&opts.output_filename) {
+    Some(output_stream) => output_stream,
+    None => return Ok(()),
+  };
+  let mut s = new_search(opts)?;
+  let mut g = Game::default();
+  let max_tries = MAX_TRIES_PER_PUZZLE * opts.puzzles as u64;
+  let mut next_report = 10000;
+  while (output_stream.puzzles as usize) < opts.puzzles && gen.tries < max_tries {
+    if let Some(p) = gen.try_next(&mut s, depth) {
+      g.set_header(
+        String::from("id"),
+        format!("gen-{}-{}", opts.seed, gen.tries),
+      );
+      output_stream.write_puzzle(depth, &g, &p.pos, p.pv, false, p.nodes)?;
+    }
+    if gen.tries >= next_report {
+      info!(
+        "{} positions were generated, {} puzzles",
+        gen.tries, output_stream.puzzles
+      );
+      next_report += 10000;
+    }
+  }
+  if (output_stream.puzzles as usize) < opts.puzzles {
+    warn!(
+      "Only {} puzzles were found after {} tries",
+      output_stream.puzzles, gen.tries
+    );
+  }
+  s.log_stats(output_stream.puzzles, tt.elapsed());
+  Ok(())
+}
+
+fn process_retro(filename: &str, opts: &CMDOptions) -> std::io::Result<()> {
+  let tt = timer::Timer::new();
+  let depth = opts.depth as u8;
+  if depth % 2 != 1 {
+    error!("expected odd depth for generated puzzles, got {}", depth);
+    return Ok(());
+  }
+  //unknown output format is reported by OutputStream::new()
+  let mut output_stream = match OutputStream::new(&opts.output_filename) {
+    Some(output_stream) => output_stream,
+    None => return Ok(()),
+  };
+  let id = filename.strip_suffix(".sfen").unwrap_or(filename);
+  let reader = BufReader::new(File::open(filename)?);
+  let mut s = new_search(opts)?;
+  let mut gen = generator::retro::RetroGenerator::new(true);
+  let mut g = Game::default();
+  for (test, line) in reader.lines().enumerate() {
+    let line = line?;
+    let test = test + 1;
+    if test < opts.skip {
+      continue;
+    }
+    //skip EPD opcodes after SFEN
+    let sfen = line.split(' ').take(4).collect::<Vec<_>>().join(" ");
+    let pos = match Position::parse_sfen(&sfen) {
+      Ok(pos) => pos,
+      Err(err) => {
+        error!("Test #{}: fail to parse SFEN. {}", test, err);
+        continue;
+      }
+    };
+    if pos.side < 0 && depth != 1 {
+      warn!(
+        "Test #{}: expected sente to move for depth {}, sfen: {}",
+        test, depth, sfen
+      );
+      continue;
+    }
+    for (k, p) in gen.search(&mut s, &pos, depth).into_iter().enumerate() {
+      g.set_header(String::from("id"), format!("{}-{}-{}", id, test, k + 1));
+      output_stream.write_puzzle(depth, &g, &p.pos, p.pv, false, p.nodes)?;
+    }
+    info!(
+      "Test #{}: {} predecessors were examined, {} puzzles",
+      test, gen.positions, output_stream.puzzles
+    );
+  }
+  s.log_stats(output_stream.puzzles, tt.elapsed());
+  Ok(())
+}
+
+fn process_tablebase(opts: &CMDOptions) -> std::io::Result<()> {
+  let tt = timer::Timer::new();
+  if opts.output_filename.is_empty() {
+    error!("expected output file for tablebase mode");
+    return Ok(());
+  }
+  let g = &opts.generator_options;
+  let mut tb = match tablebase::Tablebase::new(
+    &g.attacker,
+    &g.attacker_hand,
+    g.radius as u8,
+    opts.depth as u8,
+  ) {
+    Ok(tb) => tb,
+    Err(err) => {
+      error!("{}", err);
+      return Ok(());
+    }
+  };
+  let mut s = search::Search::new(opts.cache_memory_bytes);
+  let positions = tb.generate(&mut s);
+  info!(
+    "{} positions of {} entries were solved in {:.3}s",
+    positions,
+    tb.len(),
+    tt.elapsed()
+  );
+  tb.save_to_file(&opts.output_filename)
+}
+
+//returns false if counts differ from expected file
+fn process_perft(sfen: &str, opts: &CMDOptions) -> std::io::Result<bool> {
+  let mut pos = match Position::parse_sfen(sfen) {
+    Ok(pos) => pos,
+    Err(err) => {
+      error!("fail to parse SFEN. {}", err);
+      return Ok(false);
+    }
+  };
+  if opts.depth == 0 {
+    error!("expected positive perft depth");
+    return Ok(false);
+  }
+  let tt = timer::Timer::new();
+  let divide = perft::divide(&mut pos, opts.depth);
+  let t = tt.elapsed();
+  let mut total = perft::PerftCounts::default();
+  for (m, c) in &divide {
+    println!("{} {}", m, c.nodes);
+    total += *c;
+  }
+  println!();
+  println!("{}", total);
+  println!(
+    "{:.3}s, {:.0} nps",
+    t,
+    total.nodes as f64 / t.max(f64::EPSILON)
+  );
+  if opts.expected_filename.is_empty() {
+    return Ok(true);
+  }
+  let expected = match perft::parse_expected(&std::fs::read_to_string(&opts.expected_filename)?) {
+    Ok(expected) => expected,
+    Err(err) => {
+      error!("{}: {}", opts.expected_filename, err);
+      return Ok(false);
+    }
+  };
+  let mismatches = perft::compare(&divide, &expected);
+  for s in &mismatches {
+    println!("{}", s);
+  }
+  if mismatches.is_empty() {
+    println!(
+      "all {} moves match {}",
+      divide.len(),
+      opts.expected_filename
+    );
+  }
+  Ok(mismatches.is_empty())
+}
+
+//returns false if generators disagree
+fn process_diff(filename: Option<&String>, opts: &CMDOptions) -> std::io::Result<bool> {
+  let tt = timer::Timer::new();
+  let plies = if opts.depth == 0 { 100 } else { opts.depth };
+  let mut start = Vec::new();
+  match filename {
+    Some(filename) => {
+      for line in BufReader::new(File::open(filename)?).lines() {
+        let line = line?;
+        let sfen = line.split(' ').take(4).collect::<Vec<_>>().join(" ");
+        match Position::parse_sfen(&sfen) {
+          Ok(pos) => start.push(pos),
+          Err(err) => error!("fail to parse SFEN. {}", err),
+        }
+      }
+    }
+    None => start.push(Position::default()),
+  }
+  let mut h = differential::Harness::new(opts.seed);
+  for pos in &start {
+    for game in 1..=opts.games {
+      if let Some((p, e)) = h.walk(pos, plies) {
+        error!("Game #{} from {}: generators disagree in {}", game, pos, p);
+        for s in &e {
+          error!("{}", s);
+        }
+        let m = h.shrink(&p);
+        println!("{}", m);
+        for s in h.compare(&mut m.clone()) {
+          println!("{}", s);
+        }
+        return Ok(false);
+      }
+    }
+    info!(
+      "{} positions, {} checks, {} mates were compared",
+      h.positions, h.checks, h.mates
+    );
+  }
+  info!(
+    "{} positions were compared in {:.3}s",
+    h.positions,
+    tt.elapsed()
+  );
+  Ok(true)
+}
+
 fn main() -> std::io::Result<()> {
   let opts = CMDOptions::new(std::env::args().skip(1));
   env_logger::builder()
@@ -344,14 +1148,52 @@ fn main() -> std::io::Result<()> {
     .init();
   debug!("{:?}", opts);
   if let Some(filename) = opts.args.iter().next() {
-    if filename.ends_with(".psn") {
+    //games of PSN and PGN files are converted to KIF without output file
+    if filename.ends_with(".psn") && opts.output_filename.is_empty() {
       process_psn(&filename)?;
-    } else if filename.ends_with(".sfen") {
+    } else if filename.ends_with(".pgn") && opts.output_filename.is_empty() {
+      process_pgn(&filename)?;
+    } else if is_positions_file(filename) && opts.verify {
+      if !process_verify(filename, &opts)? {
+        std::process::exit(1);
+      }
+    } else if is_positions_file(filename) {
       process_file(&filename, &opts)?;
-    } else if filename.ends_with(".kif") {
+    } else if is_game_format(&get_file_format(filename)) || std::path::Path::new(filename).is_dir()
+    {
+      //directory of KIF files
       process_kif(&filename, &opts)?;
-    } else if filename.ends_with(".pgn") {
-      process_pgn(&filename)?;
+    } else if filename == "convert" {
+      match opts.args.get(1) {
+        Some(filename) => process_convert(filename, &opts)?,
+        None => error!("expected input file for convert mode"),
+      }
+    } else if filename == "dataset" {
+      match opts.args.get(1) {
+        Some(filename) => process_dataset(filename, &opts)?,
+        None => error!("expected input file for dataset mode"),
+      }
+    } else if filename == "gen" {
+      process_gen(&opts)?;
+    } else if filename == "perft" {
+      //SFEN could be passed as one quoted argument or as separate fields
+      let sfen = opts.args[1..].join(" ");
+      if !process_perft(&sfen, &opts)? {
+        std::process::exit(1);
+      }
+    } else if filename == "diff" {
+      if !process_diff(opts.args.get(1), &opts)? {
+        std::process::exit(1);
+      }
+    } else if filename == "usi" {
+      process_usi(&opts)?;
+    } else if filename == "tablebase" {
+      process_tablebase(&opts)?;
+    } else if filename == "retro" {
+      match opts.args.get(1) {
+        Some(filename) => process_retro(filename, &opts)?,
+        None => error!("expected input file for retro mode"),
+      }
     }
   }
   Ok(())
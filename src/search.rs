@@ -3,12 +3,15 @@ mod history;
 mod it;
 mod result;
 
+use super::tablebase::{Probe, Tablebase};
 use super::{shogi, stats};
 use result::{BestMove, SearchResult};
 use shogi::between::Between;
 use shogi::moves::{moves_to_kif, Move, Moves};
 use shogi::{alloc::PositionMovesAllocator, Checks, Position};
 use std::cmp::Ordering;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 #[cfg(feature = "stats")]
 #[derive(Default, Debug)]
@@ -23,6 +26,7 @@ struct Stats {
   mates_by_pawn_drop: u64,
   skipped_gote_searches_after_pawn_drop: u64,
   gote_cache_cuts: u64,
+  tablebase_cuts: u64,
   max_sente_hash_len: usize,
   max_gote_hash_len: usize,
   sente_skipped_moves: u64,
@@ -57,8 +61,14 @@ pub struct Search {
   gote_history: Vec<history::History>,
   allocator: PositionMovesAllocator,
   b: Between,
+  tablebases: Vec<Tablebase>,
   pub nodes: u64,
   hash_nodes: u64,
+  //search is aborted when the flag is set or after max_nodes nodes (0 is unlimited)
+  stop: Option<Arc<AtomicBool>>,
+  max_nodes: u64,
+  nodes_limit: u64,
+  aborted: bool,
   stats: Stats,
 }
 
@@ -71,11 +81,52 @@ impl Search {
       gote_history: Vec::new(),
       allocator: PositionMovesAllocator::default(),
       b: Between::default(),
+      tablebases: Vec::new(),
       nodes: 0,
       hash_nodes: 0,
+      stop: None,
+      max_nodes: 0,
+      nodes_limit: 0,
+      aborted: false,
       stats: Stats::default(),
     }
   }
+  pub fn add_tablebase(&mut self, tb: Tablebase) {
+    self.tablebases.push(tb);
+  }
+  pub fn set_stop(&mut self, stop: Option<Arc<AtomicBool>>) {
+    self.stop = stop;
+  }
+  //node budget of every search call
+  pub fn set_max_nodes(&mut self, max_nodes: u64) {
+    self.max_nodes = max_nodes;
+  }
+  //true if the last search was stopped or ran out of nodes (its result is empty)
+  pub fn is_aborted(&self) -> bool {
+    self.aborted
+  }
+  //checked in every node, aborted nodes return "no mate" and aren't stored in hashes
+  fn check_abort(&mut self) -> bool {
+    if !self.aborted {
+      self.aborted = (self.nodes_limit > 0 && self.nodes >= self.nodes_limit)
+        || self
+          .stop
+          .as_ref()
+          .is_some_and(|stop| stop.load(std::sync::atomic::Ordering::Relaxed));
+    }
+    self.aborted
+  }
+  //true if tablebase proves that sente hasn't mate in depth moves
+  fn tablebase_no_mate(&self, pos: &Position, depth: u8) -> bool {
+    for tb in &self.tablebases {
+      match tb.probe(pos) {
+        Some(Probe::NoMate) => return depth <= tb.depth,
+        Some(Probe::Mate(d, _)) => return d > depth,
+        None => (),
+      }
+    }
+    false
+  }
   fn gote_history_len(&self) -> usize {
     self.gote_history.iter().fold(0, |acc, p| acc + p.len())
   }
@@ -183,6 +234,9 @@ impl Search {
       }
       hash_best_move = m;
     }
+    if self.check_abort() {
+      return SearchResult::new(depth);
+    }
     let nodes = self.nodes_increment();
     let hash_nodes = self.hash_nodes;
     let mut res = SearchResult::new(0);
@@ -199,7 +253,12 @@ impl Search {
       while let Some((m, u)) =
         it.do_next_move(pos, &mut self.allocator, &self.gote_history[d], &mut self.b)
       {
-        let mut ev = self.sente_search(pos, next_depth, Some(&m));
+        let mut ev = if self.tablebase_no_mate(pos, next_depth) {
+          stats::incr!(self.stats.tablebase_cuts);
+          SearchResult::new(next_depth)
+        } else {
+          self.sente_search(pos, next_depth, Some(&m))
+        };
         log::debug!(
           "self.sente_search({}, next_depth: {}) = {:?} after move {}.{}",
           pos,
@@ -265,6 +324,9 @@ impl Search {
     } else {
       2
     };
+    if self.check_abort() {
+      return SearchResult::new(depth);
+    }
     let nodes = self.nodes_increment();
     let hash_nodes = self.hash_nodes;
     let mut it = it::SenteMovesIterator::new(pos, &mut self.allocator, last_move, depth > 1);
@@ -373,6 +435,12 @@ impl Search {
     log::debug!("search(pos: {}, max_depth: {})", pos, max_depth);
     assert!(pos.side > 0);
     self.next_generation();
+    self.aborted = false;
+    self.nodes_limit = if self.max_nodes > 0 {
+      self.nodes + self.max_nodes
+    } else {
+      0
+    };
     let hash = pos.hash;
     let mut res = (None, None);
     for depth in (1..=max_depth).step_by(2) {
@@ -380,6 +448,11 @@ impl Search {
       self.history_resize(depth);
       let ev = self.sente_search(pos, depth, None);
       assert_eq!(hash, pos.hash);
+      if self.aborted {
+        //results of aborted nodes were stored as "no mate" by their parents
+        self.hashes_clear();
+        break;
+      }
       if ev.best_move.is_some() {
         res.0 = Some(ev.depth);
         if ev.best_move.is_one() {
@@ -393,4 +466,58 @@ impl Search {
     self.on_search_end();
     res
   }
+  //some mating line of the mate in depth moves (found by search, but not unique, so without pv):
+  //sente plays the first move which mates in time, gote plays the longest defence
+  pub fn mating_line(&mut self, pos: &mut Position, depth: u8) -> Option<Vec<Move>> {
+    assert!(pos.side > 0);
+    self.history_resize(depth);
+    let mut r = Moves::with_capacity(depth as usize);
+    let mut last_move: Option<Move> = None;
+    let mut depth = depth;
+    while depth > 0 {
+      let mut best: Option<(Move, u8)> = None;
+      if pos.side > 0 {
+        let mut it =
+          it::SenteMovesIterator::new(pos, &mut self.allocator, last_move.as_ref(), depth > 1);
+        while let Some((m, u, oc)) = it.do_next_move(pos, &mut self.allocator) {
+          let ev = self.gote_search(pos, oc, depth - 1);
+          pos.undo_move(&m, &u);
+          if ev.best_move.is_some() && !(ev.depth == 0 && m.is_pawn_drop()) {
+            best = Some((m, ev.depth));
+            break;
+          }
+        }
+      } else {
+        let d = depth as usize / 2;
+        let mut it = it::GoteMovesIterator::new(pos.compute_checks(), None);
+        while let Some((m, u)) =
+          it.do_next_move(pos, &mut self.allocator, &self.gote_history[d], &mut self.b)
+        {
+          let ev = self.sente_search(pos, depth - 1, Some(&m));
+          pos.undo_move(&m, &u);
+          if ev.best_move.is_none() {
+            //gote escapes, the mate length was wrong
+            best = None;
+            break;
+          }
+          if best.as_ref().is_none_or(|b| ev.depth > b.1) {
+            best = Some((m, ev.depth));
+          }
+        }
+      }
+      match best {
+        Some((m, d)) => {
+          r.push(pos, m.clone());
+          last_move = Some(m);
+          depth = d;
+        }
+        None => {
+          r.undo(pos);
+          return None;
+        }
+      }
+    }
+    r.undo(pos);
+    Some(r.only_moves())
+  }
 }
@@ -8,14 +8,25 @@ mod bitboards;
 mod board;
 mod cell;
 mod consts;
+pub mod csa;
 mod direction;
+pub mod epd;
 pub mod game;
 mod hash;
+pub mod jkf;
+pub mod ki2;
 pub mod kif;
 pub mod moves;
+pub mod packed;
+pub mod perft;
 pub mod pgn;
 pub mod piece;
 pub mod psn;
+mod reference;
+mod retro;
+pub mod svg;
+pub mod usi;
+pub mod validation;
 
 use alloc::PositionMovesAllocator;
 use moves::Move;
@@ -88,6 +99,7 @@ impl IntoIterator for PotentialDropsMap {
 pub struct ParseSFENError {
   pub sfen: String,
   pub message: String,
+  pub problems: Vec<validation::Problem>,
 }
 
 impl fmt::Display for ParseSFENError {
@@ -101,6 +113,14 @@ impl ParseSFENError {
     ParseSFENError {
       sfen: String::from(sfen),
       message,
+      problems: Vec::new(),
+    }
+  }
+  fn with_problems(sfen: &str, problems: Vec<validation::Problem>) -> Self {
+    ParseSFENError {
+      sfen: String::from(sfen),
+      message: validation::describe(&problems),
+      problems,
     }
   }
 }
@@ -240,6 +260,51 @@ impl Position {
     }
     s
   }
+  //doesn't validate position (nifu, pieces on dead squares, kings under check)
+  pub fn from_board(
+    board: [i8; 81],
+    black_pockets: [u8; 8],
+    white_pockets: [u8; 8],
+    side: i8,
+    move_no: u32,
+  ) -> Self {
+    let nifu_masks = board
+      .iter()
+      .enumerate()
+      .filter(|&(_, &p)| p.abs() == piece::PAWN)
+      .fold(0u32, |acc, (k, &p)| {
+        acc | (1u32 << (((p.signum() as i32 + 1) << 3) + (k % 9) as i32))
+      });
+    let (
+      all_pieces,
+      all_pieces2,
+      all_pieces3,
+      all_pieces4,
+      black_pieces,
+      white_pieces,
+      sliding_pieces,
+    ) = board::compute_all_pieces(&board);
+    Position {
+      board,
+      black_pockets,
+      white_pockets,
+      black_king_position: board::find_king_position(&board, 1),
+      white_king_position: board::find_king_position(&board, -1),
+      all_pieces,
+      all_pieces2,
+      all_pieces3,
+      all_pieces4,
+      black_pieces,
+      white_pieces,
+      sliding_pieces,
+      hash: compute_hash(&board, &black_pockets, &white_pockets, side),
+      drop_masks: (compute_drops_mask(&black_pockets) as u16)
+        | ((compute_drops_mask(&white_pockets) as u16) << 8),
+      nifu_masks,
+      side,
+      move_no,
+    }
+  }
   pub fn parse_sfen(sfen: &str) -> Result<Self, ParseSFENError> {
     let a: Vec<_> = sfen.split(' ').collect();
     if a.len() != 4 {
@@ -259,7 +324,6 @@ impl Position {
       ));
     }
     let mut board: [i8; 81] = [piece::NONE; 81];
-    let mut nifu_masks = 0u32;
     for (row, s) in b.iter().enumerate() {
       let mut col = 0;
       let mut promoted = 0;
@@ -319,19 +383,6 @@ impl Position {
                 ),
               ));
             }
-          } else if p.abs() == piece::PAWN {
-            let bit = 1u32 << (((p.signum() as i32 + 1) << 3) + (8 - col as i32));
-            if (nifu_masks & bit) != 0 {
-              return Err(ParseSFENError::new(
-                sfen,
-                format!(
-                  "more than one {} pawn in column {}",
-                  piece::color(p),
-                  9 - col
-                ),
-              ));
-            }
-            nifu_masks |= bit;
           }
           board[9 * row + (8 - col)] = p + promoted * p.signum();
           promoted = 0;
@@ -339,19 +390,6 @@ impl Position {
         }
       }
     }
-    let (black_pieces, white_pieces) = board::count_pieces(&board);
-    if black_pieces[piece::KING as usize] > 1 {
-      return Err(ParseSFENError::new(
-        sfen,
-        String::from("too many black kings"),
-      ));
-    }
-    if white_pieces[piece::KING as usize] > 1 {
-      return Err(ParseSFENError::new(
-        sfen,
-        String::from("too many white kings"),
-      ));
-    }
     let side = if a[1] == "w" {
       -1
     } else if a[1] == "b" {
@@ -392,22 +430,6 @@ impl Position {
         }
       }
     }
-    for p in piece::PAWN..piece::KING {
-      let e = piece::expected_number_of_pieces(p);
-      let p = p as usize;
-      let t = black_pieces[p] + white_pieces[p] + black_pockets[p] as u32 + white_pockets[p] as u32;
-      if t > e {
-        return Err(ParseSFENError::new(
-          sfen,
-          format!(
-            "{} {}, expected number of theese pieces are {}",
-            t,
-            piece::to_human_string(p as i8),
-            e
-          ),
-        ));
-      }
-    }
     let move_no = u32::from_str(&a[3]);
     if move_no.is_err() {
       return Err(ParseSFENError::new(
@@ -416,57 +438,20 @@ impl Position {
       ));
     }
     let move_no = move_no.unwrap();
-    let hash = compute_hash(&board, &black_pockets, &white_pockets, side);
-    let (
-      all_pieces,
-      all_pieces2,
-      all_pieces3,
-      all_pieces4,
-      black_pieces,
-      white_pieces,
-      sliding_pieces,
-    ) = board::compute_all_pieces(&board);
-    //check pawns and knights in promotion zone
-    for k in bitboards::Bits128(
-      (!bitboards::BLACK_UNPROMOTED_KNIGHT & black_pieces)
-        | (!bitboards::WHITE_UNPROMOTED_KNIGHT & white_pieces),
-    ) {
-      let v = board[k];
-      if !piece::is_promoted(v) && !piece::could_unpromoted(v, k) {
-        return Err(ParseSFENError::new(
-          sfen,
-          format!(
-            "unpromoted {} at cell {}",
-            piece::to_human_string(v.abs()),
-            cell::to_string(k)
-          ),
-        ));
-      }
-    }
-    let pos = Position {
-      board,
-      black_pockets,
-      white_pockets,
-      black_king_position: board::find_king_position(&board, 1),
-      white_king_position: board::find_king_position(&board, -1),
-      all_pieces,
-      all_pieces2,
-      all_pieces3,
-      all_pieces4,
-      black_pieces,
-      white_pieces,
-      sliding_pieces,
-      hash,
-      drop_masks: (compute_drops_mask(&black_pockets) as u16)
-        | ((compute_drops_mask(&white_pockets) as u16) << 8),
-      nifu_masks,
-      side,
-      move_no,
-    };
-    if pos.is_legal() {
+    let problems = validation::too_many_pieces(&board, &black_pockets, &white_pockets);
+    if !problems.is_empty() {
+      return Err(ParseSFENError::with_problems(sfen, problems));
+    }
+    let pos = Position::from_board(board, black_pockets, white_pockets, side, move_no);
+    let problems: Vec<_> = pos
+      .validate()
+      .into_iter()
+      .filter(|p| p.is_fatal())
+      .collect();
+    if problems.is_empty() {
       Ok(pos)
     } else {
-      Err(ParseSFENError::new(sfen, String::from("king under check")))
+      Err(ParseSFENError::with_problems(sfen, problems))
     }
   }
   //true -> stop, false -> continue
@@ -1334,6 +1319,22 @@ impl Position {
   pub fn is_take(&self, m: &Move) -> bool {
     self.board[m.to] != piece::NONE
   }
+  pub fn piece_at(&self, cell: usize) -> i8 {
+    self.board[cell]
+  }
+  pub fn pockets(&self, side: i8) -> &[u8; 8] {
+    if side > 0 {
+      &self.black_pockets
+    } else {
+      &self.white_pockets
+    }
+  }
+  pub fn king_position(&self, side: i8) -> Option<usize> {
+    self.find_king_position(side)
+  }
+  pub fn pieces_count(&self) -> u32 {
+    self.all_pieces.count_ones()
+  }
   //slow (mate or stalemate)
   pub fn has_legal_move(&mut self, allocator: &mut PositionMovesAllocator) -> bool {
     let c = self.compute_checks();
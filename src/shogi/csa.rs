@@ -0,0 +1,609 @@
+//CSA game records (V2.2 and V3.0) used by floodgate and computer shogi tools
+use super::super::io::{FileIterator, Split, Splitter};
+use super::alloc::PositionMovesAllocator;
+use super::game::{Annotation, Game, MoveTime};
+use super::moves::Move;
+use super::{perft, piece, validation, Position};
+
+const PIECES: [(&str, i8); 14] = [
+  ("FU", piece::PAWN),
+  ("KY", piece::LANCE),
+  ("KE", piece::KNIGHT),
+  ("GI", piece::SILVER),
+  ("KI", piece::GOLD),
+  ("KA", piece::BISHOP),
+  ("HI", piece::ROOK),
+  ("OU", piece::KING),
+  ("TO", piece::PROMOTED_PAWN),
+  ("NY", piece::PROMOTED_LANCE),
+  ("NK", piece::PROMOTED_KNIGHT),
+  ("NG", piece::PROMOTED_SILVER),
+  ("UM", piece::PROMOTED_BISHOP),
+  ("RY", piece::PROMOTED_ROOK),
+];
+
+//"$KEY" headers stored under KIF names, other ones are kept as "csa:KEY"
+const HEADERS: [(&str, &str); 5] = [
+  ("EVENT", "event"),
+  ("SITE", "site"),
+  ("START_TIME", "date"),
+  ("TIME_LIMIT", "control"),
+  ("OPENING", "opening"),
+];
+
+//terminators which are written as KIF results (besides %TORYO and %TSUMI)
+const TERMINATIONS: [(&str, &str); 9] = [
+  ("%CHUDAN", "中断"),
+  ("%SENNICHITE", "千日手"),
+  ("%JISHOGI", "持将棋"),
+  ("%TIME_UP", "切れ負け"),
+  ("%ILLEGAL_MOVE", "反則負け"),
+  ("%KACHI", "入玉勝ち"),
+  ("%FUZUMI", "不詰"),
+  ("%+ILLEGAL_ACTION", "反則負け"),
+  ("%-ILLEGAL_ACTION", "反則負け"),
+];
+
+pub(super) fn piece_from_csa(s: &str) -> Option<i8> {
+  PIECES.iter().find(|(t, _)| *t == s).map(|(_, p)| *p)
+}
+
+pub fn piece_to_csa(abs_piece: i8) -> &'static str {
+  PIECES.iter().find(|(_, p)| *p == abs_piece).unwrap().0
+}
+
+//"77" is the 7th file and the 7th rank
+fn cell_from_csa(s: &str) -> Option<usize> {
+  let b = s.as_bytes();
+  if b.len() != 2 || !(b'1'..=b'9').contains(&b[0]) || !(b'1'..=b'9').contains(&b[1]) {
+    return None;
+  }
+  Some(9 * (b[1] - b'1') as usize + (b[0] - b'1') as usize)
+}
+
+fn push_cell_as_csa_str(s: &mut String, cell: usize) {
+  super::cell::push_cell_as_en_str(s, cell, true);
+}
+
+impl Move {
+  //"+7776FU", "-0055KA", piece is written after promotion
+  pub fn to_csa(&self) -> String {
+    let mut s = String::with_capacity(7);
+    s.push(if self.to_piece > 0 { '+' } else { '-' });
+    if self.is_drop() {
+      s.push_str("00");
+    } else {
+      push_cell_as_csa_str(&mut s, self.from);
+    }
+    push_cell_as_csa_str(&mut s, self.to);
+    s.push_str(piece_to_csa(self.to_piece.abs()));
+    s
+  }
+}
+
+impl Position {
+  pub fn parse_csa_move(
+    &mut self,
+    allocator: &mut PositionMovesAllocator,
+    csa: &str,
+  ) -> Option<Move> {
+    if !csa.is_ascii() || csa.len() != 7 {
+      return None;
+    }
+    let side = match &csa[..1] {
+      "+" => 1,
+      "-" => -1,
+      _ => return None,
+    };
+    if side != self.side {
+      return None;
+    }
+    let from = if &csa[1..3] == "00" {
+      None
+    } else {
+      Some(cell_from_csa(&csa[1..3])?)
+    };
+    let to = cell_from_csa(&csa[3..5])?;
+    let abs_piece = piece_from_csa(&csa[5..])?;
+    perft::legal_moves(self, allocator).into_iter().find(|m| {
+      m.to == to
+        && m.to_piece.abs() == abs_piece
+        && match from {
+          Some(from) => !m.is_drop() && m.from == from,
+          None => m.is_drop(),
+        }
+    })
+  }
+}
+
+//splits CSA files into games by "/" lines
+pub struct CSASplitter;
+
+impl Splitter for CSASplitter {
+  fn split(&mut self, s: &str, game: &[String]) -> Split {
+    if s == "/" {
+      Split::End
+    } else if game.is_empty() && s.is_empty() {
+      Split::Skip
+    } else {
+      Split::Push
+    }
+  }
+}
+
+pub fn csa_file_iterator(filename: &str) -> std::io::Result<FileIterator> {
+  Ok(FileIterator::new(filename, Box::new(CSASplitter)))
+}
+
+#[derive(Debug)]
+pub struct ParseCSAGameError {
+  pub msg: String,
+  pub line: String,
+}
+
+impl ParseCSAGameError {
+  fn new(line: &str, msg: String) -> Self {
+    Self {
+      msg,
+      line: line.to_owned(),
+    }
+  }
+}
+
+//"PI", "P1".."P9", "P+" and "P-" lines
+struct Setup {
+  board: [i8; 81],
+  black_pockets: [u8; 8],
+  white_pockets: [u8; 8],
+  side: i8,
+  seen: bool,
+}
+
+impl Default for Setup {
+  fn default() -> Self {
+    Self {
+      board: [piece::NONE; 81],
+      black_pockets: [0; 8],
+      white_pockets: [0; 8],
+      side: 1,
+      seen: false,
+    }
+  }
+}
+
+impl Setup {
+  //"PI82HI22KA" is the initial position without pieces on listed cells
+  fn parse_initial(&mut self, s: &str) -> Result<(), String> {
+    let pos = Position::default();
+    self.board = pos.board;
+    for t in chunks(s, 4) {
+      let cell = cell_from_csa(&t[..2.min(t.len())]).ok_or(format!("invalid cell in \"{}\"", t))?;
+      if self.board[cell] == piece::NONE {
+        return Err(format!("no piece to remove in \"{}\"", t));
+      }
+      self.board[cell] = piece::NONE;
+    }
+    Ok(())
+  }
+  //row is written from the 9th file, empty cell is " * "
+  fn parse_row(&mut self, row: usize, s: &str) -> Result<(), String> {
+    let mut t: Vec<char> = s.chars().collect();
+    if t.len() > 27 {
+      return Err(String::from("too many board columns"));
+    }
+    t.resize(27, ' ');
+    for (col, c) in t.chunks(3).enumerate() {
+      let k = 9 * row + (8 - col);
+      let p: String = c.iter().collect();
+      self.board[k] = match (c[0], &p[1..]) {
+        (' ', " *" | "* " | "  ") => piece::NONE,
+        ('+', t) => piece_from_csa(t).ok_or(format!("invalid piece \"{}\"", p))?,
+        ('-', t) => -piece_from_csa(t).ok_or(format!("invalid piece \"{}\"", p))?,
+        _ => return Err(format!("invalid cell \"{}\"", p)),
+      };
+    }
+    Ok(())
+  }
+  //"P+00KI00FU" (pieces in hand), "P-5152OU" (pieces on board) or "P-00AL" (rest of pieces)
+  fn parse_pieces(&mut self, s: &str, side: i8) -> Result<(), String> {
+    for t in chunks(s, 4) {
+      if t.len() != 4 {
+        return Err(format!("invalid piece \"{}\"", t));
+      }
+      let pockets = if side > 0 {
+        &mut self.black_pockets
+      } else {
+        &mut self.white_pockets
+      };
+      if t == "00AL" {
+        let (black_pieces, white_pieces) = super::board::count_pieces(&self.board);
+        for p in piece::PAWN..piece::KING {
+          let i = p as usize;
+          let used = black_pieces[i]
+            + white_pieces[i]
+            + self.black_pockets[i] as u32
+            + self.white_pockets[i] as u32;
+          let rest = piece::expected_number_of_pieces(p).saturating_sub(used) as u8;
+          if side > 0 {
+            self.black_pockets[i] += rest;
+          } else {
+            self.white_pockets[i] += rest;
+          }
+        }
+        continue;
+      }
+      let p = piece_from_csa(&t[2..]).ok_or(format!("invalid piece \"{}\"", t))?;
+      if &t[..2] == "00" {
+        if p >= piece::KING {
+          return Err(format!("invalid piece in hand \"{}\"", t));
+        }
+        pockets[p as usize] += 1;
+      } else {
+        let cell = cell_from_csa(&t[..2]).ok_or(format!("invalid cell in \"{}\"", t))?;
+        self.board[cell] = side * p;
+      }
+    }
+    Ok(())
+  }
+  //true if line belongs to the position setup
+  fn parse_line(&mut self, s: &str) -> Result<bool, String> {
+    if let Some(t) = s.strip_prefix("PI") {
+      self.seen = true;
+      self.parse_initial(t)?;
+      return Ok(true);
+    }
+    if let Some(t) = s.strip_prefix("P+") {
+      self.seen = true;
+      self.parse_pieces(t, 1)?;
+      return Ok(true);
+    }
+    if let Some(t) = s.strip_prefix("P-") {
+      self.seen = true;
+      self.parse_pieces(t, -1)?;
+      return Ok(true);
+    }
+    let mut it = s.chars();
+    if let (Some('P'), Some(d @ '1'..='9')) = (it.next(), it.next()) {
+      self.seen = true;
+      self.parse_row(d as usize - '1' as usize, it.as_str())?;
+      return Ok(true);
+    }
+    Ok(false)
+  }
+  fn position(&self) -> Result<Position, String> {
+    let problems =
+      validation::too_many_pieces(&self.board, &self.black_pockets, &self.white_pockets);
+    let problems = if problems.is_empty() {
+      let pos = Position::from_board(
+        self.board,
+        self.black_pockets,
+        self.white_pockets,
+        self.side,
+        1,
+      );
+      let problems: Vec<_> = pos
+        .validate()
+        .into_iter()
+        .filter(|p| p.is_fatal())
+        .collect();
+      if problems.is_empty() {
+        return Ok(pos);
+      }
+      problems
+    } else {
+      problems
+    };
+    Err(validation::describe(&problems))
+  }
+}
+
+//ASCII string split into pieces of n bytes, the last one could be shorter
+fn chunks(s: &str, n: usize) -> impl Iterator<Item = &str> {
+  (0..s.len())
+    .step_by(n)
+    .map(move |i| &s[i..s.len().min(i + n)])
+}
+
+//"T12" or "T12.345" (V3) to whole seconds
+fn parse_time(s: &str) -> Option<u32> {
+  let t = s.strip_prefix('T')?;
+  let t = t.split('.').next()?;
+  t.parse::<u32>().ok()
+}
+
+pub(super) fn terminate(g: &mut Game, pos: &mut Position, s: &str) {
+  match s {
+    "%TORYO" => g.resign(pos.side),
+    "%TSUMI" => g.loss(pos.side),
+    "%TIME_UP" => g.out_of_time(pos.side),
+    "%ILLEGAL_MOVE" => g.illegal_move(pos.side),
+    "%+ILLEGAL_ACTION" => g.illegal_move(1),
+    "%-ILLEGAL_ACTION" => g.illegal_move(-1),
+    "%KACHI" => g.loss(-pos.side),
+    _ => (),
+  }
+  if s == "%TORYO" || s == "%TSUMI" {
+    let mut allocator = PositionMovesAllocator::default();
+    let _ = g.adjourn(pos, &mut allocator);
+    return;
+  }
+  let t = match TERMINATIONS.iter().find(|(csa, _)| *csa == s) {
+    //illegal action of the side which made the last move
+    Some(("%+ILLEGAL_ACTION", _)) if pos.side < 0 => "反則勝ち",
+    Some(("%-ILLEGAL_ACTION", _)) if pos.side > 0 => "反則勝ち",
+    Some((_, kif)) => kif,
+    //%HIKIWAKE, %MATTA, %ERROR are kept as is
+    None => s,
+  };
+  g.set_header(String::from("termination"), t.to_owned());
+}
+
+pub fn parse_csa_game(a: &[String]) -> Result<Game, ParseCSAGameError> {
+  let mut g = Game::default();
+  let mut allocator = PositionMovesAllocator::default();
+  let mut setup = Setup::default();
+  let mut pos: Option<Position> = None;
+  let mut totals = [0u32; 2];
+  let mut finished = false;
+  for line in a {
+    log::debug!("process line {}", line);
+    //comments aren't split by commas
+    if let Some(t) = line.strip_prefix('\'') {
+      if t.starts_with("CSA encoding=") {
+        continue;
+      }
+      let a = if let Some(t) = t.strip_prefix('&') {
+        Annotation::Bookmark(t.to_owned())
+      } else {
+        Annotation::Comment(t.strip_prefix('*').unwrap_or(t).to_owned())
+      };
+      let k = if finished {
+        g.moves.len() + 1
+      } else {
+        g.moves.len()
+      };
+      g.annotations.entry(k).or_default().push(a);
+      continue;
+    }
+    for s in line.split(',').map(|s| s.trim()) {
+      if s.is_empty() || s.starts_with('V') {
+        continue;
+      }
+      if let Some(t) = s.strip_prefix("N+") {
+        g.set_header(String::from("sente"), t.to_owned());
+        continue;
+      }
+      if let Some(t) = s.strip_prefix("N-") {
+        g.set_header(String::from("gote"), t.to_owned());
+        continue;
+      }
+      if let Some(t) = s.strip_prefix('$') {
+        let (key, value) = t
+          .split_once(':')
+          .ok_or_else(|| ParseCSAGameError::new(s, String::from("expected colon in header")))?;
+        let key = match HEADERS.iter().find(|(csa, _)| *csa == key) {
+          Some((_, en)) => en.to_string(),
+          None => format!("csa:{}", key),
+        };
+        g.set_header(key, value.to_owned());
+        continue;
+      }
+      if pos.is_none() {
+        if s == "+" || s == "-" {
+          setup.side = if s == "+" { 1 } else { -1 };
+          if !setup.seen {
+            return Err(ParseCSAGameError::new(
+              s,
+              String::from("position is missing"),
+            ));
+          }
+          let p = setup
+            .position()
+            .map_err(|msg| ParseCSAGameError::new(s, msg))?;
+          if p.to_string() != Position::default().to_string() {
+            g.set_header(String::from("sfen"), p.to_string());
+          }
+          pos = Some(p);
+          continue;
+        }
+        match setup.parse_line(s) {
+          Ok(true) => continue,
+          Ok(false) => {
+            return Err(ParseCSAGameError::new(
+              s,
+              String::from("expected position or side to move"),
+            ))
+          }
+          Err(msg) => return Err(ParseCSAGameError::new(s, msg)),
+        }
+      }
+      let pos = pos.as_mut().unwrap();
+      if finished {
+        //times after the terminator
+        continue;
+      }
+      if s.starts_with('%') {
+        terminate(&mut g, pos, s);
+        finished = true;
+        continue;
+      }
+      if s.starts_with('T') {
+        let elapsed =
+          parse_time(s).ok_or_else(|| ParseCSAGameError::new(s, String::from("invalid time")))?;
+        if g.times.len() + 1 == g.moves.len() {
+          let side = if pos.side > 0 { 1 } else { 0 };
+          totals[side] += elapsed;
+          g.times.push(MoveTime {
+            elapsed,
+            total: totals[side],
+          });
+        }
+        continue;
+      }
+      match pos.parse_csa_move(&mut allocator, s) {
+        Some(m) => {
+          pos.do_move(&m);
+          g.moves.push(m);
+        }
+        None => return Err(ParseCSAGameError::new(s, String::from("illegal move"))),
+      }
+    }
+  }
+  if pos.is_none() {
+    return Err(ParseCSAGameError::new(
+      "",
+      String::from("position is missing"),
+    ));
+  }
+  //times are kept only if every move has them
+  if g.times.len() != g.moves.len() {
+    g.times.clear();
+  }
+  Ok(g)
+}
+
+fn push_position(s: &mut String, pos: &Position) {
+  if pos.to_string() == Position::default().to_string() {
+    s.push_str("PI\n+\n");
+    return;
+  }
+  for row in 0..9 {
+    s.push('P');
+    s.push((b'1' + row as u8) as char);
+    for c in pos.board.iter().skip(9 * row).take(9).rev() {
+      match *c {
+        piece::NONE => s.push_str(" * "),
+        p => {
+          s.push(if p > 0 { '+' } else { '-' });
+          s.push_str(piece_to_csa(p.abs()));
+        }
+      }
+    }
+    s.push('\n');
+  }
+  for (side, pockets) in [(1, &pos.black_pockets), (-1, &pos.white_pockets)] {
+    if pockets.iter().all(|&c| c == 0) {
+      continue;
+    }
+    s.push_str(if side > 0 { "P+" } else { "P-" });
+    for (p, &c) in pockets.iter().enumerate().skip(1).rev() {
+      for _ in 0..c {
+        s.push_str("00");
+        s.push_str(piece_to_csa(p as i8));
+      }
+    }
+    s.push('\n');
+  }
+  s.push_str(if pos.side > 0 { "+\n" } else { "-\n" });
+}
+
+fn push_annotations(s: &mut String, game: &Game, moves_played: usize) {
+  for a in game.annotations.get(&moves_played).into_iter().flatten() {
+    let (c, t) = match a {
+      Annotation::Comment(t) => ('*', t),
+      Annotation::Bookmark(t) => ('&', t),
+      //NAGs have no CSA counterpart
+      Annotation::Nag(_) => continue,
+    };
+    s.push('\'');
+    s.push(c);
+    s.push_str(t);
+    s.push('\n');
+  }
+}
+
+//CSA termination of the game result, None if game isn't finished
+pub(super) fn termination(game: &Game) -> Option<String> {
+  if game.header.contains_key("checkmate") {
+    return Some(String::from("%TSUMI"));
+  }
+  if game.header.contains_key("resignation") {
+    return Some(String::from("%TORYO"));
+  }
+  let t = game.header.get("termination")?;
+  if t.starts_with('%') {
+    return Some(t.clone());
+  }
+  if t == "反則勝ち" {
+    //loser made the last move
+    let loser = if game.header.get("result").map(|r| r.as_str()) == Some("2") {
+      '-'
+    } else {
+      '+'
+    };
+    return Some(format!("%{}ILLEGAL_ACTION", loser));
+  }
+  TERMINATIONS
+    .iter()
+    .find(|(_, kif)| kif == t)
+    .map(|(csa, _)| csa.to_string())
+}
+
+//"15分+60秒" control is written as "hh:mm+ss", controls which can't be parsed are kept as is
+fn time_limit(game: &Game) -> Option<String> {
+  let control = game.header.get("control")?;
+  if control.contains(':') {
+    return Some(control.clone());
+  }
+  let base = match game.base_time() {
+    Some(base) => base,
+    None => return Some(control.clone()),
+  };
+  let byoyomi = control
+    .split_once('+')
+    .and_then(|(_, t)| t.trim_end_matches('秒').parse::<u32>().ok())
+    .unwrap_or(0);
+  Some(format!(
+    "{:02}:{:02}+{:02}",
+    base / 3600,
+    base % 3600 / 60,
+    byoyomi
+  ))
+}
+
+pub fn game_to_csa(game: &Game, start_pos: Option<&Position>) -> String {
+  let mut s = String::from("V2.2\n");
+  for (key, sign) in [("sente", '+'), ("gote", '-')] {
+    if let Some(t) = game.header.get(key) {
+      s.push_str(&format!("N{}{}\n", sign, t));
+    }
+  }
+  for (csa, en) in HEADERS {
+    let t = if en == "control" {
+      time_limit(game)
+    } else {
+      game.header.get(en).cloned()
+    };
+    if let Some(t) = t {
+      s.push_str(&format!("${}:{}\n", csa, t));
+    }
+  }
+  for (key, t) in &game.header {
+    if let Some(key) = key.strip_prefix("csa:") {
+      s.push_str(&format!("${}:{}\n", key, t));
+    }
+  }
+  push_annotations(&mut s, game, 0);
+  let pos = match start_pos {
+    Some(pos) => pos.clone(),
+    None => game.start_position().unwrap_or_default(),
+  };
+  push_position(&mut s, &pos);
+  let with_times = !game.times.is_empty() && game.times.len() == game.moves.len();
+  for (i, m) in game.moves.iter().enumerate() {
+    s.push_str(&m.to_csa());
+    s.push('\n');
+    if with_times {
+      s.push_str(&format!("T{}\n", game.times[i].elapsed));
+    }
+    push_annotations(&mut s, game, i + 1);
+  }
+  if let Some(t) = termination(game) {
+    s.push_str(&t);
+    s.push('\n');
+  }
+  for (&k, _) in game.annotations.range(game.moves.len() + 1..) {
+    push_annotations(&mut s, game, k);
+  }
+  s
+}
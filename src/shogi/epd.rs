@@ -0,0 +1,143 @@
+//EPD-like lines of SFEN files: SFEN followed by operations "opcode operand ...;",
+//e.g. 'id "gen-1"; dm 3; c0 "▲５二金 △同　玉 ▲４二金"; pv G*5b 5a5b G*4b; acn 123;'
+//dm is the length of the mate in moves (plies) and pv has USI moves
+use super::moves::Move;
+use super::packed::{Payload, DM_ABORTED};
+use super::{ParseSFENError, Position};
+
+pub struct EPD {
+  pub pos: Position,
+  //operands of quoted strings are unquoted
+  pub operations: Vec<(String, Vec<String>)>,
+}
+
+//operand tokens with ';' as a separate token, quoted strings could have spaces and semicolons
+fn tokens(s: &str) -> Result<Vec<String>, String> {
+  let mut r = Vec::new();
+  let mut it = s.chars().peekable();
+  while let Some(&c) = it.peek() {
+    if c.is_whitespace() {
+      it.next();
+    } else if c == ';' {
+      it.next();
+      r.push(String::from(";"));
+    } else if c == '"' {
+      it.next();
+      let mut t = String::new();
+      loop {
+        match it.next() {
+          Some('"') => break,
+          Some(c) => t.push(c),
+          None => return Err(String::from("unterminated string operand")),
+        }
+      }
+      r.push(t);
+    } else {
+      let mut t = String::new();
+      while let Some(&c) = it.peek() {
+        if c.is_whitespace() || c == ';' || c == '"' {
+          break;
+        }
+        t.push(c);
+        it.next();
+      }
+      r.push(t);
+    }
+  }
+  Ok(r)
+}
+
+//operands with spaces or semicolons are quoted
+impl std::fmt::Display for EPD {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.pos)?;
+    for (opcode, operands) in &self.operations {
+      write!(f, " {}", opcode)?;
+      for t in operands {
+        if t.is_empty() || t.contains(|c: char| c.is_whitespace() || c == ';') {
+          write!(f, " \"{}\"", t)?;
+        } else {
+          write!(f, " {}", t)?;
+        }
+      }
+      write!(f, ";")?;
+    }
+    Ok(())
+  }
+}
+
+impl EPD {
+  //move number of SFEN could be omitted if operations follow
+  pub fn parse(line: &str) -> Result<Self, ParseSFENError> {
+    let a: Vec<&str> = line.trim().splitn(5, char::is_whitespace).collect();
+    let (sfen, rest) = if a.len() >= 4 && a[3].chars().all(|c| c.is_ascii_digit()) {
+      (a[..4].join(" "), a.get(4).copied().unwrap_or(""))
+    } else if a.len() >= 3 {
+      let rest = line
+        .trim()
+        .splitn(4, char::is_whitespace)
+        .nth(3)
+        .unwrap_or("");
+      (format!("{} 1", a[..3].join(" ")), rest)
+    } else {
+      return Err(ParseSFENError::new(line, String::from("too few fields")));
+    };
+    let pos = Position::parse_sfen(&sfen)?;
+    let t = tokens(rest).map_err(|msg| ParseSFENError::new(line, msg))?;
+    let mut operations = Vec::new();
+    for op in t.split(|x| x == ";") {
+      match op.split_first() {
+        Some((opcode, operands)) => operations.push((opcode.clone(), operands.to_vec())),
+        None => continue,
+      }
+    }
+    if t.last().map(|x| x.as_str()).unwrap_or(";") != ";" {
+      return Err(ParseSFENError::new(
+        line,
+        String::from("operation isn't terminated by semicolon"),
+      ));
+    }
+    Ok(Self { pos, operations })
+  }
+  //payload of packed SFEN record gives dm and bm (best move) operations
+  pub fn from_packed(pos: Position, payload: Option<&Payload>) -> Self {
+    let mut operations = Vec::new();
+    if let Some(p) = payload {
+      if p.dm > 0 && p.dm != DM_ABORTED {
+        operations.push((String::from("dm"), vec![p.dm.to_string()]));
+      }
+      if p.best_move != 0 {
+        operations.push((String::from("bm"), vec![Move::from(p.best_move).to_usi()]));
+      }
+    }
+    Self { pos, operations }
+  }
+  pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+    self
+      .operations
+      .iter()
+      .find(|(op, _)| op == opcode)
+      .map(|(_, operands)| operands.as_slice())
+  }
+  fn first_operand(&self, opcode: &str) -> Option<&str> {
+    self
+      .operands(opcode)
+      .and_then(|a| a.first())
+      .map(|s| s.as_str())
+  }
+  pub fn id(&self) -> Option<&str> {
+    self.first_operand("id")
+  }
+  pub fn comment(&self) -> Option<&str> {
+    self.first_operand("c0")
+  }
+  pub fn dm(&self) -> Option<u8> {
+    self.first_operand("dm").and_then(|s| s.parse().ok())
+  }
+  pub fn pv(&self) -> Option<&[String]> {
+    self.operands("pv")
+  }
+  pub fn acn(&self) -> Option<u64> {
+    self.first_operand("acn").and_then(|s| s.parse().ok())
+  }
+}
@@ -17,10 +17,59 @@ impl std::fmt::Display for GameResult {
   }
 }
 
+//time spent on the move and cumulative time of the side which made it (seconds)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MoveTime {
+  pub elapsed: u32,
+  pub total: u32,
+}
+
+//KIF comment ("*" line), bookmark ("&" line) or PGN numeric annotation glyph ("$1" is good move)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Annotation {
+  Comment(String),
+  Bookmark(String),
+  Nag(u8),
+}
+
+//annotations keyed by number of moves played from the start position (0 is for the game comments)
+pub type Annotations = std::collections::BTreeMap<usize, Vec<Annotation>>;
+
+//alternative line which replaces moves of the parent line starting from the move with index start
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Variation {
+  pub start: usize,
+  pub moves: Vec<Move>,
+  pub times: Vec<MoveTime>,
+  pub annotations: Annotations,
+  pub variations: Vec<Variation>,
+}
+
+impl Variation {
+  fn push_lines(&self, prefix: &[Move], r: &mut Vec<Vec<Move>>) {
+    let mut line = prefix[..self.start].to_vec();
+    line.extend_from_slice(&self.moves);
+    r.push(line.clone());
+    for v in &self.variations {
+      v.push_lines(&line, r);
+    }
+  }
+}
+
 #[derive(Default)]
 pub struct Game {
+  //keys shared by formats are english words ("sente", "termination"),
+  //keys which only one format defines are kept as "kif:key" or "csa:KEY"
   pub header: std::collections::BTreeMap<String, String>,
+  //main line
   pub moves: Vec<Move>,
+  //empty if game record doesn't have times, otherwise one item per move
+  pub times: Vec<MoveTime>,
+  pub annotations: Annotations,
+  //alternatives to the main line moves
+  pub variations: Vec<Variation>,
+  //header keys in order of appearance in the source record, "#" lines are kept as is
+  pub header_order: Vec<String>,
 }
 
 const UNDEFINED_STRING: &str = "???";
@@ -42,6 +91,36 @@ impl Game {
       self.result().to_string()
     )
   }
+  //move sequences from the start position for every variation, main line is the first one
+  pub fn lines(&self) -> Vec<Vec<Move>> {
+    let mut r = vec![self.moves.clone()];
+    for v in &self.variations {
+      v.push_lines(&self.moves, &mut r);
+    }
+    r
+  }
+  //comment after the move with index moves_played - 1
+  pub fn add_comment(&mut self, moves_played: usize, comment: String) {
+    self
+      .annotations
+      .entry(moves_played)
+      .or_default()
+      .push(Annotation::Comment(comment));
+  }
+  pub fn comments(&self, moves_played: usize) -> Vec<&str> {
+    self
+      .annotations
+      .get(&moves_played)
+      .map(|a| {
+        a.iter()
+          .filter_map(|x| match x {
+            Annotation::Comment(s) => Some(s.as_str()),
+            _ => None,
+          })
+          .collect()
+      })
+      .unwrap_or_default()
+  }
   pub fn copy_header(&mut self, src: &Game, key: &str) {
     let key = key.to_owned();
     if let Some(value) = src.header.get(&key) {
@@ -54,18 +133,65 @@ impl Game {
   pub fn set_header(&mut self, key: String, value: String) {
     self.header.insert(key, value);
   }
-  pub fn loss(&mut self, move_no: u32) {
-    self.set_header("result".to_owned(), (2 + (move_no % 2)).to_string());
+  //side which lost the game
+  pub fn loss(&mut self, side: i8) {
+    self.set_header(
+      "result".to_owned(),
+      (if side > 0 { "3" } else { "2" }).to_owned(),
+    );
   }
-  pub fn resign(&mut self, move_no: u32) {
+  pub fn resign(&mut self, side: i8) {
     self.set_header("resignation".to_owned(), "true".to_owned());
-    self.loss(move_no);
+    self.loss(side);
+  }
+  pub fn illegal_move(&mut self, side: i8) {
+    self.loss(side);
+  }
+  pub fn out_of_time(&mut self, side: i8) {
+    self.loss(side);
+  }
+  //"sfen" header is set for games which don't start from the initial position
+  pub fn start_position(&self) -> Result<Position, super::ParseSFENError> {
+    match self.header.get("sfen") {
+      Some(sfen) => Position::parse_sfen(sfen),
+      None => Ok(Position::default()),
+    }
+  }
+  //base time in seconds from "control" header ("15分+60秒", "各1時間", "600秒", CSA "00:25+00")
+  pub fn base_time(&self) -> Option<u32> {
+    let control = self.header.get("control")?;
+    if let Some((h, t)) = control.split_once(':') {
+      let m = t.split('+').next()?;
+      return Some(3600 * h.trim().parse::<u32>().ok()? + 60 * m.trim().parse::<u32>().ok()?);
+    }
+    let start = control.find(|c: char| c.is_ascii_digit())?;
+    let t = &control[start..];
+    let end = t.find(|c: char| !c.is_ascii_digit()).unwrap_or(t.len());
+    let n = t[..end].parse::<u32>().ok()?;
+    let unit = &t[end..];
+    if unit.starts_with("時間") {
+      Some(3600 * n)
+    } else if unit.starts_with('分') {
+      Some(60 * n)
+    } else if unit.starts_with('秒') {
+      Some(n)
+    } else {
+      None
+    }
   }
-  pub fn illegal_move(&mut self, move_no: u32) {
-    self.loss(move_no);
+  //time used by the side to move before the move with given index
+  pub fn time_used_before(&self, move_index: usize) -> Option<u32> {
+    self
+      .times
+      .get(move_index)
+      .map(|t| t.total.saturating_sub(t.elapsed))
   }
-  pub fn out_of_time(&mut self, move_no: u32) {
-    self.loss(move_no);
+  //main time left to the side to move before the move with given index
+  pub fn time_left_before(&self, move_index: usize) -> Option<u32> {
+    let base = self.base_time()?;
+    self
+      .time_used_before(move_index)
+      .map(|used| base.saturating_sub(used))
   }
   pub fn result(&self) -> GameResult {
     if let Some(t) = self.header.get("result") {
@@ -86,7 +212,6 @@ impl Game {
     match self.result() {
       GameResult::BlackWon => {
         if pos.side < 0 {
-          assert_eq!(self.moves.len() % 2, 1);
           if !pos.has_legal_move(allocator) {
             self.set_header(String::from("checkmate"), String::from("true"));
           } else {
@@ -101,7 +226,6 @@ impl Game {
       }
       GameResult::WhiteWon => {
         if pos.side > 0 {
-          assert_eq!(self.moves.len() % 2, 0);
           if !pos.has_legal_move(allocator) {
             self.set_header(String::from("checkmate"), String::from("true"));
           } else {
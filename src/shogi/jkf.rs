@@ -0,0 +1,588 @@
+//JKF (json-kifu-format) game records used by web viewers: headers have KIF keys,
+//pieces and terminations ("special") have CSA names, forks are variations
+use super::super::json::{self, object, Value};
+use super::alloc::PositionMovesAllocator;
+use super::csa::{piece_from_csa, piece_to_csa};
+use super::game::{Annotation, Game, MoveTime, Variation};
+use super::kif::{handicap_sfen, KIFBuilder, JP_COLS, JP_ROWS};
+use super::moves::Move;
+use super::{cell, perft, piece, validation, Position};
+use std::convert::TryFrom;
+
+//presets of handicap games with KIF handicap names
+const PRESETS: [(&str, &str); 12] = [
+  ("KY", "香落ち"),
+  ("KY_R", "右香落ち"),
+  ("KA", "角落ち"),
+  ("HI", "飛車落ち"),
+  ("HIKY", "飛香落ち"),
+  ("2", "二枚落ち"),
+  ("3", "三枚落ち"),
+  ("4", "四枚落ち"),
+  ("5", "五枚落ち"),
+  ("6", "六枚落ち"),
+  ("8", "八枚落ち"),
+  ("10", "十枚落ち"),
+];
+
+//"relative" letters of KI2 modifiers
+const RELATIVE: [(char, char); 7] = [
+  ('L', '左'),
+  ('C', '直'),
+  ('R', '右'),
+  ('U', '上'),
+  ('M', '寄'),
+  ('D', '引'),
+  ('H', '打'),
+];
+
+const HAND_PIECES: [i8; 7] = [
+  piece::PAWN,
+  piece::LANCE,
+  piece::KNIGHT,
+  piece::SILVER,
+  piece::GOLD,
+  piece::BISHOP,
+  piece::ROOK,
+];
+
+#[derive(Debug)]
+pub struct ParseJKFGameError {
+  pub msg: String,
+  //JSON of the invalid part
+  pub line: String,
+}
+
+impl ParseJKFGameError {
+  fn new(v: Option<&Value>, msg: String) -> Self {
+    Self {
+      msg,
+      line: v.map(|v| v.to_string()).unwrap_or_default(),
+    }
+  }
+}
+
+//splits file into JKF documents (one or many in JSON lines), every game is returned as one line
+pub fn jkf_file_iterator(
+  filename: &str,
+) -> std::io::Result<impl Iterator<Item = std::io::Result<Vec<String>>>> {
+  let text = super::super::encoding::read_to_string(filename)?;
+  let values = json::parse_values(&text)
+    .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))?;
+  Ok(values.into_iter().map(|v| Ok(vec![v.to_string()])))
+}
+
+fn cell_to_jkf(cell: usize) -> Value {
+  let (row, col) = cell::unpack(cell);
+  object(vec![
+    ("x", Value::from(col as u32 + 1)),
+    ("y", Value::from(row as u32 + 1)),
+  ])
+}
+
+fn cell_from_jkf(v: &Value) -> Option<usize> {
+  let x = v.get("x")?.as_i64()?;
+  let y = v.get("y")?.as_i64()?;
+  if !(1..=9).contains(&x) || !(1..=9).contains(&y) {
+    return None;
+  }
+  Some(9 * (y - 1) as usize + (x - 1) as usize)
+}
+
+fn color(side: i8) -> Value {
+  Value::from(if side > 0 { 0 } else { 1 })
+}
+
+//"now" has minutes and seconds, "total" has hours too
+fn time_to_jkf(t: &MoveTime) -> Value {
+  object(vec![
+    (
+      "now",
+      object(vec![
+        ("m", Value::from(t.elapsed / 60)),
+        ("s", Value::from(t.elapsed % 60)),
+      ]),
+    ),
+    (
+      "total",
+      object(vec![
+        ("h", Value::from(t.total / 3600)),
+        ("m", Value::from(t.total % 3600 / 60)),
+        ("s", Value::from(t.total % 60)),
+      ]),
+    ),
+  ])
+}
+
+fn parse_duration(v: &Value) -> Option<u32> {
+  let mut r = 0;
+  for (key, k) in [("h", 3600), ("m", 60), ("s", 1)] {
+    if let Some(x) = v.get(key) {
+      r += k * u32::try_from(x.as_i64()?).ok()?;
+    }
+  }
+  Some(r)
+}
+
+fn parse_time(v: &Value) -> Option<MoveTime> {
+  Some(MoveTime {
+    elapsed: parse_duration(v.get("now")?)?,
+    total: parse_duration(v.get("total")?)?,
+  })
+}
+
+fn initial_to_jkf(pos: &Position) -> Value {
+  let sfen = pos.to_string();
+  if sfen == Position::default().to_string() {
+    return object(vec![("preset", Value::from("HIRATE"))]);
+  }
+  for (preset, name) in PRESETS {
+    let handicap = Position::parse_sfen(handicap_sfen(name).unwrap()).unwrap();
+    if handicap.to_string() == sfen {
+      return object(vec![("preset", Value::from(preset))]);
+    }
+  }
+  //board[x - 1][y - 1], the first column is the 1st file
+  let board: Vec<Value> = (0..9)
+    .map(|col| {
+      Value::Array(
+        (0..9)
+          .map(|row| match pos.board[9 * row + col] {
+            piece::NONE => Value::Object(Vec::new()),
+            p => object(vec![
+              ("color", color(p.signum())),
+              ("kind", Value::from(piece_to_csa(p.abs()))),
+            ]),
+          })
+          .collect(),
+      )
+    })
+    .collect();
+  let hands: Vec<Value> = [&pos.black_pockets, &pos.white_pockets]
+    .iter()
+    .map(|pockets| {
+      Value::Object(
+        HAND_PIECES
+          .iter()
+          .map(|&p| {
+            (
+              piece_to_csa(p).to_owned(),
+              Value::from(pockets[p as usize] as u32),
+            )
+          })
+          .collect(),
+      )
+    })
+    .collect();
+  object(vec![
+    ("preset", Value::from("OTHER")),
+    (
+      "data",
+      object(vec![
+        ("color", color(pos.side)),
+        ("board", Value::Array(board)),
+        ("hands", Value::Array(hands)),
+      ]),
+    ),
+  ])
+}
+
+fn parse_data(data: &Value) -> Result<Position, String> {
+  let side = match data.get("color").and_then(|c| c.as_i64()) {
+    Some(0) => 1,
+    Some(1) => -1,
+    _ => return Err(String::from("invalid color")),
+  };
+  let columns = data
+    .get("board")
+    .and_then(|b| b.as_array())
+    .filter(|b| b.len() == 9)
+    .ok_or_else(|| String::from("board isn't 9x9 array"))?;
+  let mut board = [piece::NONE; 81];
+  for (col, column) in columns.iter().enumerate() {
+    let column = column
+      .as_array()
+      .filter(|c| c.len() == 9)
+      .ok_or_else(|| String::from("board isn't 9x9 array"))?;
+    for (row, c) in column.iter().enumerate() {
+      let kind = match c.get("kind") {
+        None => continue,
+        Some(kind) => kind,
+      };
+      let p = kind
+        .as_str()
+        .and_then(piece_from_csa)
+        .ok_or_else(|| format!("invalid piece {}", c))?;
+      board[9 * row + col] = match c.get("color").and_then(|c| c.as_i64()) {
+        Some(0) => p,
+        Some(1) => -p,
+        _ => return Err(format!("invalid piece color {}", c)),
+      };
+    }
+  }
+  let mut pockets = [[0u8; 8]; 2];
+  if let Some(hands) = data.get("hands") {
+    let hands = hands
+      .as_array()
+      .filter(|h| h.len() == 2)
+      .ok_or_else(|| String::from("hands isn't array of two objects"))?;
+    for (i, hand) in hands.iter().enumerate() {
+      for (kind, n) in hand.as_object().into_iter().flatten() {
+        let p = piece_from_csa(kind)
+          .filter(|&p| p < piece::KING)
+          .ok_or_else(|| format!("invalid piece in hand {}", kind))?;
+        pockets[i][p as usize] = n
+          .as_i64()
+          .and_then(|n| u8::try_from(n).ok())
+          .ok_or_else(|| format!("invalid number of pieces in hand {}", n))?;
+      }
+    }
+  }
+  let problems = validation::too_many_pieces(&board, &pockets[0], &pockets[1]);
+  let problems = if problems.is_empty() {
+    let pos = Position::from_board(board, pockets[0], pockets[1], side, 1);
+    let problems: Vec<_> = pos
+      .validate()
+      .into_iter()
+      .filter(|p| p.is_fatal())
+      .collect();
+    if problems.is_empty() {
+      return Ok(pos);
+    }
+    problems
+  } else {
+    problems
+  };
+  Err(validation::describe(&problems))
+}
+
+fn comments_to_jkf(comments: Vec<&str>) -> Option<Value> {
+  if comments.is_empty() {
+    None
+  } else {
+    Some(Value::Array(
+      comments.into_iter().map(Value::from).collect(),
+    ))
+  }
+}
+
+impl Position {
+  //"piece" is the kind before the move, "promote" is given only if the move could promote
+  pub fn move_to_jkf(
+    &mut self,
+    allocator: &mut PositionMovesAllocator,
+    m: &Move,
+    last_move: &Option<Move>,
+  ) -> Value {
+    let mut a = vec![("color", color(self.side))];
+    if !m.is_drop() {
+      a.push(("from", cell_to_jkf(m.from)));
+    }
+    a.push(("to", cell_to_jkf(m.to)));
+    let abs_piece = if m.is_drop() {
+      m.to_piece.abs()
+    } else {
+      m.from_piece.abs()
+    };
+    a.push(("piece", Value::from(piece_to_csa(abs_piece))));
+    if last_move.as_ref().map(|q| q.to) == Some(m.to) {
+      a.push(("same", Value::from(true)));
+    }
+    let choice = !m.is_drop()
+      && perft::legal_moves(self, allocator)
+        .iter()
+        .any(|q| q.from == m.from && q.to == m.to && q.is_promotion() != m.is_promotion());
+    if choice {
+      a.push(("promote", Value::from(m.is_promotion())));
+    }
+    if self.board[m.to] != piece::NONE {
+      a.push(("capture", Value::from(piece_to_csa(self.board[m.to].abs()))));
+    }
+    let relative: String = self
+      .ki2_modifiers(allocator, m)
+      .chars()
+      .filter_map(|c| RELATIVE.iter().find(|r| r.1 == c).map(|r| r.0))
+      .collect();
+    if !relative.is_empty() {
+      a.push(("relative", Value::from(relative)));
+    }
+    object(a)
+  }
+  //move is found by "from" or by "relative" if "from" is omitted
+  pub fn parse_jkf_move(
+    &mut self,
+    allocator: &mut PositionMovesAllocator,
+    v: &Value,
+    last_move: &Option<Move>,
+  ) -> Option<Move> {
+    if let Some(c) = v.get("color") {
+      if c.as_i64()? != if self.side > 0 { 0 } else { 1 } {
+        return None;
+      }
+    }
+    let to = match v.get("to") {
+      Some(to) => cell_from_jkf(to)?,
+      None if v.get("same").and_then(|x| x.as_bool()) == Some(true) => last_move.as_ref()?.to,
+      None => return None,
+    };
+    let abs_piece = piece_from_csa(v.get("piece")?.as_str()?)?;
+    let promotion = v.get("promote").and_then(|x| x.as_bool()) == Some(true);
+    if let Some(from) = v.get("from") {
+      let from = cell_from_jkf(from)?;
+      return perft::legal_moves(self, allocator).into_iter().find(|m| {
+        !m.is_drop()
+          && m.from == from
+          && m.to == to
+          && m.from_piece.abs() == abs_piece
+          && m.is_promotion() == promotion
+      });
+    }
+    let mut ki2 = String::new();
+    let (row, col) = cell::unpack(to);
+    ki2.push(JP_COLS[col]);
+    ki2.push(JP_ROWS[row]);
+    ki2.push_str(piece::to_jp_string(abs_piece));
+    for c in v
+      .get("relative")
+      .and_then(|r| r.as_str())
+      .unwrap_or("")
+      .chars()
+    {
+      ki2.push(RELATIVE.iter().find(|r| r.0 == c)?.1);
+    }
+    if promotion {
+      ki2.push('成');
+    }
+    self.parse_ki2_move(allocator, &ki2, &None)
+  }
+}
+
+//entries of the line moves with comments, times and forks (variations of the same move)
+fn line_to_jkf(
+  v: &Variation,
+  pos: &Position,
+  last_move: Option<Move>,
+  allocator: &mut PositionMovesAllocator,
+) -> Vec<Value> {
+  let mut pos = pos.clone();
+  let mut last_move = last_move;
+  let with_times = !v.times.is_empty() && v.times.len() == v.moves.len();
+  let mut r = Vec::with_capacity(v.moves.len());
+  for (i, m) in v.moves.iter().enumerate() {
+    let k = v.start + i;
+    let mut a = vec![("move", pos.move_to_jkf(allocator, m, &last_move))];
+    if with_times {
+      a.push(("time", time_to_jkf(&v.times[i])));
+    }
+    if let Some(c) = comments_to_jkf(comments(v, k + 1)) {
+      a.push(("comments", c));
+    }
+    let forks: Vec<Value> = v
+      .variations
+      .iter()
+      .filter(|f| f.start == k)
+      .map(|f| Value::Array(line_to_jkf(f, &pos, last_move.clone(), allocator)))
+      .collect();
+    if !forks.is_empty() {
+      a.push(("forks", Value::Array(forks)));
+    }
+    r.push(object(a));
+    pos.do_move(m);
+    last_move = Some(m.clone());
+  }
+  r
+}
+
+fn comments(v: &Variation, moves_played: usize) -> Vec<&str> {
+  v.annotations
+    .get(&moves_played)
+    .into_iter()
+    .flatten()
+    .filter_map(|a| match a {
+      Annotation::Comment(s) => Some(s.as_str()),
+      _ => None,
+    })
+    .collect()
+}
+
+//line starting from the move with index start, special (termination) and position after the line
+fn parse_line(
+  entries: &[Value],
+  pos: &Position,
+  last_move: Option<Move>,
+  start: usize,
+  allocator: &mut PositionMovesAllocator,
+) -> Result<(Variation, Option<String>, Position), ParseJKFGameError> {
+  let mut v = Variation {
+    start,
+    ..Variation::default()
+  };
+  let mut pos = pos.clone();
+  let mut last_move = last_move;
+  let mut special = None;
+  for e in entries {
+    if e.as_object().is_none() {
+      return Err(ParseJKFGameError::new(
+        Some(e),
+        String::from("move entry isn't object"),
+      ));
+    }
+    if special.is_some() {
+      return Err(ParseJKFGameError::new(
+        Some(e),
+        String::from("move entry after special"),
+      ));
+    }
+    let k = start + v.moves.len();
+    if let Some(mv) = e.get("move") {
+      let m = pos
+        .parse_jkf_move(allocator, mv, &last_move)
+        .ok_or_else(|| {
+          ParseJKFGameError::new(Some(mv), format!("illegal move in position {}", pos))
+        })?;
+      for f in e
+        .get("forks")
+        .and_then(|f| f.as_array())
+        .into_iter()
+        .flatten()
+      {
+        let f = f
+          .as_array()
+          .ok_or_else(|| ParseJKFGameError::new(Some(f), String::from("fork isn't array")))?;
+        let (fork, _, _) = parse_line(f, &pos, last_move.clone(), k, allocator)?;
+        v.variations.push(fork);
+      }
+      if let Some(t) = e.get("time").and_then(parse_time) {
+        v.times.push(t);
+      }
+      pos.do_move(&m);
+      v.moves.push(m.clone());
+      last_move = Some(m);
+    } else if let Some(s) = e.get("special") {
+      let s = s
+        .as_str()
+        .ok_or_else(|| ParseJKFGameError::new(Some(s), String::from("special isn't string")))?;
+      special = Some(s.to_owned());
+    }
+    //comments of the special are comments after the result
+    let moves_played = start + v.moves.len() + if special.is_some() { 1 } else { 0 };
+    for c in e
+      .get("comments")
+      .and_then(|c| c.as_array())
+      .into_iter()
+      .flatten()
+    {
+      if let Some(c) = c.as_str() {
+        v.annotations
+          .entry(moves_played)
+          .or_default()
+          .push(Annotation::Comment(c.to_owned()));
+      }
+    }
+  }
+  //times are kept only if every move has them
+  if v.times.len() != v.moves.len() {
+    v.times.clear();
+  }
+  Ok((v, special, pos))
+}
+
+impl KIFBuilder {
+  pub fn game_to_jkf(&self, game: &Game, start_pos: Option<&Position>) -> String {
+    let header: Vec<(String, Value)> = self
+      .headers(game)
+      .into_iter()
+      .map(|(jp, t)| (jp.to_owned(), Value::from(t)))
+      .collect();
+    let pos = match start_pos {
+      Some(pos) => pos.clone(),
+      None => game.start_position().unwrap_or_default(),
+    };
+    let main = Variation {
+      start: 0,
+      moves: game.moves.clone(),
+      times: game.times.clone(),
+      annotations: game.annotations.clone(),
+      variations: game.variations.clone(),
+    };
+    let mut first = Vec::new();
+    if let Some(c) = comments_to_jkf(game.comments(0)) {
+      first.push(("comments", c));
+    }
+    let mut moves = vec![object(first)];
+    let mut allocator = PositionMovesAllocator::default();
+    moves.extend(line_to_jkf(&main, &pos, None, &mut allocator));
+    if let Some(t) = super::csa::termination(game) {
+      let mut a = vec![("special", Value::from(t.trim_start_matches('%')))];
+      if let Some(c) = comments_to_jkf(game.comments(game.moves.len() + 1)) {
+        a.push(("comments", c));
+      }
+      moves.push(object(a));
+    }
+    object(vec![
+      ("header", Value::Object(header)),
+      ("initial", initial_to_jkf(&pos)),
+      ("moves", Value::Array(moves)),
+    ])
+    .to_string()
+  }
+  pub fn parse_jkf_game(&self, s: &str) -> Result<Game, ParseJKFGameError> {
+    let v = json::parse(s).map_err(|err| ParseJKFGameError::new(None, err.to_string()))?;
+    if v.as_object().is_none() {
+      return Err(ParseJKFGameError::new(
+        Some(&v),
+        String::from("JKF isn't object"),
+      ));
+    }
+    let mut g = Game::default();
+    if let Some(header) = v.get("header") {
+      for (key, value) in header.as_object().into_iter().flatten() {
+        let value = match value.as_str() {
+          Some(value) => value,
+          None => continue,
+        };
+        let key = self.header_key(key);
+        if !g.header_order.contains(&key) {
+          g.header_order.push(key.clone());
+        }
+        g.set_header(key, value.to_owned());
+      }
+    }
+    let initial = v.get("initial");
+    let preset = initial
+      .and_then(|i| i.get("preset"))
+      .and_then(|p| p.as_str())
+      .unwrap_or("HIRATE");
+    let handicap = PRESETS.iter().find(|(p, _)| *p == preset).map(|(_, h)| *h);
+    let pos = if let Some(data) = initial.and_then(|i| i.get("data")) {
+      let pos = parse_data(data).map_err(|msg| ParseJKFGameError::new(Some(data), msg))?;
+      g.set_header(String::from("sfen"), pos.to_string());
+      pos
+    } else if preset == "HIRATE" {
+      Position::default()
+    } else if let Some(handicap) = handicap {
+      let sfen = handicap_sfen(handicap).unwrap();
+      g.set_header(String::from("sfen"), sfen.to_owned());
+      if !g.header.contains_key("handicap") {
+        g.set_header(String::from("handicap"), handicap.to_owned());
+      }
+      Position::parse_sfen(sfen).unwrap()
+    } else {
+      return Err(ParseJKFGameError::new(
+        initial,
+        format!("unknown preset {}", preset),
+      ));
+    };
+    let entries = v.get("moves").and_then(|m| m.as_array()).unwrap_or(&[]);
+    let mut allocator = PositionMovesAllocator::default();
+    let (main, special, mut end) = parse_line(entries, &pos, None, 0, &mut allocator)?;
+    g.moves = main.moves;
+    g.times = main.times;
+    g.annotations = main.annotations;
+    g.variations = main.variations;
+    if let Some(special) = special {
+      super::csa::terminate(&mut g, &mut end, &format!("%{}", special));
+    }
+    Ok(g)
+  }
+}
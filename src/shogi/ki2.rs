@@ -0,0 +1,257 @@
+//KI2 move notation (▲７六歩, △同　銀左, ▲２二角成): moves have no origin squares,
+//so pieces of the same kind are told apart by 右/左/直/上/引/寄 and drops by 打
+use super::alloc::PositionMovesAllocator;
+use super::kif::{push_cell_as_jp_str, JP_COLS, JP_ROWS};
+use super::{cell, moves::Move, perft, piece, Position};
+
+fn in_promotion_zone(cell: usize, side: i8) -> bool {
+  let row = cell / 9;
+  if side > 0 {
+    row < 3
+  } else {
+    row > 5
+  }
+}
+
+//上 (forward), 引 (backward) or 寄 (sideways) from the side which moves
+fn direction(m: &Move, side: i8) -> char {
+  let dr = (cell::unpack(m.to).0 as isize - cell::unpack(m.from).0 as isize) * side as isize;
+  if dr < 0 {
+    '上'
+  } else if dr > 0 {
+    '引'
+  } else {
+    '寄'
+  }
+}
+
+//one step straight forward
+fn is_straight_up(m: &Move, side: i8) -> bool {
+  let (from_row, from_col) = cell::unpack(m.from);
+  let (to_row, to_col) = cell::unpack(m.to);
+  from_col == to_col && to_row as isize == from_row as isize - side as isize
+}
+
+//直 is used only for golds, silvers and promoted minor pieces
+fn could_be_straight(abs_piece: i8) -> bool {
+  abs_piece == piece::SILVER
+    || abs_piece == piece::GOLD
+    || (piece::is_promoted(abs_piece)
+      && abs_piece != piece::PROMOTED_BISHOP
+      && abs_piece != piece::PROMOTED_ROOK)
+}
+
+//file 1 is on the right side of sente
+fn rightness(m: &Move, side: i8) -> isize {
+  -(side as isize) * cell::unpack(m.from).1 as isize
+}
+
+//moves of the group selected by the modifiers (direction filters are applied before 右/左)
+fn select<'a>(group: &[&'a Move], modifiers: &str, side: i8) -> Vec<&'a Move> {
+  let mut r: Vec<&Move> = group.to_vec();
+  for c in modifiers.chars() {
+    match c {
+      '上' | '引' | '寄' => r.retain(|m| direction(m, side) == c),
+      '直' => r.retain(|m| is_straight_up(m, side)),
+      _ => (),
+    }
+  }
+  for c in modifiers.chars() {
+    let best = match c {
+      '右' => r.iter().map(|m| rightness(m, side)).max(),
+      '左' => r.iter().map(|m| rightness(m, side)).min(),
+      _ => continue,
+    };
+    r.retain(|m| Some(rightness(m, side)) == best);
+  }
+  r
+}
+
+//shortest modifiers which tell the move from other moves of the same piece kind to the same cell
+fn disambiguation(m: &Move, group: &[&Move], side: i8) -> String {
+  if group.len() <= 1 {
+    return String::new();
+  }
+  let dir = direction(m, side).to_string();
+  let mut candidates = vec![dir.clone()];
+  if could_be_straight(m.from_piece.abs()) {
+    candidates.push(String::from("直"));
+  }
+  for lr in ["右", "左"] {
+    candidates.push(lr.to_owned());
+  }
+  for lr in ["右", "左"] {
+    candidates.push(format!("{}{}", lr, dir));
+  }
+  for t in &candidates {
+    let r = select(group, t, side);
+    if r.len() == 1 && r[0].from == m.from {
+      return t.clone();
+    }
+  }
+  candidates.pop().unwrap()
+}
+
+//moves of the same piece from different cells to the cell to (promotion doesn't matter)
+fn same_piece_moves(moves: &[Move], abs_piece: i8, to: usize) -> Vec<&Move> {
+  let mut r: Vec<&Move> = Vec::new();
+  for m in moves {
+    if !m.is_drop()
+      && m.to == to
+      && m.from_piece.abs() == abs_piece
+      && !r.iter().any(|q| q.from == m.from)
+    {
+      r.push(m);
+    }
+  }
+  r
+}
+
+//右/左/直/上/引/寄 of the board move or 打 of the drop
+fn modifiers(moves: &[Move], m: &Move, side: i8) -> String {
+  if m.is_drop() {
+    //打 is needed only if the piece on board could go to the cell too
+    return if same_piece_moves(moves, m.to_piece.abs(), m.to).is_empty() {
+      String::new()
+    } else {
+      String::from("打")
+    };
+  }
+  let group = same_piece_moves(moves, m.from_piece.abs(), m.to);
+  disambiguation(m, &group, side)
+}
+
+impl Position {
+  //KI2 modifiers of the move without promotion marks (empty if the move isn't ambiguous)
+  pub fn ki2_modifiers(&mut self, allocator: &mut PositionMovesAllocator, m: &Move) -> String {
+    let moves = perft::legal_moves(self, allocator);
+    modifiers(&moves, m, self.side)
+  }
+  pub fn move_to_ki2(
+    &mut self,
+    allocator: &mut PositionMovesAllocator,
+    m: &Move,
+    last_move: &Option<Move>,
+  ) -> String {
+    let moves = perft::legal_moves(self, allocator);
+    let mut s = String::with_capacity(16);
+    if last_move.as_ref().map(|q| q.to) == Some(m.to) {
+      s.push_str("同　");
+    } else {
+      push_cell_as_jp_str(&mut s, m.to);
+    }
+    if m.is_drop() {
+      s.push_str(piece::to_jp_string(m.to_piece.abs()));
+      s.push_str(&modifiers(&moves, m, self.side));
+      return s;
+    }
+    let abs_piece = m.from_piece.abs();
+    s.push_str(piece::to_jp_string(abs_piece));
+    s.push_str(&modifiers(&moves, m, self.side));
+    if m.is_promotion() {
+      s.push('成');
+    } else if piece::could_promoted(abs_piece)
+      && !piece::is_promoted(abs_piece)
+      && (in_promotion_zone(m.from, self.side) || in_promotion_zone(m.to, self.side))
+    {
+      s.push_str("不成");
+    }
+    s
+  }
+  //move with optional ▲/△ prefix, "同" could be followed by spaces
+  pub fn parse_ki2_move(
+    &mut self,
+    allocator: &mut PositionMovesAllocator,
+    ki2: &str,
+    last_move: &Option<Move>,
+  ) -> Option<Move> {
+    let t: String = ki2
+      .trim()
+      .trim_start_matches(['▲', '△', '☗', '☖'])
+      .chars()
+      .filter(|c| !c.is_whitespace())
+      .collect();
+    let mut it = t.chars().peekable();
+    let to = if it.peek() == Some(&'同') {
+      it.next();
+      last_move.as_ref()?.to
+    } else {
+      let c = it.next()?;
+      let col = JP_COLS.iter().position(|&x| x == c).or_else(|| {
+        c.to_digit(10)
+          .map(|d| d as usize)
+          .filter(|&d| d >= 1)
+          .map(|d| d - 1)
+      })?;
+      let c = it.next()?;
+      let row = JP_ROWS.iter().position(|&x| x == c)?;
+      9 * row + col
+    };
+    let mut abs_piece = piece::from_jp_char(*it.peek()?);
+    if abs_piece == piece::NONE && it.next() == Some('成') {
+      //成香, 成桂, 成銀
+      abs_piece = piece::from_jp_char(*it.peek()?);
+      if !matches!(abs_piece, piece::LANCE | piece::KNIGHT | piece::SILVER) {
+        return None;
+      }
+      abs_piece = piece::promote(abs_piece);
+    }
+    if abs_piece == piece::NONE {
+      return None;
+    }
+    it.next();
+    let rest: String = it.collect();
+    let (modifiers, promotion, drop) = if let Some(t) = rest.strip_suffix("不成") {
+      (t, false, false)
+    } else if let Some(t) = rest.strip_suffix('成') {
+      (t, true, false)
+    } else if let Some(t) = rest.strip_suffix('打') {
+      (t, false, true)
+    } else {
+      (rest.as_str(), false, false)
+    };
+    if !modifiers.chars().all(|c| "右左直上引寄".contains(c)) {
+      return None;
+    }
+    let moves = perft::legal_moves(self, allocator);
+    let board_moves: Vec<&Move> = moves
+      .iter()
+      .filter(|m| {
+        !m.is_drop()
+          && m.to == to
+          && m.from_piece.abs() == abs_piece
+          && m.is_promotion() == promotion
+      })
+      .collect();
+    if !drop && !board_moves.is_empty() {
+      let r = select(&board_moves, modifiers, self.side);
+      return if r.len() == 1 {
+        Some(r[0].clone())
+      } else {
+        None
+      };
+    }
+    if promotion || !modifiers.is_empty() {
+      return None;
+    }
+    moves
+      .into_iter()
+      .find(|m| m.is_drop() && m.to == to && m.to_piece.abs() == abs_piece)
+  }
+}
+
+//moves from the position with ▲/△ marks, solutions in tsume books are written this way
+pub fn moves_to_ki2(pos: &Position, moves: &[Move]) -> String {
+  let mut pos = pos.clone();
+  let mut allocator = PositionMovesAllocator::default();
+  let mut last_move = None;
+  let mut r = Vec::with_capacity(moves.len());
+  for m in moves {
+    let mut s = String::from(if pos.side > 0 { "▲" } else { "△" });
+    s.push_str(&pos.move_to_ki2(&mut allocator, m, &last_move));
+    r.push(s);
+    pos.do_move(m);
+    last_move = Some(m.clone());
+  }
+  r.join(" ")
+}
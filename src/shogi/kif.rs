@@ -1,10 +1,13 @@
+use super::super::io::{FileIterator, Split, Splitter};
 use super::alloc::PositionMovesAllocator;
-use super::game::Game;
+use super::game::{Annotation, Annotations, Game, GameResult, MoveTime, Variation};
+use super::moves::Move;
 use super::piece;
+use super::validation;
 use super::Position;
 
-use super::super::io::FileIterator;
 use std::collections::HashMap;
+use std::path::Path;
 
 pub const JP_COLS: [char; 9] = ['１', '２', '３', '４', '５', '６', '７', '８', '９'];
 
@@ -41,6 +44,13 @@ impl Default for KIFBuilder {
 }
 
 impl KIFBuilder {
+  //english key of the header, unknown headers are kept with japanese keys and "kif:" prefix
+  pub(super) fn header_key(&self, jp: &str) -> String {
+    match self.jp_to_en(jp) {
+      Some(en) => en.to_owned(),
+      None => format!("{}{}", KIF_KEY_PREFIX, jp),
+    }
+  }
   fn jp_to_en(&self, word: &str) -> Option<&'static str> {
     self.jp.get(&word).map(|p| *p)
   }
@@ -58,6 +68,12 @@ pub fn push_cell_as_jp_str(s: &mut String, cell: usize) {
 fn push_pockets_as_jp_str(s: &mut String, pockets: &[u8], side: i8) {
   s.push(if side > 0 { '先' } else { '後' });
   s.push_str("手の持駒：");
+  push_hand_as_jp_str(s, pockets);
+  s.push('\n');
+}
+
+//hand pieces with kanji counts ("飛 金二 歩十一") or "なし"
+pub(super) fn push_hand_as_jp_str(s: &mut String, pockets: &[u8]) {
   let mut empty_hand = true;
   for (f, (i, p)) in pockets
     .iter()
@@ -76,7 +92,9 @@ fn push_pockets_as_jp_str(s: &mut String, pockets: &[u8], side: i8) {
       if *p >= 10 {
         assert!(*p < 20);
         s.push('十');
-        s.push(JP_ROWS[(*p % 10) as usize - 1]);
+        if *p > 10 {
+          s.push(JP_ROWS[(*p % 10) as usize - 1]);
+        }
       } else {
         s.push(JP_ROWS[*p as usize - 1]);
       }
@@ -85,7 +103,6 @@ fn push_pockets_as_jp_str(s: &mut String, pockets: &[u8], side: i8) {
   if empty_hand {
     s.push_str("なし");
   }
-  s.push('\n');
 }
 
 const BOARD_DELIMETER: &'static str = "+---------------------------+\n";
@@ -116,67 +133,581 @@ pub fn position_to_kif(s: &mut String, pos: &Position) {
   }
   s.push_str(BOARD_DELIMETER);
   push_pockets_as_jp_str(s, &pos.black_pockets, 1);
+  if pos.move_no > 1 {
+    s.push_str(&format!("手数＝{}\n", pos.move_no - 1));
+  }
   if pos.side < 0 {
     s.push_str("後手番\n");
   }
 }
 
-impl KIFBuilder {
-  pub fn game_to_kif(&self, game: &Game, start_pos: Option<&Position>) -> String {
-    let mut s = KIF_HEADER_LINE.to_owned();
-    s.push('\n');
-    for en in vec![
-      "date", "event", "site", "sfen", "control", "handicap", "sente", "gote",
-    ] {
-      if en == "sfen" {
-        if let Some(pos) = start_pos {
-          position_to_kif(&mut s, pos);
+//kanji numbers up to 19 ("二", "十", "十八")
+fn parse_jp_number(s: &str) -> Option<u8> {
+  if s.is_empty() {
+    return Some(1);
+  }
+  if let Ok(n) = s.parse::<u8>() {
+    return Some(n);
+  }
+  let digit = |c| JP_ROWS.iter().position(|&d| d == c).map(|i| i as u8 + 1);
+  let mut it = s.chars();
+  match (it.next(), it.next(), it.next()) {
+    (Some('十'), None, None) => Some(10),
+    (Some('十'), Some(c), None) => digit(c).map(|d| 10 + d),
+    (Some(c), None, None) => digit(c),
+    _ => None,
+  }
+}
+
+//board diagram (BOD) in KIF header
+struct Bod {
+  board: [i8; 81],
+  black_pockets: [u8; 8],
+  white_pockets: [u8; 8],
+  //"残り全部" in gote hand (tsume collections)
+  white_rest: bool,
+  side: i8,
+  //"手数＝N" line of mid-game diagrams, the position is at move N + 1
+  move_no: u32,
+  rows: usize,
+  seen: bool,
+}
+
+impl Default for Bod {
+  fn default() -> Self {
+    Self {
+      board: [piece::NONE; 81],
+      black_pockets: [0; 8],
+      white_pockets: [0; 8],
+      white_rest: false,
+      side: 1,
+      move_no: 1,
+      rows: 0,
+      seen: false,
+    }
+  }
+}
+
+impl Bod {
+  fn parse_pockets(&mut self, s: &str, side: i8) -> Result<(), String> {
+    let pockets = if side > 0 {
+      &mut self.black_pockets
+    } else {
+      &mut self.white_pockets
+    };
+    for t in s
+      .split(|c: char| c.is_whitespace())
+      .filter(|t| !t.is_empty())
+    {
+      if t == "なし" {
+        continue;
+      }
+      if t == "残り全部" && side < 0 {
+        self.white_rest = true;
+        continue;
+      }
+      let mut it = t.chars();
+      let p = piece::from_jp_char(it.next().unwrap());
+      if p == piece::NONE || p >= piece::KING {
+        return Err(format!("invalid piece in hand \"{}\"", t));
+      }
+      match parse_jp_number(it.as_str()) {
+        Some(n) => pockets[p as usize] += n,
+        None => return Err(format!("invalid number of pieces in hand \"{}\"", t)),
+      }
+    }
+    Ok(())
+  }
+  fn parse_row(&mut self, s: &str) -> Result<(), String> {
+    if self.rows >= 9 {
+      return Err(String::from("too many board rows"));
+    }
+    let row = self.rows;
+    let mut col = 0;
+    let mut side = 1;
+    for c in s.chars().skip(1) {
+      match c {
+        '|' => break,
+        'v' => side = -1,
+        ' ' | '　' | '^' => (),
+        _ => {
+          if col >= 9 {
+            return Err(String::from("too many board columns"));
+          }
+          //columns are written from the 9th file
+          let k = 9 * row + (8 - col);
+          if c != '・' {
+            let p = piece::from_jp_char(c);
+            if p == piece::NONE {
+              return Err(format!("invalid piece '{}' on board", c));
+            }
+            self.board[k] = side * p;
+          }
+          col += 1;
+          side = 1;
         }
-      } else {
-        if let Some(t) = game.header.get(en) {
-          if let Some(jp) = self.en_to_jp(en) {
-            s.push_str(jp);
-            s.push_str(": ");
-            if en == "sente" {
-              if let Some(elo) = game.header.get("senteelo") {
-                s.push_str(&format!("{} ({})", t, elo));
-              } else {
-                s.push_str(t);
-              }
-            } else if en == "gote" {
-              if let Some(elo) = game.header.get("goteelo") {
-                s.push_str(&format!("{} ({})", t, elo));
-              } else {
-                s.push_str(t);
-              }
-            } else {
-              s.push_str(t);
-            };
-            s.push('\n');
+      }
+    }
+    if col != 9 {
+      return Err(String::from("too few board columns"));
+    }
+    self.rows += 1;
+    Ok(())
+  }
+  //true if line belongs to the board diagram
+  fn parse_line(&mut self, s: &str) -> Result<bool, String> {
+    let s = s.trim();
+    let hands = [
+      ("先手の持駒：", 1),
+      ("下手の持駒：", 1),
+      ("後手の持駒：", -1),
+      ("上手の持駒：", -1),
+    ];
+    for (prefix, side) in hands {
+      if let Some(t) = s.strip_prefix(prefix) {
+        self.seen = true;
+        self.parse_pockets(t, side)?;
+        return Ok(true);
+      }
+    }
+    if s.starts_with('|') {
+      self.seen = true;
+      self.parse_row(s)?;
+      return Ok(true);
+    }
+    if let Some(t) = s.strip_prefix("手数＝") {
+      let n = t
+        .split_whitespace()
+        .next()
+        .and_then(|n| n.parse::<u32>().ok())
+        .ok_or_else(|| format!("invalid number of moves \"{}\"", s))?;
+      self.move_no = n + 1;
+      return Ok(true);
+    }
+    if s.starts_with("+--") || s.starts_with('９') {
+      return Ok(true);
+    }
+    if s == "後手番" || s == "上手番" {
+      self.side = -1;
+      return Ok(true);
+    }
+    if s == "先手番" || s == "下手番" {
+      self.side = 1;
+      return Ok(true);
+    }
+    Ok(false)
+  }
+  fn position(&self) -> Result<Position, String> {
+    if self.rows != 9 {
+      return Err(format!("expected 9 board rows, got {}", self.rows));
+    }
+    let mut white_pockets = self.white_pockets;
+    if self.white_rest {
+      let (black_pieces, white_pieces) = super::board::count_pieces(&self.board);
+      for p in piece::PAWN..piece::KING {
+        let i = p as usize;
+        let used = black_pieces[i] + white_pieces[i] + self.black_pockets[i] as u32;
+        white_pockets[i] = piece::expected_number_of_pieces(p).saturating_sub(used) as u8;
+      }
+    }
+    let problems = validation::too_many_pieces(&self.board, &self.black_pockets, &white_pockets);
+    let problems = if problems.is_empty() {
+      let pos = Position::from_board(
+        self.board,
+        self.black_pockets,
+        white_pockets,
+        self.side,
+        self.move_no,
+      );
+      let problems: Vec<_> = pos
+        .validate()
+        .into_iter()
+        .filter(|p| p.is_fatal())
+        .collect();
+      if problems.is_empty() {
+        return Ok(pos);
+      }
+      problems
+    } else {
+      problems
+    };
+    Err(validation::describe(&problems))
+  }
+}
+
+impl KIFBuilder {
+  fn push_header(&self, s: &mut String, game: &Game, start_pos: Option<&Position>, key: &str) {
+    if key.starts_with('#') {
+      s.push_str(key);
+      s.push('\n');
+      return;
+    }
+    if key == "sfen" {
+      if let Some(pos) = start_pos {
+        position_to_kif(s, pos);
+      } else if !game.header.contains_key("handicap") {
+        if let Ok(pos) = game.start_position() {
+          if pos.to_string() != Position::default().to_string() {
+            position_to_kif(s, &pos);
           }
         }
       }
+      return;
     }
-    s.push_str("手数----指手---------消費時間--\n");
-    let mut last_move = None;
-    for (i, m) in game.moves.iter().enumerate() {
-      s.push_str(&format!("{0:>4} {1}\n", i + 1, m.to_kif(&last_move)));
-      last_move = Some(m.clone());
+    if let Some((jp, t)) = self.header_entry(game, key) {
+      s.push_str(jp);
+      s.push('：');
+      s.push_str(&t);
+      s.push('\n');
     }
+  }
+  //japanese key and value of the header
+  fn header_entry<'a>(&self, game: &'a Game, key: &'a str) -> Option<(&'a str, String)> {
+    let t = game.header.get(key)?;
+    let jp = match self.en_to_jp(key) {
+      Some(jp) => jp,
+      None => key.strip_prefix(KIF_KEY_PREFIX)?,
+    };
+    let elo = match key {
+      "sente" => game.header.get("senteelo"),
+      "gote" => game.header.get("goteelo"),
+      _ => None,
+    };
+    if let Some(elo) = elo {
+      Some((jp, format!("{} ({})", t, elo)))
+    } else {
+      Some((jp, t.clone()))
+    }
+  }
+  fn header_keys<'a>(&self, game: &'a Game) -> Vec<&'a str> {
+    let mut keys: Vec<&str> = game.header_order.iter().map(|k| k.as_str()).collect();
+    for en in [
+      "date", "event", "site", "sfen", "control", "handicap", "sente", "gote",
+    ] {
+      if !keys.contains(&en) {
+        keys.push(en);
+      }
+    }
+    for key in game.header.keys() {
+      if key.starts_with(KIF_KEY_PREFIX) && !keys.contains(&key.as_str()) {
+        keys.push(key);
+      }
+    }
+    keys
+  }
+  //headers with japanese keys without board diagram and "#" lines (JKF header)
+  pub(super) fn headers<'a>(&self, game: &'a Game) -> Vec<(&'a str, String)> {
+    self
+      .header_keys(game)
+      .into_iter()
+      .filter(|&key| key != "sfen")
+      .filter_map(|key| self.header_entry(game, key))
+      .collect()
+  }
+  //headers and game comments (shared by KIF and KI2)
+  fn push_headers(&self, s: &mut String, game: &Game, start_pos: Option<&Position>) {
+    for key in self.header_keys(game) {
+      self.push_header(s, game, start_pos, key);
+    }
+    push_annotations(s, &game.annotations, 0);
+  }
+  pub fn game_to_kif(&self, game: &Game, start_pos: Option<&Position>) -> String {
+    let mut s = KIF_HEADER_LINE.to_owned();
+    s.push('\n');
+    self.push_headers(&mut s, game, start_pos);
+    s.push_str("手数----指手---------消費時間--\n");
+    let played = (super::psn::start_position(game, start_pos).0.move_no as usize).saturating_sub(1);
+    push_moves(
+      &mut s,
+      played,
+      &[],
+      &game.moves,
+      &game.times,
+      &game.annotations,
+      &game.variations,
+    );
     if let Some(_) = game.header.get("checkmate") {
-      s.push_str(&format!("{0:>4} {1}\n", game.moves.len() + 1, "詰み"));
+      s.push_str(&format!(
+        "{0:>4} {1}\n",
+        played + game.moves.len() + 1,
+        "詰み"
+      ));
     }
     if let Some(_) = game.header.get("resignation") {
-      s.push_str(&format!("{0:>4} {1}\n", game.moves.len() + 1, "投了"));
+      s.push_str(&format!(
+        "{0:>4} {1}\n",
+        played + game.moves.len() + 1,
+        "投了"
+      ));
     }
+    if let Some(t) = game.header.get("termination") {
+      if TERMINATIONS.contains(&t.as_str()) {
+        s.push_str(&format!("{0:>4} {1}\n", played + game.moves.len() + 1, t));
+      }
+    }
+    //comments after the result line
+    for (&k, _) in game.annotations.range(game.moves.len() + 1..) {
+      push_annotations(&mut s, &game.annotations, k);
+    }
+    push_variations(&mut s, played, &game.moves, &game.variations);
     s
   }
 }
 
+fn push_annotations(s: &mut String, annotations: &Annotations, moves_played: usize) {
+  for a in annotations.get(&moves_played).into_iter().flatten() {
+    let (c, t) = match a {
+      Annotation::Comment(t) => ('*', t),
+      Annotation::Bookmark(t) => ('&', t),
+      //NAGs have no KIF counterpart
+      Annotation::Nag(_) => continue,
+    };
+    s.push(c);
+    s.push_str(t);
+    s.push('\n');
+  }
+}
+
+//moves with '+' mark if there are variations of the move
+//moves continue the line before, played is the number of moves before the start position
+//(mid-game diagrams)
+fn push_moves(
+  s: &mut String,
+  played: usize,
+  before: &[Move],
+  moves: &[Move],
+  times: &[MoveTime],
+  annotations: &Annotations,
+  variations: &[Variation],
+) {
+  let start = before.len();
+  let mut last_move = before.last().cloned();
+  let with_times = times.len() == moves.len();
+  for (i, m) in moves.iter().enumerate() {
+    let kif = m.to_kif(&last_move);
+    s.push_str(&format!("{0:>4} {1}", played + start + i + 1, kif));
+    if with_times {
+      push_move_time(s, &kif, &times[i]);
+    }
+    if variations.iter().any(|v| v.start == start + i) {
+      s.push('+');
+    }
+    s.push('\n');
+    push_annotations(s, annotations, start + i + 1);
+    last_move = Some(m.clone());
+  }
+}
+
+//variations of the line (moves from the start position) are written in Kakinoki order:
+//the latest branch first and sub-variations right after their variation
+fn push_variations(s: &mut String, played: usize, line: &[Move], variations: &[Variation]) {
+  let mut variations: Vec<&Variation> = variations.iter().collect();
+  variations.sort_by_key(|v| std::cmp::Reverse(v.start));
+  for v in variations {
+    s.push_str(&format!("\n変化：{}手\n", played + v.start + 1));
+    push_annotations(s, &v.annotations, v.start);
+    push_moves(
+      s,
+      played,
+      &line[..v.start],
+      &v.moves,
+      &v.times,
+      &v.annotations,
+      &v.variations,
+    );
+    let mut v_line = line[..v.start].to_vec();
+    v_line.extend_from_slice(&v.moves);
+    push_variations(s, played, &v_line, &v.variations);
+  }
+}
+
+//handicaps are taken by gote (uwate) which moves first, left pieces are on the 1st file side
+const HANDICAPS: [(&str, &str); 13] = [
+  (
+    "香落ち",
+    "lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "右香落ち",
+    "1nsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "角落ち",
+    "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "飛車落ち",
+    "lnsgkgsnl/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "飛香落ち",
+    "lnsgkgsn1/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "二枚落ち",
+    "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "三枚落ち",
+    "lnsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "四枚落ち",
+    "1nsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "五枚落ち",
+    "1nsgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "六枚落ち",
+    "2sgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "七枚落ち",
+    "2sgkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "八枚落ち",
+    "3gkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+  (
+    "十枚落ち",
+    "4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+  ),
+];
+
+//None for 平手 (initial position) and unknown handicaps
+pub fn handicap_sfen(name: &str) -> Option<&'static str> {
+  HANDICAPS
+    .iter()
+    .find(|(jp, _)| *jp == name.trim())
+    .map(|(_, sfen)| *sfen)
+}
+
+//move column is padded to 13 half-width characters as in Kakinoki records
+fn push_move_time(s: &mut String, kif: &str, t: &MoveTime) {
+  let width: usize = kif.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum();
+  for _ in width..13.max(width + 1) {
+    s.push(' ');
+  }
+  s.push_str(&format!(
+    "({:>2}:{:02}/{:02}:{:02}:{:02})",
+    t.elapsed / 60,
+    t.elapsed % 60,
+    t.total / 3600,
+    (t.total / 60) % 60,
+    t.total % 60
+  ));
+}
+
+//"1:05" or "00:12:34" to seconds
+fn parse_duration(s: &str) -> Option<u32> {
+  let mut r = 0u32;
+  for t in s.trim().split(':') {
+    r = r
+      .checked_mul(60)?
+      .checked_add(t.trim().parse::<u32>().ok()?)?;
+  }
+  Some(r)
+}
+
+//time field at the end of move line "   1 ７六歩(77)   ( 0:01/00:00:01)"
+fn parse_move_time(s: &str) -> Option<MoveTime> {
+  let (_, t) = s
+    .trim_end()
+    .trim_end_matches('+')
+    .strip_suffix(')')?
+    .rsplit_once('(')?;
+  let (elapsed, total) = t.split_once('/')?;
+  Some(MoveTime {
+    elapsed: parse_duration(elapsed)?,
+    total: parse_duration(total)?,
+  })
+}
+
+const KIF_KEY_PREFIX: &str = "kif:";
+
 const KIF_HEADER_LINE: &str = "#KIF version=2.0 encoding=UTF-8";
 
+//results written after the last move besides 投了 and 詰み
+const TERMINATIONS: [&str; 10] = [
+  "中断",
+  "千日手",
+  "持将棋",
+  "切れ負け",
+  "反則勝ち",
+  "反則負け",
+  "入玉勝ち",
+  "不戦勝",
+  "不戦敗",
+  "不詰",
+];
+
+//lines which could be only in the game header: "key：value" (except variations),
+//"#" comments and board diagram
+fn is_header_line(s: &str) -> bool {
+  if s.starts_with('*') || s.starts_with('&') || s.starts_with("変化：") {
+    return false;
+  }
+  s.starts_with('#')
+    || s.starts_with('|')
+    || s.starts_with('９')
+    || s.starts_with("+--")
+    || parse_header(s).is_some()
+}
+
+//splits KIF files into games: game ends at 81dojo separator, at the end of file or
+//when header of the next game starts after the moves section
+#[derive(Default)]
+pub struct KIFSplitter {
+  moves_section: bool,
+}
+
+impl Splitter for KIFSplitter {
+  fn split(&mut self, s: &str, game: &[String]) -> Split {
+    if game.is_empty() {
+      self.moves_section = false;
+    }
+    if s == KIF_HEADER_LINE {
+      return Split::End;
+    }
+    let moves_header = s.starts_with("手数-");
+    if self.moves_section && (moves_header || is_header_line(s)) {
+      return Split::EndBefore;
+    }
+    if moves_header || is_ki2_moves_line(s) {
+      self.moves_section = true;
+    }
+    if game.is_empty() && s.is_empty() {
+      Split::Skip
+    } else {
+      Split::Push
+    }
+  }
+}
+
+//directory is read as a list of KIF files (sorted by names), KI2 files are split the same way,
+//Shift_JIS files of desktop software are decoded too
 pub fn kif_file_iterator(filename: &str) -> std::io::Result<FileIterator> {
-  FileIterator::new(filename, KIF_HEADER_LINE)
+  let path = Path::new(filename);
+  let files = if path.is_dir() {
+    let mut files = Vec::new();
+    for e in std::fs::read_dir(path)? {
+      let p = e?.path();
+      if p.extension().is_some_and(|e| e == "kif" || e == "kifu") {
+        files.push(p);
+      }
+    }
+    files.sort();
+    files
+  } else {
+    vec![path.to_path_buf()]
+  };
+  Ok(FileIterator::from_files(
+    files,
+    Box::new(KIFSplitter::default()),
+  ))
 }
 
 fn parse_header<'a>(s: &'a str) -> Option<(&'a str, &'a str)> {
@@ -184,7 +715,8 @@ fn parse_header<'a>(s: &'a str) -> Option<(&'a str, &'a str)> {
 }
 
 fn parse_move<'a>(s: &'a str, move_no: u32) -> Option<&'a str> {
-  let mut it = s.split_ascii_whitespace();
+  //'+' marks moves with variations
+  let mut it = s.trim_end().trim_end_matches('+').split_ascii_whitespace();
   if let Some(s) = it.next() {
     if s != move_no.to_string() {
       None
@@ -196,6 +728,67 @@ fn parse_move<'a>(s: &'a str, move_no: u32) -> Option<&'a str> {
   }
 }
 
+fn parse_annotation(s: &str) -> Option<Annotation> {
+  if let Some(t) = s.strip_prefix('*') {
+    Some(Annotation::Comment(t.to_owned()))
+  } else {
+    s.strip_prefix('&')
+      .map(|t| Annotation::Bookmark(t.to_owned()))
+  }
+}
+
+//annotation target is (variation index or main line, number of moves played)
+fn add_annotation(
+  g: &mut Game,
+  lines: &mut [(usize, Variation)],
+  target: (Option<usize>, usize),
+  a: Annotation,
+) {
+  let annotations = match target.0 {
+    Some(k) => &mut lines[k].1.annotations,
+    None => &mut g.annotations,
+  };
+  annotations.entry(target.1).or_default().push(a);
+}
+
+//number of the move line ("  12 ７六歩(77)")
+fn move_number(s: &str) -> Option<u32> {
+  s.split_ascii_whitespace().next()?.parse().ok()
+}
+
+//"変化：12手" to index of the first move of variation
+fn parse_variation_header(s: &str) -> Option<usize> {
+  let n = s.trim().strip_prefix("変化：")?.strip_suffix('手')?;
+  n.trim().parse::<usize>().ok().map(|n| n.saturating_sub(1))
+}
+
+//moves from the start position to the end of the line
+fn variation_line(lines: &[(usize, Variation)], k: usize) -> Vec<Move> {
+  let (parent, v) = &lines[k];
+  let mut r = if k == 0 {
+    Vec::new()
+  } else {
+    let mut r = variation_line(lines, *parent);
+    r.truncate(v.start);
+    r
+  };
+  r.extend_from_slice(&v.moves);
+  r
+}
+
+fn variations_tree(lines: &[(usize, Variation)], parent: usize) -> Vec<Variation> {
+  lines
+    .iter()
+    .enumerate()
+    .skip(1)
+    .filter(|(_, (p, _))| *p == parent)
+    .map(|(k, (_, v))| Variation {
+      variations: variations_tree(lines, k),
+      ..v.clone()
+    })
+    .collect()
+}
+
 #[derive(Debug)]
 pub struct ParseKIFGameError {
   pub msg: String,
@@ -208,7 +801,72 @@ impl ParseKIFGameError {
   }
 }
 
+//board diagram has priority over handicap header
+fn start_position(g: &mut Game, bod: &Bod, line: &str) -> Result<Position, ParseKIFGameError> {
+  if bod.seen {
+    let pos = bod
+      .position()
+      .map_err(|msg| ParseKIFGameError::new(line.to_owned(), msg))?;
+    g.set_header(String::from("sfen"), pos.to_string());
+    return Ok(pos);
+  }
+  match g.header.get("handicap").map(|h| h.trim()) {
+    None | Some("平手") => Ok(Position::default()),
+    Some(h) => match handicap_sfen(h) {
+      Some(sfen) => {
+        g.set_header(String::from("sfen"), sfen.to_owned());
+        Ok(Position::parse_sfen(sfen).unwrap())
+      }
+      None => Err(ParseKIFGameError::new(
+        line.to_owned(),
+        format!("unknown handicap {}", h),
+      )),
+    },
+  }
+}
+
 impl KIFBuilder {
+  //header line, game comment or board diagram line (shared by KIF and KI2)
+  fn parse_header_line(
+    &self,
+    g: &mut Game,
+    bod: &mut Bod,
+    s: &str,
+  ) -> Result<(), ParseKIFGameError> {
+    if s.starts_with('#') {
+      if !s.starts_with("#KIF") {
+        g.header_order.push(s.to_owned());
+      }
+      return Ok(());
+    }
+    if let Some(a) = parse_annotation(s) {
+      g.annotations.entry(0).or_default().push(a);
+      return Ok(());
+    }
+    match bod.parse_line(s) {
+      Ok(true) => {
+        if bod.seen && !g.header_order.iter().any(|k| k == "sfen") {
+          g.header_order.push(String::from("sfen"));
+        }
+        return Ok(());
+      }
+      Ok(false) => (),
+      Err(msg) => return Err(ParseKIFGameError::new(s.to_owned(), msg)),
+    }
+    if let Some((key, value)) = parse_header(s) {
+      let key = self.header_key(key);
+      if !g.header_order.contains(&key) {
+        g.header_order.push(key.clone());
+      }
+      g.set_header(key, value.to_owned());
+      Ok(())
+    } else {
+      Err(ParseKIFGameError::new(
+        s.to_owned(),
+        "fail to parse game header (no colon delimiter?)".to_owned(),
+      ))
+    }
+  }
   pub fn parse_kif_game(
     &mut self,
     a: &Vec<String>,
@@ -216,49 +874,137 @@ impl KIFBuilder {
     let mut g = Game::default();
     let mut st = 0;
     let mut pos = Position::default();
+    let mut bod = Bod::default();
     let mut last_move = None;
+    //variations with indices of parent lines, 0 is the main line
+    let mut lines: Vec<(usize, Variation)> = Vec::new();
+    let mut stack = Vec::new();
+    let mut start_pos = pos.clone();
+    let mut target = (None, 0);
     for s in a {
       log::debug!("st = {}, process line {}", st, s);
       if st == 0 {
         if s == "手数----指手---------消費時間--" {
+          pos = start_position(&mut g, &bod, s)?;
+          start_pos = pos.clone();
           st += 1;
           continue;
         }
-        if let Some((key, value)) = parse_header(&s) {
-          if let Some(en) = self.jp_to_en(key) {
-            g.set_header(en.to_owned(), value.to_owned());
+        self.parse_header_line(&mut g, &mut bod, s)?;
+        continue;
+      }
+      if st != 0 && st != 2 {
+        if let Some(start) = parse_variation_header(s) {
+          //move numbers of mid-game diagrams don't start from 1
+          let start = match start.checked_sub(start_pos.move_no.saturating_sub(1) as usize) {
+            Some(start) => start,
+            None => {
+              return Err(ParseKIFGameError::new(
+                s.to_owned(),
+                "variation before the first move".to_owned(),
+              ))
+            }
+          };
+          if lines.is_empty() {
+            let main_line = Variation {
+              moves: g.moves.clone(),
+              ..Variation::default()
+            };
+            lines.push((0, main_line));
+            stack.push(0);
           }
-        } else {
-          return Err(ParseKIFGameError::new(
-            s.to_owned(),
-            "fail to parse game header (no colon delimiter?)".to_owned(),
-          ));
+          //variation branches from the latest line which has a move before it
+          while let Some(&k) = stack.last() {
+            if k != 0 && lines[k].1.start >= start {
+              stack.pop();
+            } else {
+              break;
+            }
+          }
+          let parent = *stack.last().unwrap();
+          let line = variation_line(&lines, parent);
+          if start > line.len() {
+            return Err(ParseKIFGameError::new(
+              s.to_owned(),
+              "variation doesn't branch from the parent line".to_owned(),
+            ));
+          }
+          pos = start_pos.clone();
+          for m in &line[..start] {
+            pos.do_move(m);
+          }
+          last_move = line[..start].last().cloned();
+          let v = Variation {
+            start,
+            ..Variation::default()
+          };
+          lines.push((parent, v));
+          stack.push(lines.len() - 1);
+          target = (Some(lines.len() - 1), start);
+          st = 4;
+          continue;
+        }
+      }
+      if st == 3 || st == 4 {
+        if let Some(a) = parse_annotation(s) {
+          add_annotation(&mut g, &mut lines, target, a);
+          continue;
         }
       }
       if st == 1 {
         if s == "*時間切れにて終局" {
-          g.out_of_time(pos.move_no);
-          break;
+          //kept as comment after the last move
+          g.out_of_time(pos.side);
+          target = (None, g.moves.len() + 1);
+          add_annotation(&mut g, &mut lines, target, parse_annotation(s).unwrap());
+          st = 3;
+          continue;
+        }
+        if let Some(a) = parse_annotation(s) {
+          add_annotation(&mut g, &mut lines, target, a);
+          continue;
         }
         if let Some(kif) = parse_move(s, pos.move_no) {
           if kif == "投了" {
-            g.resign(pos.move_no);
+            g.resign(pos.side);
             let mut allocator = PositionMovesAllocator::default();
             let _ = g.adjourn(&mut pos, &mut allocator);
-            break;
+            target = (None, g.moves.len() + 1);
+            st = 3;
+            continue;
           }
           if kif == "詰み" {
             //checkmate
-            g.loss(pos.move_no);
+            g.loss(pos.side);
             let mut allocator = PositionMovesAllocator::default();
             let _ = g.adjourn(&mut pos, &mut allocator);
-            break;
+            target = (None, g.moves.len() + 1);
+            st = 3;
+            continue;
+          }
+          if TERMINATIONS.contains(&kif) {
+            match kif {
+              "切れ負け" => g.out_of_time(pos.side),
+              "反則勝ち" => g.illegal_move(-pos.side),
+              "反則負け" => g.illegal_move(pos.side),
+              "入玉勝ち" => g.loss(-pos.side),
+              _ => (),
+            }
+            g.set_header(String::from("termination"), kif.to_owned());
+            target = (None, g.moves.len() + 1);
+            st = 3;
+            continue;
           }
           if let Some(m) = pos.parse_kif_move(&mut self.allocator, kif, last_move) {
             pos.do_move(&m);
             last_move = Some(m.clone());
             if pos.is_legal() {
               g.moves.push(m);
+              target = (None, g.moves.len());
+              match parse_move_time(s) {
+                Some(t) if g.times.len() + 1 == g.moves.len() => g.times.push(t),
+                _ => (),
+              }
             } else {
               st = 2;
             }
@@ -266,6 +1012,11 @@ impl KIFBuilder {
             last_move = None;
             st = 2;
           }
+        } else if let Some(n) = move_number(s) {
+          return Err(ParseKIFGameError::new(
+            s.to_owned(),
+            format!("expected move {}, got move {}", pos.move_no, n),
+          ));
         }
         //for checking illegal move comment
         continue;
@@ -278,10 +1029,222 @@ impl KIFBuilder {
             "expected illegal move message".to_owned(),
           ));
         }
-        g.illegal_move(pos.move_no - 1);
+        g.illegal_move(-pos.side);
+        st = 3;
+        continue;
+      }
+      //inside variation, result lines end it
+      if st == 4 {
+        if let Some(kif) = parse_move(s, pos.move_no) {
+          let v = &mut lines.last_mut().unwrap().1;
+          match pos.parse_kif_move(&mut self.allocator, kif, last_move.clone()) {
+            Some(m) => {
+              pos.do_move(&m);
+              if !pos.is_legal() {
+                return Err(ParseKIFGameError::new(
+                  s.to_owned(),
+                  "illegal move in variation".to_owned(),
+                ));
+              }
+              last_move = Some(m.clone());
+              v.moves.push(m);
+              target.1 = v.start + v.moves.len();
+              match parse_move_time(s) {
+                Some(t) if v.times.len() + 1 == v.moves.len() => v.times.push(t),
+                _ => (),
+              }
+            }
+            None => st = 3,
+          }
+        }
+      }
+    }
+    if st == 0 {
+      //diagram without moves
+      start_position(&mut g, &bod, "")?;
+    }
+    //times are kept only if every move has them
+    if g.times.len() != g.moves.len() {
+      g.times.clear();
+    }
+    for (_, v) in lines.iter_mut() {
+      if v.times.len() != v.moves.len() {
+        v.times.clear();
+      }
+    }
+    g.variations = variations_tree(&lines, 0);
+    Ok(g)
+  }
+}
+
+fn is_ki2_moves_line(s: &str) -> bool {
+  s.starts_with(['▲', '△', '☗', '☖'])
+}
+
+//"▲７六歩    △３四歩    ▲同　歩" to moves
+fn split_ki2_moves(s: &str) -> Vec<&str> {
+  let mut r = Vec::new();
+  let mut start = None;
+  for (i, c) in s.char_indices() {
+    if matches!(c, '▲' | '△' | '☗' | '☖') {
+      if let Some(k) = start {
+        r.push(s[k..i].trim());
+      }
+      start = Some(i);
+    }
+  }
+  if let Some(k) = start {
+    r.push(s[k..].trim());
+  }
+  r
+}
+
+//"64手で先手の勝ち", "31手で詰み", "120手で千日手" (after "まで")
+fn parse_ki2_result(g: &mut Game, pos: &mut Position, s: &str) {
+  let t = s.trim_start_matches(|c: char| c.is_ascii_digit());
+  let t = t.strip_prefix("手で").unwrap_or(t);
+  let named = if t.contains("先手") || t.contains("下手") {
+    1
+  } else if t.contains("後手") || t.contains("上手") {
+    -1
+  } else {
+    0
+  };
+  let mut allocator = PositionMovesAllocator::default();
+  if named != 0 && t.ends_with("勝ち") {
+    g.loss(-named);
+    let _ = g.adjourn(pos, &mut allocator);
+  } else if named != 0 && t.ends_with("負け") {
+    g.loss(named);
+    let _ = g.adjourn(pos, &mut allocator);
+  } else if t == "詰み" {
+    g.loss(pos.side);
+    let _ = g.adjourn(pos, &mut allocator);
+  } else if TERMINATIONS.contains(&t) {
+    g.set_header(String::from("termination"), t.to_owned());
+  }
+}
+
+const KI2_MOVES_PER_LINE: usize = 6;
+
+impl KIFBuilder {
+  //KI2 has the same header as KIF, variations aren't supported
+  pub fn parse_ki2_game(&mut self, a: &[String]) -> Result<Game, ParseKIFGameError> {
+    let mut g = Game::default();
+    let mut st = 0;
+    let mut pos = Position::default();
+    let mut bod = Bod::default();
+    let mut last_move = None;
+    for s in a {
+      log::debug!("st = {}, process line {}", st, s);
+      if s.is_empty() {
+        continue;
+      }
+      if st == 0 {
+        if !is_ki2_moves_line(s) {
+          self.parse_header_line(&mut g, &mut bod, s)?;
+          continue;
+        }
+        pos = start_position(&mut g, &bod, s)?;
+        st = 1;
+      }
+      if st == 1 {
+        if let Some(a) = parse_annotation(s) {
+          g.annotations.entry(g.moves.len()).or_default().push(a);
+          continue;
+        }
+        if let Some(t) = s.strip_prefix("まで") {
+          parse_ki2_result(&mut g, &mut pos, t);
+          st = 2;
+          continue;
+        }
+        if s.starts_with("変化：") {
+          break;
+        }
+        if !is_ki2_moves_line(s) {
+          return Err(ParseKIFGameError::new(
+            s.to_owned(),
+            "expected KI2 moves".to_owned(),
+          ));
+        }
+        for t in split_ki2_moves(s) {
+          match pos.parse_ki2_move(&mut self.allocator, t, &last_move) {
+            Some(m) => {
+              pos.do_move(&m);
+              last_move = Some(m.clone());
+              g.moves.push(m);
+            }
+            None => {
+              return Err(ParseKIFGameError::new(
+                s.to_owned(),
+                format!("illegal or ambiguous move {}", t),
+              ))
+            }
+          }
+        }
+        continue;
+      }
+      //after result line
+      if let Some(a) = parse_annotation(s) {
+        g.annotations.entry(g.moves.len() + 1).or_default().push(a);
+      } else if s.starts_with("変化：") {
         break;
       }
     }
+    if st == 0 {
+      //diagram without moves
+      start_position(&mut g, &bod, "")?;
+    }
     Ok(g)
   }
+  pub fn game_to_ki2(&self, game: &Game, start_pos: Option<&Position>) -> String {
+    let mut s = String::new();
+    self.push_headers(&mut s, game, start_pos);
+    let mut pos = match start_pos {
+      Some(pos) => pos.clone(),
+      None => game.start_position().unwrap_or_default(),
+    };
+    let mut allocator = PositionMovesAllocator::default();
+    let mut last_move = None;
+    let mut line = Vec::new();
+    for (i, m) in game.moves.iter().enumerate() {
+      let mut t = String::from(if pos.side > 0 { "▲" } else { "△" });
+      t.push_str(&pos.move_to_ki2(&mut allocator, m, &last_move));
+      line.push(t);
+      pos.do_move(m);
+      last_move = Some(m.clone());
+      if line.len() == KI2_MOVES_PER_LINE
+        || game.annotations.contains_key(&(i + 1))
+        || i + 1 == game.moves.len()
+      {
+        s.push_str(&line.join(" "));
+        s.push('\n');
+        line.clear();
+        push_annotations(&mut s, &game.annotations, i + 1);
+      }
+    }
+    let n = game.moves.len();
+    let result = match game.result() {
+      GameResult::BlackWon => Some("先手の勝ち"),
+      GameResult::WhiteWon => Some("後手の勝ち"),
+      GameResult::Unknown => {
+        if game.header.contains_key("checkmate") {
+          Some("詰み")
+        } else {
+          game
+            .header
+            .get("termination")
+            .map(|t| t.as_str())
+            .filter(|t| TERMINATIONS.contains(t))
+        }
+      }
+    };
+    if let Some(t) = result {
+      s.push_str(&format!("まで{}手で{}\n", n, t));
+    }
+    for (&k, _) in game.annotations.range(n + 1..) {
+      push_annotations(&mut s, &game.annotations, k);
+    }
+    s
+  }
 }
@@ -167,6 +167,21 @@ impl Move {
       s
     }
   }
+  //"7g7f", "8h2b+", "P*5e"
+  pub fn to_usi(&self) -> String {
+    let mut s = String::with_capacity(5);
+    if self.is_drop() {
+      s.push_str(&piece::to_string(self.to_piece.abs(), true));
+      s.push('*');
+    } else {
+      cell::push_cell_as_en_str(&mut s, self.from, false);
+    }
+    cell::push_cell_as_en_str(&mut s, self.to, false);
+    if self.is_promotion() {
+      s.push('+');
+    }
+    s
+  }
   pub fn packed_take_move(&self, taken_piece: i8) -> u32 {
     u32::from(self) + ((taken_piece.abs() as u32) << 26)
   }
@@ -258,7 +273,8 @@ impl FromStr for Move {
         }
         //after piece
         2 => {
-          if c == '*' {
+          //PSN drops are written with apostrophe, asterisk is accepted too
+          if c == '\'' || c == '*' {
             drop = true;
             st = 5;
           } else if c.is_ascii_digit() {
@@ -0,0 +1,349 @@
+//256-bit packed SFEN (PackedSfen layout of YaneuraOu and learning data tools):
+//side to move (1 bit), black and white king squares (7 bits each),
+//Huffman codes of the board pieces except kings in square order (1a, 1b, ..., 9i)
+//followed by promotion (not for gold) and color bits, then hand pieces of black and white.
+//Bits are written from the least significant bit of the first byte.
+//Every piece besides kings has to be on the board or in hand, otherwise zero padding would be read
+//as pawns in hand, so missing pieces are packed in the hand of the side which isn't to move
+//(gote in tsume, where rest pieces are in gote hand).
+//Positions without a king (tsume) need one more bit for its empty square, so the missing king
+//square is 81 + the next bit of the stream (extension of the layout, 81 is "no king" of YaneuraOu).
+use std::io::{Read, Write};
+
+use super::{cell, piece, Position};
+
+pub const PACKED_SFEN_SIZE: usize = 32;
+pub const PAYLOAD_SIZE: usize = 8;
+
+const BITS: usize = 8 * PACKED_SFEN_SIZE;
+const NO_KING: u32 = 81;
+
+//code and length of the board piece by unpromoted piece type, hand codes are shorter by one bit
+const HUFFMAN: [(u32, usize); 8] = [
+  (0x00, 1),
+  (0x01, 2),
+  (0x03, 4),
+  (0x0b, 4),
+  (0x07, 4),
+  (0x0f, 5),
+  (0x1f, 6),
+  (0x3f, 6),
+];
+
+//hand pieces order
+const HAND_PIECES: [i8; 7] = [
+  piece::PAWN,
+  piece::LANCE,
+  piece::KNIGHT,
+  piece::SILVER,
+  piece::BISHOP,
+  piece::ROOK,
+  piece::GOLD,
+];
+
+//square index of the packed SFEN (file-major) to cell
+fn square_to_cell(sq: usize) -> usize {
+  9 * (sq % 9) + sq / 9
+}
+
+fn cell_to_square(cell: usize) -> u32 {
+  let (row, col) = cell::unpack(cell);
+  (9 * col + row) as u32
+}
+
+#[derive(Default)]
+struct BitWriter {
+  bits: Vec<bool>,
+}
+
+impl BitWriter {
+  fn write(&mut self, value: u32, bits: usize) {
+    for i in 0..bits {
+      self.bits.push(value & (1 << i) != 0);
+    }
+  }
+  //board piece (NONE for empty cell) or hand piece
+  fn write_piece(&mut self, p: i8, hand: bool) {
+    let t = piece::unpromote(p).abs();
+    let (code, bits) = HUFFMAN[t as usize];
+    if hand {
+      self.write(code >> 1, bits - 1);
+    } else {
+      self.write(code, bits);
+    }
+    if p == piece::NONE {
+      return;
+    }
+    if t != piece::GOLD {
+      self.write((p.abs() >= piece::PROMOTED) as u32, 1);
+    }
+    self.write((p < 0) as u32, 1);
+  }
+}
+
+struct BitReader<'a> {
+  data: &'a [u8; PACKED_SFEN_SIZE],
+  cursor: usize,
+  //bits of missing king squares which go before the data at cursor
+  pending: Vec<bool>,
+}
+
+impl BitReader<'_> {
+  fn is_empty(&self) -> bool {
+    self.pending.is_empty() && self.cursor >= BITS
+  }
+  fn read(&mut self, bits: usize) -> Result<u32, String> {
+    let mut r = 0;
+    for i in 0..bits {
+      let b = if !self.pending.is_empty() {
+        self.pending.remove(0)
+      } else if self.cursor < BITS {
+        self.cursor += 1;
+        self.data[(self.cursor - 1) / 8] & (1 << ((self.cursor - 1) & 7)) != 0
+      } else {
+        return Err(String::from("unexpected end of data"));
+      };
+      if b {
+        r |= 1 << i;
+      }
+    }
+    Ok(r)
+  }
+  fn read_piece(&mut self, hand: bool) -> Result<i8, String> {
+    let (mut code, mut bits) = (0, 0);
+    let t = loop {
+      code |= self.read(1)? << bits;
+      bits += 1;
+      //there are no empty cells in hand
+      let t = (hand as usize..HUFFMAN.len()).find(|&t| {
+        let (c, b) = HUFFMAN[t];
+        if hand {
+          (c >> 1, b - 1) == (code, bits)
+        } else {
+          (c, b) == (code, bits)
+        }
+      });
+      if let Some(t) = t {
+        break t as i8;
+      }
+      if bits >= 6 {
+        return Err(format!("invalid piece code {:#x}", code));
+      }
+    };
+    if t == piece::NONE {
+      return Ok(piece::NONE);
+    }
+    let promoted = t != piece::GOLD && self.read(1)? != 0;
+    if promoted && hand {
+      return Err(String::from("promoted piece in hand"));
+    }
+    let p = if promoted { piece::promote(t) } else { t };
+    Ok(if self.read(1)? != 0 { -p } else { p })
+  }
+}
+
+impl Position {
+  fn with_rest_pieces_in_defender_hand(&self) -> Position {
+    let (black_pieces, white_pieces) = super::board::count_pieces(&self.board);
+    let mut pockets = [self.black_pockets, self.white_pockets];
+    let defender = (self.side > 0) as usize;
+    for p in piece::PAWN..piece::KING {
+      let i = p as usize;
+      let used = black_pieces[i]
+        + white_pieces[i]
+        + self.black_pockets[i] as u32
+        + self.white_pockets[i] as u32;
+      pockets[defender][i] += piece::expected_number_of_pieces(p).saturating_sub(used) as u8;
+    }
+    Position::from_board(self.board, pockets[0], pockets[1], self.side, self.move_no)
+  }
+  pub fn to_packed_sfen(&self) -> Result<[u8; PACKED_SFEN_SIZE], String> {
+    self.with_rest_pieces_in_defender_hand().pack()
+  }
+  fn pack(&self) -> Result<[u8; PACKED_SFEN_SIZE], String> {
+    let mut body = BitWriter::default();
+    for sq in 0..81 {
+      let p = self.board[square_to_cell(sq)];
+      if p.abs() != piece::KING {
+        body.write_piece(p, false);
+      }
+    }
+    for side in [1i8, -1] {
+      let pockets = self.pockets(side);
+      for &p in &HAND_PIECES {
+        for _ in 0..pockets[p as usize] {
+          body.write_piece(p * side, true);
+        }
+      }
+    }
+    let mut w = BitWriter::default();
+    w.write((self.side < 0) as u32, 1);
+    let mut body = body.bits.into_iter().peekable();
+    for side in [1, -1] {
+      match self.king_position(side) {
+        Some(cell) => w.write(cell_to_square(cell), 7),
+        None => w.write(NO_KING + body.next().unwrap_or(false) as u32, 7),
+      }
+    }
+    w.bits.extend(body);
+    if w.bits.len() > BITS {
+      return Err(format!("too many pieces ({} bits)", w.bits.len()));
+    } else if w.bits.len() < BITS {
+      return Err(format!("{} bits are left", BITS - w.bits.len()));
+    }
+    let mut data = [0; PACKED_SFEN_SIZE];
+    for (i, &b) in w.bits.iter().enumerate() {
+      if b {
+        data[i / 8] |= 1 << (i & 7);
+      }
+    }
+    Ok(data)
+  }
+  //move number isn't packed
+  pub fn from_packed_sfen(data: &[u8; PACKED_SFEN_SIZE]) -> Result<Self, String> {
+    let mut r = BitReader {
+      data,
+      cursor: 0,
+      pending: Vec::new(),
+    };
+    let side = if r.read(1)? == 0 { 1 } else { -1 };
+    let mut board = [piece::NONE; 81];
+    let mut pending = Vec::new();
+    for king in [piece::KING, piece::WHITE_KING] {
+      let sq = r.read(7)?;
+      if sq > NO_KING + 1 {
+        return Err(format!("invalid king square {}", sq));
+      } else if sq >= NO_KING {
+        pending.push(sq > NO_KING);
+      } else {
+        let cell = square_to_cell(sq as usize);
+        if board[cell] != piece::NONE {
+          return Err(String::from("kings on the same square"));
+        }
+        board[cell] = king;
+      }
+    }
+    r.pending = pending;
+    for sq in 0..81 {
+      let cell = square_to_cell(sq);
+      if board[cell] == piece::NONE {
+        board[cell] = r.read_piece(false)?;
+      }
+    }
+    let mut pockets = [[0u8; 8]; 2];
+    while !r.is_empty() {
+      let p = r.read_piece(true)?;
+      pockets[(p < 0) as usize][p.unsigned_abs() as usize] += 1;
+    }
+    Ok(Position::from_board(board, pockets[0], pockets[1], side, 1))
+  }
+}
+
+//per-record data after the packed SFEN: best move (packed u32, 0 if unknown),
+//mate length (0 if unknown) and move number, little-endian
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Payload {
+  pub best_move: u32,
+  pub dm: u16,
+  pub move_no: u16,
+}
+
+//mate length of the record which search was aborted (mate labelled datasets)
+pub const DM_ABORTED: u16 = u16::MAX;
+
+impl Payload {
+  fn to_bytes(self) -> [u8; PAYLOAD_SIZE] {
+    let mut r = [0; PAYLOAD_SIZE];
+    r[..4].copy_from_slice(&self.best_move.to_le_bytes());
+    r[4..6].copy_from_slice(&self.dm.to_le_bytes());
+    r[6..].copy_from_slice(&self.move_no.to_le_bytes());
+    r
+  }
+  fn from_bytes(a: &[u8; PAYLOAD_SIZE]) -> Self {
+    Self {
+      best_move: u32::from_le_bytes([a[0], a[1], a[2], a[3]]),
+      dm: u16::from_le_bytes([a[4], a[5]]),
+      move_no: u16::from_le_bytes([a[6], a[7]]),
+    }
+  }
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+  std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+//fixed size records, a record which can't be decoded is returned as InvalidData error
+//and reading could be continued from the next one
+pub struct PackedSFENReader<R: Read> {
+  reader: R,
+  payload: bool,
+}
+
+impl<R: Read> PackedSFENReader<R> {
+  pub fn new(reader: R, payload: bool) -> Self {
+    Self { reader, payload }
+  }
+}
+
+impl<R: Read> Iterator for PackedSFENReader<R> {
+  type Item = std::io::Result<(Position, Option<Payload>)>;
+  fn next(&mut self) -> Option<Self::Item> {
+    let mut data = [0; PACKED_SFEN_SIZE];
+    match self.reader.read(&mut data[..1]) {
+      Ok(0) => return None,
+      Ok(_) => (),
+      Err(err) => return Some(Err(err)),
+    }
+    if let Err(err) = self.reader.read_exact(&mut data[1..]) {
+      return Some(Err(err));
+    }
+    let payload = if self.payload {
+      let mut a = [0; PAYLOAD_SIZE];
+      if let Err(err) = self.reader.read_exact(&mut a) {
+        return Some(Err(err));
+      }
+      Some(Payload::from_bytes(&a))
+    } else {
+      None
+    };
+    Some(
+      Position::from_packed_sfen(&data)
+        .map(|mut pos| {
+          if let Some(p) = payload.filter(|p| p.move_no > 0) {
+            pos.move_no = p.move_no as u32;
+          }
+          (pos, payload)
+        })
+        .map_err(invalid_data),
+    )
+  }
+}
+
+pub struct PackedSFENWriter<W: Write> {
+  writer: W,
+  payload: bool,
+}
+
+impl<W: Write> PackedSFENWriter<W> {
+  pub fn new(writer: W, payload: bool) -> Self {
+    Self { writer, payload }
+  }
+  //payload is ignored by writer without payloads, missing payload is written as zeros
+  pub fn write(&mut self, pos: &Position, payload: Option<&Payload>) -> std::io::Result<()> {
+    let data = pos.to_packed_sfen().map_err(|msg| {
+      std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{} in {}", msg, pos),
+      )
+    })?;
+    self.writer.write_all(&data)?;
+    if self.payload {
+      let p = payload.copied().unwrap_or_default();
+      self.writer.write_all(&p.to_bytes())?;
+    }
+    Ok(())
+  }
+  pub fn flush(&mut self) -> std::io::Result<()> {
+    self.writer.flush()
+  }
+}
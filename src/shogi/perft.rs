@@ -0,0 +1,157 @@
+use super::alloc::PositionMovesAllocator;
+use super::moves::{Move, PSNMove, UndoMove};
+use super::Position;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::AddAssign;
+
+//leaf nodes, split by the kind of the last move
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct PerftCounts {
+  pub nodes: u64,
+  pub moves: u64,
+  pub drops: u64,
+  pub captures: u64,
+  pub promotions: u64,
+  pub checks: u64,
+}
+
+impl AddAssign for PerftCounts {
+  fn add_assign(&mut self, o: Self) {
+    self.nodes += o.nodes;
+    self.moves += o.moves;
+    self.drops += o.drops;
+    self.captures += o.captures;
+    self.promotions += o.promotions;
+    self.checks += o.checks;
+  }
+}
+
+impl fmt::Display for PerftCounts {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "nodes {}, moves {}, drops {}, captures {}, promotions {}, checks {}",
+      self.nodes, self.moves, self.drops, self.captures, self.promotions, self.checks
+    )
+  }
+}
+
+pub fn legal_moves(pos: &mut Position, allocator: &mut PositionMovesAllocator) -> Vec<Move> {
+  let checks = pos.compute_checks();
+  let moves = pos.compute_moves(&checks);
+  let drops = pos.compute_drops(allocator, &checks);
+  let mut r = Vec::with_capacity(moves.len() + drops.len());
+  for m in moves.into_iter().chain(drops) {
+    let u = pos.do_move(&m);
+    if pos.is_legal() {
+      r.push(m.clone());
+    }
+    pos.undo_move(&m, &u);
+  }
+  r
+}
+
+//pos after the move m
+fn leaf(pos: &Position, m: &Move, u: &UndoMove) -> PerftCounts {
+  PerftCounts {
+    nodes: 1,
+    moves: !m.is_drop() as u64,
+    drops: m.is_drop() as u64,
+    captures: u.is_take() as u64,
+    promotions: m.is_promotion() as u64,
+    checks: pos.is_check() as u64,
+  }
+}
+
+fn rec(pos: &mut Position, allocator: &mut PositionMovesAllocator, depth: usize) -> PerftCounts {
+  let mut r = PerftCounts::default();
+  if depth == 0 {
+    r.nodes = 1;
+    return r;
+  }
+  for m in legal_moves(pos, allocator) {
+    let u = pos.do_move(&m);
+    if depth == 1 {
+      r += leaf(pos, &m, &u);
+    } else {
+      r += rec(pos, allocator, depth - 1);
+    }
+    pos.undo_move(&m, &u);
+  }
+  r
+}
+
+pub fn perft(pos: &mut Position, depth: usize) -> PerftCounts {
+  let mut allocator = PositionMovesAllocator::default();
+  rec(pos, &mut allocator, depth)
+}
+
+//counts for every legal move in the root position (depth >= 1)
+pub fn divide(pos: &mut Position, depth: usize) -> Vec<(String, PerftCounts)> {
+  assert!(depth > 0);
+  let mut allocator = PositionMovesAllocator::default();
+  let mut r = Vec::new();
+  for m in legal_moves(pos, &mut allocator) {
+    let s = PSNMove::new(pos, &m).to_string();
+    let u = pos.do_move(&m);
+    let c = if depth == 1 {
+      leaf(pos, &m, &u)
+    } else {
+      rec(pos, &mut allocator, depth - 1)
+    };
+    pos.undo_move(&m, &u);
+    r.push((s, c));
+  }
+  r
+}
+
+//expected file lines: "<move> <nodes>" (divide output), empty lines and '#' comments are skipped
+pub fn parse_expected(s: &str) -> Result<HashMap<String, u64>, String> {
+  let mut r = HashMap::new();
+  for (i, line) in s.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let mut it = line.split_whitespace();
+    let (m, n) = match (it.next(), it.next(), it.next()) {
+      (Some(m), Some(n), None) => (m, n),
+      _ => {
+        return Err(format!(
+          "line {}: expected \"<move> <nodes>\", got \"{}\"",
+          i + 1,
+          line
+        ))
+      }
+    };
+    let n = n
+      .parse::<u64>()
+      .map_err(|err| format!("line {}: invalid number of nodes \"{}\", {}", i + 1, n, err))?;
+    if r.insert(m.to_owned(), n).is_some() {
+      return Err(format!("line {}: duplicated move {}", i + 1, m));
+    }
+  }
+  Ok(r)
+}
+
+//mismatches between divide output and expected counts
+pub fn compare(divide: &[(String, PerftCounts)], expected: &HashMap<String, u64>) -> Vec<String> {
+  let mut r = Vec::new();
+  for (m, c) in divide {
+    match expected.get(m) {
+      Some(&n) if n != c.nodes => r.push(format!("{}: expected {}, got {}", m, n, c.nodes)),
+      Some(_) => (),
+      None => r.push(format!("{}: unexpected move ({} nodes)", m, c.nodes)),
+    }
+  }
+  let mut missing: Vec<_> = expected
+    .iter()
+    .filter(|(m, _)| !divide.iter().any(|(s, _)| s == *m))
+    .collect();
+  missing.sort();
+  for (m, n) in missing {
+    r.push(format!("{}: missing move ({} nodes expected)", m, n));
+  }
+  r
+}
@@ -5,8 +5,8 @@ use std::io::BufReader;
 
 use super::{
   alloc::PositionMovesAllocator,
-  game::Game,
-  //moves::Move,
+  game::{Annotation, Annotations, Game, GameResult, Variation},
+  moves::Move,
   Position,
 };
 
@@ -66,89 +66,409 @@ impl ParsePGNGameError {
   }
 }
 
-pub fn parse_pgn_game(a: &Vec<String>) -> std::result::Result<Game, ParsePGNGameError> {
-  let mut g = Game::default();
-  let mut st = 0;
-  let mut pos = Position::default();
-  let mut allocator = PositionMovesAllocator::default();
-  let mut game_result = String::new();
-  for s in a {
-    log::debug!("st = {}, process line {}", st, s);
-    if st == 0 {
-      //parse headers
-      if s.is_empty() {
-        game_result = g.result().to_string();
-        st += 1;
-        log::debug!("Game headers: {:?}", g.header);
-      } else {
-        match super::psn::parse_header(s) {
-          Some((key, value)) => {
-            if key == "result" {
-              if value == "1-0" {
-                g.set_header(key, "2".to_owned());
-              } else if value == "0-1" {
-                g.set_header(key, "3".to_owned());
-              }
-            } else if key == "p1" {
-              g.set_header("sente".to_owned(), value);
-            } else if key == "p2" {
-              g.set_header("gote".to_owned(), value);
-            } else if key == "p1elo" {
-              g.set_header("senteelo".to_owned(), value);
-            } else if key == "p2elo" {
-              g.set_header("goteelo".to_owned(), value);
-            } else {
-              g.set_header(key, value);
+//movetext token, move numbers are dropped
+#[derive(Debug, PartialEq)]
+enum Token {
+  Move(String),
+  Comment(String),
+  //numeric annotation glyph "$n"
+  Nag(u8),
+  VariationStart,
+  VariationEnd,
+  Result(String),
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+//"{...}" comments could span lines, ";" comments last until the end of line, "%" lines are escaped
+fn tokens(lines: &[String]) -> Result<Vec<Token>, ParsePGNGameError> {
+  let mut r = Vec::new();
+  let mut comment: Option<String> = None;
+  for line in lines {
+    if comment.is_none() && line.starts_with('%') {
+      continue;
+    }
+    let mut it = line.chars().peekable();
+    if let Some(t) = comment.as_mut() {
+      t.push(' ');
+    }
+    while let Some(c) = it.next() {
+      if let Some(t) = comment.as_mut() {
+        if c == '}' {
+          r.push(Token::Comment(t.trim().to_owned()));
+          comment = None;
+        } else {
+          t.push(c);
+        }
+        continue;
+      }
+      match c {
+        '{' => comment = Some(String::new()),
+        ';' => {
+          r.push(Token::Comment(
+            it.by_ref().collect::<String>().trim().to_owned(),
+          ));
+        }
+        '(' => r.push(Token::VariationStart),
+        ')' => r.push(Token::VariationEnd),
+        _ if c.is_whitespace() => (),
+        _ => {
+          let mut t = String::from(c);
+          while let Some(&c) = it.peek() {
+            if c.is_whitespace() || "{};()$".contains(c) {
+              break;
             }
-            continue;
+            t.push(c);
+            it.next();
           }
-          None => {
-            return Err(ParsePGNGameError::new(
-              s.clone(),
-              String::from("parsing header"),
-            ))
+          if let Some(n) = t.strip_prefix('$') {
+            let n = n.parse().map_err(|_| {
+              ParsePGNGameError::new(t.clone(), String::from("invalid numeric annotation glyph"))
+            })?;
+            r.push(Token::Nag(n));
+          } else if RESULTS.contains(&t.as_str()) {
+            r.push(Token::Result(t));
+          } else {
+            //move number could be glued to the move ("12.d1d2")
+            let m = t.trim_start_matches(|c: char| c.is_ascii_digit());
+            let m = if m.len() < t.len() && m.starts_with('.') {
+              m.trim_start_matches('.')
+            } else {
+              &t
+            };
+            if !m.is_empty() {
+              r.push(Token::Move(m.to_owned()));
+            }
           }
         }
       }
     }
-    if st == 1 {
-      //TODO: s.split_whitespace()
-      for t in s.split_whitespace() {
-        if t.ends_with('.') {
-          //skip move numbers
-          continue;
-        }
-        if t == game_result.as_str() {
-          //skip result
-          continue;
-        }
-        let m = pos.parse_pgn_move(&t);
-        if m.is_none() {
-          return Err(ParsePGNGameError::new(
-            t.to_owned(),
+  }
+  if comment.is_some() {
+    return Err(ParsePGNGameError::new(
+      String::default(),
+      String::from("unterminated comment"),
+    ));
+  }
+  Ok(r)
+}
+
+//line being parsed: position after its last move and position before it (start of variations)
+struct Line {
+  v: Variation,
+  pos: Position,
+  prev: Option<Position>,
+}
+
+//main line and its variations as variation starting from the first move
+fn parse_movetext(
+  tokens: Vec<Token>,
+  pos: &Position,
+) -> Result<(Variation, Position, Option<String>), ParsePGNGameError> {
+  let mut stack = vec![Line {
+    v: Variation::default(),
+    pos: pos.clone(),
+    prev: None,
+  }];
+  let mut result = None;
+  for t in tokens {
+    if result.is_some() {
+      return Err(ParsePGNGameError::new(
+        format!("{:?}", t),
+        String::from("extra data after result"),
+      ));
+    }
+    let line = stack.last_mut().unwrap();
+    let moves_played = line.v.start + line.v.moves.len();
+    match t {
+      Token::Move(t) => {
+        let pos = &mut line.pos;
+        let m = pos.parse_pgn_move(&t).ok_or_else(|| {
+          ParsePGNGameError::new(
+            t.clone(),
             format!("fail to parse pgn move in position \"{}\"", pos),
-          ));
-        }
-        let m = m.unwrap();
+          )
+        })?;
         if !pos.validate_move(&m) {
           return Err(ParsePGNGameError::new(
-            t.to_owned(),
+            t,
             format!("illegal move in position \"{}\"", pos),
           ));
         }
-        let _ = pos.do_move(&m);
+        line.prev = Some(pos.clone());
+        pos.do_move(&m);
         if !pos.is_legal() {
           return Err(ParsePGNGameError::new(
-            t.to_owned(),
+            t,
             format!("king under check in position \"{}\"", pos),
           ));
         }
-        g.moves.push(m);
+        line.v.moves.push(m);
+      }
+      Token::Comment(t) => line
+        .v
+        .annotations
+        .entry(moves_played)
+        .or_default()
+        .push(Annotation::Comment(t)),
+      Token::Nag(n) => line
+        .v
+        .annotations
+        .entry(moves_played)
+        .or_default()
+        .push(Annotation::Nag(n)),
+      Token::VariationStart => {
+        //alternative to the last move
+        let pos = line.prev.clone().ok_or_else(|| {
+          ParsePGNGameError::new(String::from("("), String::from("variation before move"))
+        })?;
+        stack.push(Line {
+          v: Variation {
+            start: moves_played - 1,
+            ..Variation::default()
+          },
+          pos,
+          prev: None,
+        });
+      }
+      Token::VariationEnd => {
+        if stack.len() < 2 {
+          return Err(ParsePGNGameError::new(
+            String::from(")"),
+            String::from("unbalanced parentheses"),
+          ));
+        }
+        let line = stack.pop().unwrap();
+        stack.last_mut().unwrap().v.variations.push(line.v);
+      }
+      Token::Result(t) => {
+        if stack.len() > 1 {
+          return Err(ParsePGNGameError::new(
+            t,
+            String::from("result inside variation"),
+          ));
+        }
+        result = Some(t);
+      }
+    }
+  }
+  if stack.len() > 1 {
+    return Err(ParsePGNGameError::new(
+      String::from("("),
+      String::from("unbalanced parentheses"),
+    ));
+  }
+  let line = stack.pop().unwrap();
+  Ok((line.v, line.pos, result))
+}
+
+pub fn parse_pgn_game(a: &[String]) -> std::result::Result<Game, ParsePGNGameError> {
+  let mut g = Game::default();
+  let mut allocator = PositionMovesAllocator::default();
+  let mut pos = Position::default();
+  //index of the first movetext line
+  let mut movetext = a.len();
+  for (i, s) in a.iter().enumerate() {
+    log::debug!("process header line {}", s);
+    if s.is_empty() {
+      movetext = i + 1;
+      log::debug!("Game headers: {:?}", g.header);
+      break;
+    }
+    match super::psn::parse_header(s) {
+      Some((key, value)) => {
+        if super::psn::is_start_position_tag(&key) {
+          pos = Position::parse_sfen(&value)
+            .map_err(|err| ParsePGNGameError::new(s.clone(), err.to_string()))?;
+          g.set_header(String::from("sfen"), pos.to_string());
+        } else if key == "result" {
+          if let Some(r) = super::psn::result_header(&value) {
+            g.set_header(key, r);
+          }
+        } else if key == "p1" {
+          g.set_header("sente".to_owned(), value);
+        } else if key == "p2" {
+          g.set_header("gote".to_owned(), value);
+        } else if key == "p1elo" {
+          g.set_header("senteelo".to_owned(), value);
+        } else if key == "p2elo" {
+          g.set_header("goteelo".to_owned(), value);
+        } else {
+          g.set_header(key, value);
+        }
+      }
+      None => {
+        return Err(ParsePGNGameError::new(
+          s.clone(),
+          String::from("parsing header"),
+        ))
       }
     }
   }
+  let tokens = tokens(&a[movetext..])?;
+  let (v, mut pos, result) = parse_movetext(tokens, &pos)?;
+  g.moves = v.moves;
+  g.annotations = v.annotations;
+  g.variations = v.variations;
+  //result token is used if the tag is missing
+  if let Some(r) = result.as_deref().and_then(super::psn::result_header) {
+    if !g.header.contains_key("result") {
+      g.set_header(String::from("result"), r);
+    }
+  }
+  if super::psn::is_problem(&g) {
+    return Ok(g);
+  }
   let res = g.adjourn(&mut pos, &mut allocator);
   res
     .map(|_| g)
     .map_err(|s| ParsePGNGameError::new(String::default(), s))
 }
+
+//movetext lines are wrapped before this width
+const PGN_LINE_WIDTH: usize = 80;
+
+//chess-like numbering (one number for black and white moves)
+struct Numbering {
+  //full move number of the first move
+  first: usize,
+  //1 if the first move is white's
+  offset: usize,
+}
+
+impl Numbering {
+  //move numbers aren't separated from moves by line breaks,
+  //white moves are numbered ("1... b7b6") at the start of lines and after comments
+  fn token(&self, moves_played: usize, m: &Move, numbered: bool) -> String {
+    let k = moves_played + self.offset;
+    let n = self.first + k / 2;
+    if k & 1 == 0 {
+      format!("{}. {}", n, m.to_pgn())
+    } else if numbered {
+      format!("{}... {}", n, m.to_pgn())
+    } else {
+      m.to_pgn()
+    }
+  }
+}
+
+//NAGs and comments after moves_played moves, true if anything was pushed
+fn push_annotations(
+  tokens: &mut Vec<String>,
+  annotations: &Annotations,
+  moves_played: usize,
+) -> bool {
+  let a = match annotations.get(&moves_played) {
+    Some(a) => a,
+    None => return false,
+  };
+  let mut r = false;
+  for x in a {
+    if let Annotation::Nag(n) = x {
+      tokens.push(format!("${}", n));
+      r = true;
+    }
+  }
+  for x in a {
+    if let Annotation::Comment(t) = x {
+      //braces can't be escaped
+      tokens.push(format!("{{{}}}", t.replace('}', ")").replace('\n', " ")));
+      r = true;
+    }
+  }
+  r
+}
+
+//moves with annotations, variations of the move follow it in parentheses
+fn push_line(
+  tokens: &mut Vec<String>,
+  numbering: &Numbering,
+  pos: &Position,
+  start: usize,
+  moves: &[Move],
+  annotations: &Annotations,
+  variations: &[Variation],
+) {
+  let mut pos = pos.clone();
+  let mut numbered = true;
+  push_annotations(tokens, annotations, start);
+  for (i, m) in moves.iter().enumerate() {
+    let k = start + i;
+    tokens.push(numbering.token(k, m, numbered));
+    numbered = push_annotations(tokens, annotations, k + 1);
+    for v in variations
+      .iter()
+      .filter(|v| v.start == k && !v.moves.is_empty())
+    {
+      let mut t = Vec::new();
+      push_line(
+        &mut t,
+        numbering,
+        &pos,
+        v.start,
+        &v.moves,
+        &v.annotations,
+        &v.variations,
+      );
+      t[0].insert(0, '(');
+      t.last_mut().unwrap().push(')');
+      tokens.append(&mut t);
+      numbered = true;
+    }
+    pos.do_move(m);
+  }
+}
+
+//result token ends the movetext
+pub fn game_to_pgn(game: &Game, start_pos: Option<&Position>) -> String {
+  let mut s = String::new();
+  let first = ["event", "site", "date", "round", "sente", "gote", "result"];
+  let names = [
+    ("sente", "P1"),
+    ("gote", "P2"),
+    ("senteelo", "P1Elo"),
+    ("goteelo", "P2Elo"),
+  ];
+  let result = match game.result() {
+    GameResult::Unknown => String::from("*"),
+    r => r.to_string(),
+  };
+  for (name, value) in super::psn::tags(game, &first, &names) {
+    let value = if name == "Result" { &result } else { value };
+    super::psn::push_header(&mut s, &name, value);
+  }
+  let (pos, sfen) = super::psn::start_position(game, start_pos);
+  if let Some(sfen) = sfen {
+    super::psn::push_header(&mut s, "FEN", &sfen);
+  }
+  s.push('\n');
+  let numbering = Numbering {
+    first: pos.move_no.div_ceil(2).max(1) as usize,
+    offset: if pos.side < 0 { 1 } else { 0 },
+  };
+  let mut tokens = Vec::with_capacity(game.moves.len() + 1);
+  push_line(
+    &mut tokens,
+    &numbering,
+    &pos,
+    0,
+    &game.moves,
+    &game.annotations,
+    &game.variations,
+  );
+  tokens.push(result);
+  let mut width = 0;
+  for t in tokens {
+    if width > 0 && width + 1 + t.len() > PGN_LINE_WIDTH {
+      s.push('\n');
+      width = 0;
+    } else if width > 0 {
+      s.push(' ');
+      width += 1;
+    }
+    s.push_str(&t);
+    width += t.len();
+  }
+  s.push_str("\n\n");
+  s
+}
@@ -247,6 +247,27 @@ pub fn to_jp_string(abs_piece: i8) -> &'static str {
   }
 }
 
+//abs piece in KIF board diagrams, NONE for unknown characters
+pub fn from_jp_char(c: char) -> i8 {
+  match c {
+    '歩' => PAWN,
+    '香' => LANCE,
+    '桂' => KNIGHT,
+    '銀' => SILVER,
+    '金' => GOLD,
+    '角' => BISHOP,
+    '飛' => ROOK,
+    '玉' | '王' => KING,
+    'と' => PROMOTED_PAWN,
+    '杏' => PROMOTED_LANCE,
+    '圭' => PROMOTED_KNIGHT,
+    '全' => PROMOTED_SILVER,
+    '馬' => PROMOTED_BISHOP,
+    '龍' | '竜' => PROMOTED_ROOK,
+    _ => NONE,
+  }
+}
+
 const fn bit(p: i8) -> u32 {
   1 << p
 }
@@ -3,7 +3,12 @@ use std::io::prelude::*;
 use std::io::BufReader;
 use std::str::FromStr;
 
-use super::{alloc::PositionMovesAllocator, game::Game, moves::Move, Position};
+use super::{
+  alloc::PositionMovesAllocator,
+  game::{Game, GameResult},
+  moves::{Move, PSNMove},
+  Position,
+};
 
 #[test]
 fn test_parse_header() {
@@ -77,27 +82,40 @@ pub fn parse_header(s: &str) -> Option<(String, String)> {
   Some((key, value))
 }
 
+//games end with the result line or with the headers of the next game (problem collections)
 pub struct PSNFileIterator {
   reader: BufReader<File>,
+  next_header: Option<String>,
 }
 
 impl Iterator for PSNFileIterator {
   type Item = std::io::Result<Vec<String>>;
   fn next(&mut self) -> Option<Self::Item> {
-    let mut r = Vec::new();
+    let mut r: Vec<String> = self.next_header.take().into_iter().collect();
+    //moves or a blank line after headers, next headers start a new game
+    let mut body = false;
     loop {
       let mut s = String::new();
       match self.reader.read_line(&mut s) {
         Ok(sz) => {
           if sz == 0 {
             //EOF reached
-            break None;
+            if r.is_empty() {
+              break None;
+            }
+            break Some(Ok(r));
           }
           let s = s.trim();
-          if r.is_empty() && s.is_empty() {
-            //skip first blank lines
+          if s.is_empty() {
+            body = !r.is_empty();
             continue;
           }
+          if parse_header(s).is_none() {
+            body = true;
+          } else if body {
+            self.next_header = Some(String::from(s));
+            return Some(Ok(r));
+          }
           let last = last_line(s).is_some();
           r.push(String::from(s));
           if last {
@@ -114,7 +132,10 @@ impl PSNFileIterator {
   pub fn new(filename: &str) -> std::io::Result<Self> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
-    Ok(Self { reader })
+    Ok(Self {
+      reader,
+      next_header: None,
+    })
   }
 }
 
@@ -141,7 +162,17 @@ pub fn parse_psn_game(a: &Vec<String>) -> std::result::Result<Game, ParsePSNGame
       //parse headers
       match parse_header(s) {
         Some((key, value)) => {
-          g.set_header(key, value);
+          if is_start_position_tag(&key) {
+            pos = Position::parse_sfen(&value)
+              .map_err(|err| ParsePSNGameError::new(s.clone(), err.to_string()))?;
+            g.set_header(String::from("sfen"), pos.to_string());
+          } else if key == "result" {
+            if let Some(r) = result_header(&value) {
+              g.set_header(key, r);
+            }
+          } else {
+            g.set_header(key, value);
+          }
           continue;
         }
         None => st += 1,
@@ -177,7 +208,7 @@ pub fn parse_psn_game(a: &Vec<String>) -> std::result::Result<Game, ParsePSNGame
           }
         }
       } else if let Some(t) = last_line(s) {
-        g.set_header(String::from("text_result"), String::from(t));
+        g.set_header(String::from("termination"), String::from(t));
         st += 1;
       } else {
         return Err(ParsePSNGameError::new(
@@ -192,8 +223,127 @@ pub fn parse_psn_game(a: &Vec<String>) -> std::result::Result<Game, ParsePSNGame
       ));
     }
   }
+  if is_problem(&g) {
+    return Ok(g);
+  }
   let res = g.adjourn(&mut pos, &mut allocator);
   res
     .map(|_| g)
     .map_err(|s| ParsePSNGameError::new(String::default(), s))
 }
+
+//"result" header value of the reader (see Game::result) from the tag value ("1-0", "0-1")
+pub(super) fn result_header(s: &str) -> Option<String> {
+  match s {
+    "1-0" => Some(String::from("2")),
+    "0-1" => Some(String::from("3")),
+    _ => None,
+  }
+}
+
+//SFEN or FEN tag (the value is SFEN in both) sets the start position
+pub(super) fn is_start_position_tag(key: &str) -> bool {
+  key == "sfen" || key == "fen"
+}
+
+//games from board diagrams (problems, puzzle collections) could be without result
+pub(super) fn is_problem(game: &Game) -> bool {
+  game.header.contains_key("sfen") && matches!(game.result(), GameResult::Unknown)
+}
+
+//game headers which aren't written as tags
+const INTERNAL_HEADERS: [&str; 5] = ["result", "termination", "resignation", "checkmate", "sfen"];
+
+//"[Key \"value\"]" line, quotes in values are replaced since they can't be escaped
+pub(super) fn push_header(s: &mut String, key: &str, value: &str) {
+  s.push('[');
+  s.push_str(key);
+  s.push_str(" \"");
+  s.push_str(&value.replace('"', "'"));
+  s.push_str("\"]\n");
+}
+
+//tag names of the game headers (first letter is capitalized, names are case-insensitive on reading),
+//"first" keys go before others, keys which aren't alphanumeric (KIF, CSA headers) are skipped
+pub(super) fn tags<'a>(
+  game: &'a Game,
+  first: &[&'a str],
+  names: &[(&str, &str)],
+) -> Vec<(String, &'a str)> {
+  let mut keys: Vec<&str> = first
+    .iter()
+    .copied()
+    .filter(|k| game.header.contains_key(*k))
+    .collect();
+  for k in game.header.keys() {
+    if !keys.contains(&k.as_str())
+      && !INTERNAL_HEADERS.contains(&k.as_str())
+      && !k.is_empty()
+      && k.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+      keys.push(k);
+    }
+  }
+  keys
+    .into_iter()
+    .map(|k| {
+      let name = match names.iter().find(|(en, _)| *en == k) {
+        Some((_, name)) => name.to_string(),
+        None => k[..1].to_ascii_uppercase() + &k[1..],
+      };
+      (name, game.header.get(k).unwrap().as_str())
+    })
+    .collect()
+}
+
+//text of the last line ("--Resigns--")
+fn termination(game: &Game) -> &str {
+  if let Some(t) = game.header.get("termination") {
+    t
+  } else if game.header.contains_key("checkmate") {
+    "Checkmate"
+  } else if game.header.contains_key("resignation") {
+    "Resigns"
+  } else {
+    "Unknown"
+  }
+}
+
+//start position and SFEN of the tag (if the game doesn't start from the initial position)
+pub(super) fn start_position(
+  game: &Game,
+  start_pos: Option<&Position>,
+) -> (Position, Option<String>) {
+  match start_pos {
+    Some(pos) => (pos.clone(), Some(pos.to_string())),
+    None => (
+      game.start_position().unwrap_or_default(),
+      game.header.get("sfen").cloned(),
+    ),
+  }
+}
+
+//one move per line with the move number, result tag is written as in PGN ("1-0", "0-1", "*")
+pub fn game_to_psn(game: &Game, start_pos: Option<&Position>) -> String {
+  let mut s = String::new();
+  let first = ["event", "site", "date", "round", "sente", "gote", "result"];
+  let names = [("senteelo", "SenteElo"), ("goteelo", "GoteElo")];
+  let result = match game.result() {
+    GameResult::Unknown => String::from("*"),
+    r => r.to_string(),
+  };
+  for (name, value) in tags(game, &first, &names) {
+    let value = if name == "Result" { &result } else { value };
+    push_header(&mut s, &name, value);
+  }
+  let (mut pos, sfen) = start_position(game, start_pos);
+  if let Some(sfen) = sfen {
+    push_header(&mut s, "SFEN", &sfen);
+  }
+  for m in &game.moves {
+    s.push_str(&format!("{}.{}\n", pos.move_no, PSNMove::new(&pos, m)));
+    pos.do_move(m);
+  }
+  s.push_str(&format!("--{}--\n", termination(game)));
+  s
+}
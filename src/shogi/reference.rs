@@ -0,0 +1,249 @@
+//deliberately slow move generator working directly on board and pockets without bitboards,
+//masks and precomputed tables, used for differential testing of the fast generator
+use super::{moves::Move, piece, Position};
+
+const ORTHOGONAL: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAGONAL: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+//(row delta, col delta, sliding) for black piece, rows are counted from the white side
+fn steps(abs_piece: i8) -> Vec<(isize, isize, bool)> {
+  let gold = vec![
+    (-1, -1, false),
+    (-1, 0, false),
+    (-1, 1, false),
+    (0, -1, false),
+    (0, 1, false),
+    (1, 0, false),
+  ];
+  match abs_piece {
+    piece::PAWN => vec![(-1, 0, false)],
+    piece::LANCE => vec![(-1, 0, true)],
+    piece::KNIGHT => vec![(-2, -1, false), (-2, 1, false)],
+    piece::SILVER => vec![
+      (-1, -1, false),
+      (-1, 0, false),
+      (-1, 1, false),
+      (1, -1, false),
+      (1, 1, false),
+    ],
+    piece::GOLD
+    | piece::PROMOTED_PAWN
+    | piece::PROMOTED_LANCE
+    | piece::PROMOTED_KNIGHT
+    | piece::PROMOTED_SILVER => gold,
+    piece::BISHOP => DIAGONAL.iter().map(|&(r, c)| (r, c, true)).collect(),
+    piece::ROOK => ORTHOGONAL.iter().map(|&(r, c)| (r, c, true)).collect(),
+    piece::PROMOTED_BISHOP => DIAGONAL
+      .iter()
+      .map(|&(r, c)| (r, c, true))
+      .chain(ORTHOGONAL.iter().map(|&(r, c)| (r, c, false)))
+      .collect(),
+    piece::PROMOTED_ROOK => ORTHOGONAL
+      .iter()
+      .map(|&(r, c)| (r, c, true))
+      .chain(DIAGONAL.iter().map(|&(r, c)| (r, c, false)))
+      .collect(),
+    piece::KING => ORTHOGONAL
+      .iter()
+      .chain(DIAGONAL.iter())
+      .map(|&(r, c)| (r, c, false))
+      .collect(),
+    _ => panic!("unhandled piece {}", abs_piece),
+  }
+}
+
+fn in_promotion_zone(row: usize, side: i8) -> bool {
+  if side > 0 {
+    row < 3
+  } else {
+    row > 5
+  }
+}
+
+//pawns and lances on the last rank, knights on two last ranks have no moves
+fn is_dead_square(v: i8, row: usize) -> bool {
+  let r = if v > 0 { row } else { 8 - row };
+  match v.abs() {
+    piece::PAWN | piece::LANCE => r == 0,
+    piece::KNIGHT => r <= 1,
+    _ => false,
+  }
+}
+
+impl Position {
+  //cells reachable by the piece standing on the cell from
+  fn reference_targets(&self, from: usize) -> Vec<usize> {
+    let v = self.board[from];
+    let s = if v > 0 { 1 } else { -1 };
+    let mut r = Vec::new();
+    for (dr, dc, sliding) in steps(v.abs()) {
+      //white pieces move in opposite direction
+      let (dr, dc) = (dr * s as isize, dc * s as isize);
+      let (mut row, mut col) = ((from / 9) as isize, (from % 9) as isize);
+      loop {
+        row += dr;
+        col += dc;
+        if !(0..9).contains(&row) || !(0..9).contains(&col) {
+          break;
+        }
+        let to = (9 * row + col) as usize;
+        let w = self.board[to];
+        if w * s > 0 {
+          break;
+        }
+        r.push(to);
+        if w != piece::NONE || !sliding {
+          break;
+        }
+      }
+    }
+    r
+  }
+  //cells of side pieces attacking the cell
+  pub fn reference_attackers(&self, cell: usize, side: i8) -> Vec<usize> {
+    (0..81)
+      .filter(|&k| self.board[k] * side > 0 && self.reference_targets(k).contains(&cell))
+      .collect()
+  }
+  fn reference_king(&self, side: i8) -> Option<usize> {
+    (0..81).find(|&k| self.board[k] == side * piece::KING)
+  }
+  //cells of pieces checking the king of side to move
+  pub fn reference_checks(&self) -> Vec<usize> {
+    match self.reference_king(self.side) {
+      Some(k) => self.reference_attackers(k, -self.side),
+      None => Vec::new(),
+    }
+  }
+  //king of the side which has just moved isn't attacked
+  fn reference_is_legal(&self) -> bool {
+    match self.reference_king(-self.side) {
+      Some(k) => self.reference_attackers(k, self.side).is_empty(),
+      None => true,
+    }
+  }
+  //pseudo legal moves and drops (pawn drop mates aren't filtered as in the fast generator)
+  fn reference_pseudo_legal_moves(&self) -> Vec<Move> {
+    let s = self.side;
+    let mut r = Vec::new();
+    for from in 0..81 {
+      let v = self.board[from];
+      if v * s <= 0 {
+        continue;
+      }
+      for to in self.reference_targets(from) {
+        if piece::could_promoted(v)
+          && !piece::is_promoted(v)
+          && (in_promotion_zone(from / 9, s) || in_promotion_zone(to / 9, s))
+        {
+          r.push(Move {
+            from,
+            to,
+            from_piece: v,
+            to_piece: piece::promote(v),
+          });
+        }
+        if !is_dead_square(v, to / 9) {
+          r.push(Move {
+            from,
+            to,
+            from_piece: v,
+            to_piece: v,
+          });
+        }
+      }
+    }
+    let pockets = if s > 0 {
+      &self.black_pockets
+    } else {
+      &self.white_pockets
+    };
+    for p in piece::PAWN..piece::KING {
+      if pockets[p as usize] == 0 {
+        continue;
+      }
+      let v = s * p;
+      for to in 0..81 {
+        if self.board[to] != piece::NONE || is_dead_square(v, to / 9) {
+          continue;
+        }
+        if p == piece::PAWN && (0..9).any(|row| self.board[9 * row + to % 9] == v) {
+          continue;
+        }
+        r.push(Move {
+          from: 0x7f,
+          to,
+          from_piece: piece::NONE,
+          to_piece: v,
+        });
+      }
+    }
+    r
+  }
+  pub fn reference_legal_moves(&self) -> Vec<Move> {
+    self
+      .reference_pseudo_legal_moves()
+      .into_iter()
+      .filter(|m| {
+        let mut pos = self.clone();
+        pos.do_move(m);
+        pos.reference_is_legal()
+      })
+      .collect()
+  }
+  //legal moves after which the opponent king is in check
+  pub fn reference_checking_moves(&self) -> Vec<Move> {
+    self
+      .reference_legal_moves()
+      .into_iter()
+      .filter(|m| {
+        let mut pos = self.clone();
+        pos.do_move(m);
+        !pos.reference_checks().is_empty()
+      })
+      .collect()
+  }
+  //interposition on the cell is futile if whatever piece is dropped there the checking piece
+  //takes it (with or without promotion) and the king has no legal moves afterwards
+  fn reference_is_futile_drop_cell(&self, attacker: usize, cell: usize) -> bool {
+    self
+      .reference_legal_moves()
+      .into_iter()
+      .filter(|m| m.is_drop() && m.to == cell)
+      .all(|drop| {
+        let mut pos = self.clone();
+        pos.do_move(&drop);
+        pos
+          .reference_legal_moves()
+          .into_iter()
+          .filter(|m| m.from == attacker && m.to == cell)
+          .any(|take| {
+            let mut pos = pos.clone();
+            pos.do_move(&take);
+            let king = pos.reference_king(pos.side);
+            !pos
+              .reference_legal_moves()
+              .iter()
+              .any(|m| Some(m.from) == king)
+          })
+      })
+  }
+  //checkmate in tsume sense: no legal moves except futile interpositions
+  pub fn reference_is_checkmate(&self) -> bool {
+    let checks = self.reference_checks();
+    if checks.is_empty() {
+      return false;
+    }
+    let moves = self.reference_legal_moves();
+    if moves.iter().any(|m| !m.is_drop()) {
+      return false;
+    }
+    if moves.is_empty() {
+      return true;
+    }
+    checks.len() == 1
+      && moves
+        .iter()
+        .all(|m| self.reference_is_futile_drop_cell(checks[0], m.to))
+  }
+}
@@ -0,0 +1,125 @@
+use super::{cell, moves::Move, piece, Position};
+
+impl Position {
+  fn is_pawn_in_column(&self, col: usize, side: i8) -> bool {
+    (self.nifu_masks & (1u32 << (((1 + side as i32) << 3) + col as i32))) != 0
+  }
+  fn retro_taken_pieces(&self, to: usize, side: i8, allow_uncaptures: bool) -> Vec<i8> {
+    let mut r = vec![piece::NONE];
+    if !allow_uncaptures {
+      return r;
+    }
+    let pockets = if side > 0 {
+      &self.black_pockets
+    } else {
+      &self.white_pockets
+    };
+    for p in piece::PAWN..piece::KING {
+      if pockets[p as usize] == 0 {
+        continue;
+      }
+      let v = -side * p;
+      if piece::could_unpromoted(v, to)
+        && (p != piece::PAWN || !self.is_pawn_in_column(to % 9, -side))
+      {
+        r.push(v);
+      }
+      if piece::could_promoted(v) {
+        r.push(piece::promote(v));
+      }
+    }
+    r
+  }
+  fn retro_position(&self, m: &Move, taken_piece: i8) -> Position {
+    let mut board = self.board;
+    let mut black_pockets = self.black_pockets;
+    let mut white_pockets = self.white_pockets;
+    let s = -self.side;
+    let pockets = if s > 0 {
+      &mut black_pockets
+    } else {
+      &mut white_pockets
+    };
+    if m.is_drop() {
+      pockets[m.to_piece.unsigned_abs() as usize] += 1;
+    } else {
+      board[m.from] = m.from_piece;
+    }
+    if taken_piece != piece::NONE {
+      pockets[piece::unpromote(taken_piece).unsigned_abs() as usize] -= 1;
+    }
+    board[m.to] = taken_piece;
+    Position::from_board(
+      board,
+      black_pockets,
+      white_pockets,
+      s,
+      self.move_no.max(2) - 1,
+    )
+  }
+  //moves of the side which isn't to move with legal positions before them (retrograde analysis)
+  pub fn compute_retro_moves(&self, allow_uncaptures: bool) -> Vec<(Move, Position)> {
+    let s = -self.side;
+    let mut r = Vec::new();
+    for to in 0..81 {
+      let v = self.board[to];
+      if v * s <= 0 {
+        continue;
+      }
+      let mut moves = Vec::new();
+      //undrops
+      if !piece::is_promoted(v) && v.abs() != piece::KING && piece::could_unpromoted(v, to) {
+        moves.push(Move {
+          from: 0x7f,
+          to,
+          from_piece: piece::NONE,
+          to_piece: v,
+        });
+      }
+      let mut from_pieces = vec![v];
+      if piece::is_promoted(v) {
+        from_pieces.push(piece::unpromote(v));
+      }
+      for from_piece in from_pieces {
+        let promotion = from_piece != v;
+        //pawn promotion frees the column
+        if promotion && from_piece.abs() == piece::PAWN && self.is_pawn_in_column(to % 9, s) {
+          continue;
+        }
+        for from in 0..81 {
+          if self.board[from] != piece::NONE
+            || (self.attack_from(from, from_piece) & (1u128 << to)) == 0
+          {
+            continue;
+          }
+          if !piece::is_promoted(from_piece) && !piece::could_unpromoted(from_piece, from) {
+            continue;
+          }
+          if promotion && !cell::promotion_zone(from, s) && !cell::promotion_zone(to, s) {
+            continue;
+          }
+          moves.push(Move {
+            from,
+            to,
+            from_piece,
+            to_piece: v,
+          });
+        }
+      }
+      for m in moves {
+        let taken_pieces = if m.is_drop() {
+          vec![piece::NONE]
+        } else {
+          self.retro_taken_pieces(to, s, allow_uncaptures)
+        };
+        for t in taken_pieces {
+          let pos = self.retro_position(&m, t);
+          if pos.is_legal() {
+            r.push((m.clone(), pos));
+          }
+        }
+      }
+    }
+    r
+  }
+}
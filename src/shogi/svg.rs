@@ -0,0 +1,185 @@
+//SVG board diagram: 9x9 grid with Japanese coordinates, kanji pieces (gote pieces are rotated),
+//hands of both sides (side to move in bold), highlighted cells of the last move and mate squares
+use std::fmt::Write;
+
+use super::alloc::PositionMovesAllocator;
+use super::kif::{push_hand_as_jp_str, JP_COLS, JP_ROWS};
+use super::moves::Move;
+use super::{cell, piece, Position};
+
+const CELL: usize = 40;
+const BOARD_X: usize = 10;
+const BOARD_Y: usize = 60;
+const BOARD_SIZE: usize = 9 * CELL;
+const WIDTH: usize = BOARD_X + BOARD_SIZE + 30;
+const HEIGHT: usize = BOARD_Y + BOARD_SIZE + 40;
+const FONT: &str = "serif";
+
+const BOARD_COLOR: &str = "#f3d9a4";
+const LAST_MOVE_COLOR: &str = "#f7e07b";
+const MATE_COLOR: &str = "#f2a0a0";
+const PROMOTED_COLOR: &str = "#c00000";
+
+//top left corner of the cell (file 9 is on the left, rank 一 is on the top)
+fn cell_origin(cell: usize) -> (usize, usize) {
+  let (row, col) = cell::unpack(cell);
+  (BOARD_X + (8 - col) * CELL, BOARD_Y + row * CELL)
+}
+
+fn push_cell_rect(s: &mut String, cell: usize, color: &str) {
+  let (x, y) = cell_origin(cell);
+  writeln!(
+    s,
+    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+    x, y, CELL, CELL, color
+  )
+  .unwrap();
+}
+
+fn push_hand(s: &mut String, pos: &Position, side: i8) {
+  let mut t = String::from(if side > 0 {
+    "☗先手 持駒 "
+  } else {
+    "☖後手 持駒 "
+  });
+  push_hand_as_jp_str(&mut t, pos.pockets(side));
+  //gote hand is above the board on the left, sente hand is below on the right
+  let (x, y, anchor) = if side > 0 {
+    (BOARD_X + BOARD_SIZE, BOARD_Y + BOARD_SIZE + 28, "end")
+  } else {
+    (BOARD_X, 28, "start")
+  };
+  let weight = if pos.side == side { "bold" } else { "normal" };
+  writeln!(
+    s,
+    r#"<text x="{}" y="{}" font-size="18" font-weight="{}" text-anchor="{}">{}</text>"#,
+    x, y, weight, anchor, t
+  )
+  .unwrap();
+}
+
+//cells of the checkmated king and of pieces giving check, empty if the side to move isn't mated
+pub fn mate_squares(pos: &Position) -> Vec<usize> {
+  if !pos.is_check() {
+    return Vec::new();
+  }
+  let mut pos = pos.clone();
+  if pos.has_legal_move(&mut PositionMovesAllocator::default()) {
+    return Vec::new();
+  }
+  let mut r: Vec<usize> = pos.king_position(pos.side).into_iter().collect();
+  let checks = pos.compute_checks();
+  r.extend(checks.attacking_pieces);
+  r
+}
+
+//last move cells (destination only for drops) and marked cells are highlighted
+pub fn position_to_svg(pos: &Position, last_move: Option<&Move>, marks: &[usize]) -> String {
+  let mut s = String::new();
+  writeln!(
+    s,
+    r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="{}">"#,
+    FONT,
+    w = WIDTH,
+    h = HEIGHT
+  )
+  .unwrap();
+  writeln!(
+    s,
+    r##"<rect width="{}" height="{}" fill="#ffffff"/>"##,
+    WIDTH, HEIGHT
+  )
+  .unwrap();
+  writeln!(
+    s,
+    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+    BOARD_X, BOARD_Y, BOARD_SIZE, BOARD_SIZE, BOARD_COLOR
+  )
+  .unwrap();
+  if let Some(m) = last_move {
+    if !m.is_drop() {
+      push_cell_rect(&mut s, m.from, LAST_MOVE_COLOR);
+    }
+    push_cell_rect(&mut s, m.to, LAST_MOVE_COLOR);
+  }
+  for &c in marks {
+    push_cell_rect(&mut s, c, MATE_COLOR);
+  }
+  //grid, the outer border is thicker
+  for i in 0..=9 {
+    let width = if i == 0 || i == 9 { 2 } else { 1 };
+    let x = BOARD_X + i * CELL;
+    let y = BOARD_Y + i * CELL;
+    writeln!(
+      s,
+      r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="{}"/>"#,
+      x,
+      BOARD_Y,
+      x,
+      BOARD_Y + BOARD_SIZE,
+      width
+    )
+    .unwrap();
+    writeln!(
+      s,
+      r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="{}"/>"#,
+      BOARD_X,
+      y,
+      BOARD_X + BOARD_SIZE,
+      y,
+      width
+    )
+    .unwrap();
+  }
+  for (col, c) in JP_COLS.iter().enumerate() {
+    writeln!(
+      s,
+      r#"<text x="{}" y="{}" font-size="16" text-anchor="middle">{}</text>"#,
+      BOARD_X + (8 - col) * CELL + CELL / 2,
+      BOARD_Y - 8,
+      c
+    )
+    .unwrap();
+  }
+  for (row, c) in JP_ROWS.iter().enumerate() {
+    writeln!(
+      s,
+      r#"<text x="{}" y="{}" font-size="16" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+      BOARD_X + BOARD_SIZE + 15,
+      BOARD_Y + row * CELL + CELL / 2,
+      c
+    )
+    .unwrap();
+  }
+  for (c, &p) in pos.board.iter().enumerate() {
+    if p == piece::NONE {
+      continue;
+    }
+    let (x, y) = cell_origin(c);
+    let (cx, cy) = (x + CELL / 2, y + CELL / 2);
+    let color = if p.abs() >= piece::PROMOTED {
+      PROMOTED_COLOR
+    } else {
+      "black"
+    };
+    let rotate = if p < 0 {
+      format!(r#" transform="rotate(180 {} {})""#, cx, cy)
+    } else {
+      String::new()
+    };
+    writeln!(
+      s,
+      r#"<text x="{}" y="{}" font-size="30" fill="{}" text-anchor="middle" dominant-baseline="central"{}>{}</text>"#,
+      cx,
+      cy,
+      color,
+      rotate,
+      piece::to_jp_char(p.abs())
+    )
+    .unwrap();
+  }
+  push_hand(&mut s, pos, -1);
+  push_hand(&mut s, pos, 1);
+  s.push_str("</svg>\n");
+  s
+}
@@ -0,0 +1,96 @@
+//USI moves ("7g7f", "8h2b+", "P*5e") and "position" command strings
+use super::alloc::PositionMovesAllocator;
+use super::moves::Move;
+use super::{perft, ParseSFENError, Position};
+
+impl Position {
+  pub fn parse_usi_move(
+    &mut self,
+    allocator: &mut PositionMovesAllocator,
+    usi: &str,
+  ) -> Option<Move> {
+    perft::legal_moves(self, allocator)
+      .into_iter()
+      .find(|m| m.to_usi() == usi)
+  }
+}
+
+pub fn moves_to_usi(moves: &[Move]) -> String {
+  moves
+    .iter()
+    .map(|m| m.to_usi())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+//"position sfen <sfen> moves ..." or "position startpos moves ..." ("position" could be omitted)
+pub fn parse_usi_position(s: &str) -> Result<(Position, Vec<Move>), ParseSFENError> {
+  let t = s.trim();
+  let t = t.strip_prefix("position").unwrap_or(t);
+  let mut it = t.split_ascii_whitespace().peekable();
+  let mut pos = match it.next() {
+    Some("startpos") => Position::default(),
+    Some("sfen") => {
+      let mut a = Vec::with_capacity(4);
+      while let Some(&x) = it.peek() {
+        if x == "moves" {
+          break;
+        }
+        a.push(x);
+        it.next();
+      }
+      //move number is optional
+      if a.len() == 3 {
+        a.push("1");
+      }
+      Position::parse_sfen(&a.join(" "))?
+    }
+    _ => {
+      return Err(ParseSFENError::new(
+        s,
+        String::from("expected startpos or sfen"),
+      ))
+    }
+  };
+  let start = pos.clone();
+  let mut moves = Vec::new();
+  match it.next() {
+    None => return Ok((start, moves)),
+    Some("moves") => (),
+    Some(x) => {
+      return Err(ParseSFENError::new(
+        s,
+        format!("expected moves, got \"{}\"", x),
+      ))
+    }
+  }
+  let mut allocator = PositionMovesAllocator::default();
+  for usi in it {
+    match pos.parse_usi_move(&mut allocator, usi) {
+      Some(m) => {
+        pos.do_move(&m);
+        moves.push(m);
+      }
+      None => {
+        return Err(ParseSFENError::new(
+          s,
+          format!("illegal move \"{}\" after {} moves", usi, moves.len()),
+        ))
+      }
+    }
+  }
+  Ok((start, moves))
+}
+
+pub fn usi_position(pos: &Position, moves: &[Move]) -> String {
+  let mut s = if pos.to_string() == Position::default().to_string() {
+    String::from("position startpos")
+  } else {
+    format!("position sfen {}", pos)
+  };
+  if !moves.is_empty() {
+    s.push_str(" moves ");
+    s.push_str(&moves_to_usi(moves));
+  }
+  s
+}
@@ -0,0 +1,182 @@
+use super::{board, cell, piece, Position};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+  //col is file number (1..9)
+  Nifu {
+    side: i8,
+    col: usize,
+  },
+  DeadSquare {
+    piece: i8,
+    cell: usize,
+  },
+  TooManyPieces {
+    piece: i8,
+    count: u32,
+    expected: u32,
+  },
+  TooManyKings {
+    side: i8,
+  },
+  MissingKing {
+    side: i8,
+  },
+  //king of the side which isn't to move is under check
+  KingInCheck {
+    side: i8,
+  },
+  //every promotion takes a move of the side
+  TooManyPromotedPieces {
+    side: i8,
+    count: u32,
+    moves: u32,
+  },
+}
+
+impl Problem {
+  //problems which were always rejected by Position::parse_sfen()
+  pub fn is_fatal(&self) -> bool {
+    !matches!(
+      self,
+      Problem::MissingKing { .. } | Problem::TooManyPromotedPieces { .. }
+    )
+  }
+}
+
+impl fmt::Display for Problem {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *self {
+      Problem::Nifu { side, col } => write!(
+        f,
+        "more than one {} pawn in column {}",
+        piece::color(side),
+        col
+      ),
+      Problem::DeadSquare { piece, cell } => write!(
+        f,
+        "unpromoted {} {} at cell {}",
+        piece::color(piece),
+        piece::to_human_string(piece.abs()),
+        cell::to_string(cell)
+      ),
+      Problem::TooManyPieces {
+        piece,
+        count,
+        expected,
+      } => write!(
+        f,
+        "{} {}, expected number of theese pieces are {}",
+        count,
+        piece::to_human_string(piece),
+        expected
+      ),
+      Problem::TooManyKings { side } => write!(f, "too many {} kings", piece::color(side)),
+      Problem::MissingKing { side } => write!(f, "missing {} king", piece::color(side)),
+      Problem::KingInCheck { side } => {
+        write!(f, "king under check ({} isn't to move)", piece::color(side))
+      }
+      Problem::TooManyPromotedPieces { side, count, moves } => write!(
+        f,
+        "{} promoted {} pieces after {} moves",
+        count,
+        piece::color(side),
+        moves
+      ),
+    }
+  }
+}
+
+//problems joined by commas for error messages
+pub fn describe(problems: &[Problem]) -> String {
+  problems
+    .iter()
+    .map(|p| p.to_string())
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+//also used before the position construction (pockets hashes are limited by expected numbers)
+pub(super) fn too_many_pieces(
+  board: &[i8; 81],
+  black_pockets: &[u8; 8],
+  white_pockets: &[u8; 8],
+) -> Vec<Problem> {
+  let (black_pieces, white_pieces) = board::count_pieces(board);
+  let mut r = Vec::new();
+  for p in piece::PAWN..piece::KING {
+    let i = p as usize;
+    let count =
+      black_pieces[i] + white_pieces[i] + black_pockets[i] as u32 + white_pockets[i] as u32;
+    let expected = piece::expected_number_of_pieces(p);
+    if count > expected {
+      r.push(Problem::TooManyPieces {
+        piece: p,
+        count,
+        expected,
+      });
+    }
+  }
+  r
+}
+
+impl Position {
+  //problems which couldn't occur in a real game, defender is the side which isn't to move
+  pub fn validate(&self) -> Vec<Problem> {
+    let mut r = Vec::new();
+    for side in [1, -1] {
+      for col in 0..9 {
+        let pawns = (0..9)
+          .filter(|row| self.board[9 * row + col] == side * piece::PAWN)
+          .count();
+        if pawns > 1 {
+          r.push(Problem::Nifu { side, col: col + 1 });
+        }
+      }
+    }
+    for (k, &v) in self.board.iter().enumerate() {
+      if v != piece::NONE && !piece::is_promoted(v) && !piece::could_unpromoted(v, k) {
+        r.push(Problem::DeadSquare { piece: v, cell: k });
+      }
+    }
+    r.extend(too_many_pieces(
+      &self.board,
+      &self.black_pockets,
+      &self.white_pockets,
+    ));
+    let (black_pieces, white_pieces) = board::count_pieces(&self.board);
+    let k = piece::KING as usize;
+    for (side, kings) in [(1, black_pieces[k]), (-1, white_pieces[k])] {
+      if kings > 1 {
+        r.push(Problem::TooManyKings { side });
+      }
+    }
+    let defender_kings = if self.side > 0 {
+      white_pieces[k]
+    } else {
+      black_pieces[k]
+    };
+    if defender_kings == 0 {
+      r.push(Problem::MissingKing { side: -self.side });
+    }
+    if black_pieces[k] <= 1 && white_pieces[k] <= 1 && !self.is_legal() {
+      r.push(Problem::KingInCheck { side: -self.side });
+    }
+    //move number 1 is used for composed positions
+    if self.move_no > 1 {
+      let moves = self.move_no / 2;
+      for side in [1, -1] {
+        let count = self
+          .board
+          .iter()
+          .filter(|&&v| v * side > 0 && piece::is_promoted(v))
+          .count() as u32;
+        if count > moves {
+          r.push(Problem::TooManyPromotedPieces { side, count, moves });
+        }
+      }
+    }
+    r
+  }
+}
@@ -0,0 +1,257 @@
+use super::generator::Material;
+use super::search::Search;
+use super::shogi::{piece, Position};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 4] = b"TSTB";
+const VERSION: u8 = 1;
+//entry values: mate length (odd) with UNIQUE bit, NO_MATE within table depth or INVALID index
+const INVALID: u8 = 0;
+const UNIQUE: u8 = 0x80;
+const NO_MATE: u8 = 0xff;
+pub const MAX_PIECES: usize = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum Probe {
+  Mate(u8, bool),
+  NoMate,
+}
+
+//positions with sente to move: defender king alone on board, up to MAX_PIECES attacking pieces
+//not further than radius cells from it, fixed attacker hand, defender holds the rest pieces
+//index = ((king * slots[0] + slot[0]) * slots[1] + slot[1]) ...
+//slot = (promoted * window + window cell of piece)
+pub struct Tablebase {
+  pieces: Vec<i8>,
+  hand: [u8; 8],
+  white_pockets: [u8; 8],
+  radius: u8,
+  pub depth: u8,
+  slots: Vec<usize>,
+  values: Vec<u8>,
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+  std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+impl Tablebase {
+  pub fn new(pieces: &Material, hand: &Material, radius: u8, depth: u8) -> Result<Self, String> {
+    let n = pieces.len() as usize;
+    if n == 0 || n > MAX_PIECES {
+      return Err(format!(
+        "expected from 1 to {} attacking pieces, got {}",
+        MAX_PIECES, n
+      ));
+    }
+    if depth % 2 != 1 || depth >= UNIQUE {
+      return Err(format!("invalid tablebase depth {}", depth));
+    }
+    let mut white_pockets = [0u8; 8];
+    let mut v = Vec::with_capacity(n);
+    for p in (piece::PAWN..piece::KING).rev() {
+      let i = p as usize;
+      let t = pieces.0[i] as u32 + hand.0[i] as u32;
+      let e = piece::expected_number_of_pieces(p);
+      if t > e {
+        return Err(format!(
+          "{} {}s in tablebase material, expected number of theese pieces are {}",
+          t,
+          piece::to_human_string(p),
+          e
+        ));
+      }
+      white_pockets[i] = (e - t) as u8;
+      for _ in 0..pieces.0[i] {
+        v.push(p);
+      }
+    }
+    let w = 2 * radius as usize + 1;
+    let slots: Vec<usize> = v
+      .iter()
+      .map(|&p| {
+        if piece::could_promoted(p) {
+          2 * w * w
+        } else {
+          w * w
+        }
+      })
+      .collect();
+    let len = slots.iter().product::<usize>() * 81;
+    Ok(Self {
+      pieces: v,
+      hand: hand.0,
+      white_pockets,
+      radius,
+      depth,
+      slots,
+      values: vec![INVALID; len],
+    })
+  }
+  pub fn len(&self) -> usize {
+    self.values.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.values.is_empty()
+  }
+  fn width(&self) -> usize {
+    2 * self.radius as usize + 1
+  }
+  pub fn position(&self, index: usize) -> Option<Position> {
+    let w = self.width();
+    let r = self.radius as isize;
+    let mut i = index;
+    let mut cells = vec![(0usize, 0i8); self.pieces.len()];
+    for (j, &p) in self.pieces.iter().enumerate().rev() {
+      let slot = i % self.slots[j];
+      i /= self.slots[j];
+      cells[j] = (
+        slot % (w * w),
+        if slot >= w * w { piece::promote(p) } else { p },
+      );
+    }
+    let king = i;
+    let mut board = [piece::NONE; 81];
+    board[king] = piece::WHITE_KING;
+    for (off, v) in cells {
+      let row = (king / 9) as isize + (off / w) as isize - r;
+      let col = (king % 9) as isize + (off % w) as isize - r;
+      if !(0..9).contains(&row) || !(0..9).contains(&col) {
+        return None;
+      }
+      let k = 9 * row as usize + col as usize;
+      if board[k] != piece::NONE {
+        return None;
+      }
+      if !piece::is_promoted(v) && !piece::could_unpromoted(v, k) {
+        return None;
+      }
+      if v == piece::PAWN && (0..9).any(|row| board[9 * row + k % 9] == piece::PAWN) {
+        return None;
+      }
+      board[k] = v;
+    }
+    let pos = Position::from_board(board, self.hand, self.white_pockets, 1, 1);
+    if pos.is_legal() {
+      Some(pos)
+    } else {
+      None
+    }
+  }
+  pub fn index(&self, pos: &Position) -> Option<usize> {
+    if pos.side < 0
+      || pos.pieces_count() as usize != self.pieces.len() + 1
+      || *pos.pockets(1) != self.hand
+      || *pos.pockets(-1) != self.white_pockets
+    {
+      return None;
+    }
+    let king = pos.king_position(-1)?;
+    let mut v = Vec::with_capacity(self.pieces.len());
+    for k in 0..81 {
+      let p = pos.piece_at(k);
+      if p == piece::NONE || k == king {
+        continue;
+      }
+      if p < 0 || p == piece::KING {
+        return None;
+      }
+      v.push((piece::unpromote(p), k, piece::is_promoted(p)));
+    }
+    v.sort_by_key(|e| std::cmp::Reverse(e.0));
+    let w = self.width() as isize;
+    let r = self.radius as isize;
+    let mut index = king;
+    for (j, (p, k, promoted)) in v.into_iter().enumerate() {
+      if p != self.pieces[j] {
+        return None;
+      }
+      let dr = (k / 9) as isize - (king / 9) as isize + r;
+      let dc = (k % 9) as isize - (king % 9) as isize + r;
+      if !(0..w).contains(&dr) || !(0..w).contains(&dc) {
+        return None;
+      }
+      let mut slot = (dr * w + dc) as usize;
+      if promoted {
+        slot += (w * w) as usize;
+      }
+      index = index * self.slots[j] + slot;
+    }
+    Some(index)
+  }
+  pub fn probe(&self, pos: &Position) -> Option<Probe> {
+    let v = self.values[self.index(pos)?];
+    match v {
+      INVALID => None,
+      NO_MATE => Some(Probe::NoMate),
+      _ => Some(Probe::Mate(v & !UNIQUE, (v & UNIQUE) != 0)),
+    }
+  }
+  //returns number of valid positions
+  pub fn generate(&mut self, s: &mut Search) -> usize {
+    let mut positions = 0;
+    for i in 0..self.values.len() {
+      if let Some(mut pos) = self.position(i) {
+        positions += 1;
+        let (res, pv) = s.search(&mut pos, self.depth);
+        s.hashes_clear();
+        self.values[i] = match res {
+          Some(d) => d | (if pv.is_some() { UNIQUE } else { 0 }),
+          None => NO_MATE,
+        };
+      }
+    }
+    positions
+  }
+  pub fn save<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&[VERSION, self.depth, self.radius, self.pieces.len() as u8])?;
+    for &p in &self.pieces {
+      w.write_all(&[p as u8])?;
+    }
+    w.write_all(&self.hand)?;
+    w.write_all(&(self.values.len() as u32).to_le_bytes())?;
+    w.write_all(&self.values)
+  }
+  pub fn load<R: Read>(r: &mut R) -> std::io::Result<Self> {
+    let mut header = [0u8; 8];
+    r.read_exact(&mut header)?;
+    if &header[0..4] != MAGIC {
+      return Err(invalid_data(String::from("not a tablebase file")));
+    }
+    if header[4] != VERSION {
+      return Err(invalid_data(format!(
+        "unsupported tablebase version {}",
+        header[4]
+      )));
+    }
+    let mut pieces = vec![0u8; header[7] as usize];
+    r.read_exact(&mut pieces)?;
+    let mut material = Material::default();
+    for p in pieces {
+      if p as i8 <= piece::NONE || p as i8 >= piece::KING {
+        return Err(invalid_data(format!("invalid tablebase piece {}", p)));
+      }
+      material.0[p as usize] += 1;
+    }
+    let mut hand = Material::default();
+    r.read_exact(&mut hand.0)?;
+    let mut tb = Tablebase::new(&material, &hand, header[6], header[5]).map_err(invalid_data)?;
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len)?;
+    if u32::from_le_bytes(len) as usize != tb.values.len() {
+      return Err(invalid_data(String::from("tablebase size mismatch")));
+    }
+    r.read_exact(&mut tb.values)?;
+    Ok(tb)
+  }
+  pub fn save_to_file(&self, filename: &str) -> std::io::Result<()> {
+    let mut w = BufWriter::new(File::create(filename)?);
+    self.save(&mut w)?;
+    w.flush()
+  }
+  pub fn load_from_file(filename: &str) -> std::io::Result<Self> {
+    Tablebase::load(&mut BufReader::new(File::open(filename)?))
+  }
+}
@@ -0,0 +1,66 @@
+//self-checking SFEN corpora: found mate length and principal variation are compared
+//with dm and pv operations of EPD lines
+use super::search::Search;
+use super::shogi::epd::EPD;
+use super::shogi::validation;
+
+//mismatches of the line (empty if search agrees), without dm the search depth is the pv length
+pub fn verify(s: &mut Search, epd: &EPD) -> Vec<String> {
+  let mut r = Vec::new();
+  let dm = epd.dm();
+  let pv = epd.pv();
+  let depth = match (dm, pv) {
+    (Some(dm), _) => dm as usize,
+    (None, Some(pv)) => pv.len(),
+    _ => return r,
+  };
+  let mut pos = epd.pos.clone();
+  let swapped = pos.side < 0;
+  if swapped {
+    pos.swap_sides();
+  }
+  let problems = pos.validate();
+  if !problems.is_empty() {
+    r.push(format!(
+      "invalid position ({})",
+      validation::describe(&problems)
+    ));
+    return r;
+  }
+  pos.move_no = 1;
+  let (res, found) = s.search(&mut pos, depth.min(u8::MAX as usize) as u8);
+  s.hashes_clear();
+  match (dm, res) {
+    (Some(dm), Some(res)) if dm != res => {
+      r.push(format!("expected mate in {}, found mate in {}", dm, res))
+    }
+    (Some(dm), None) => r.push(format!("expected mate in {} isn't found", dm)),
+    (None, None) => r.push(format!("mate in {} moves isn't found", depth)),
+    _ => (),
+  }
+  if let (Some(pv), Some(res)) = (pv, res) {
+    match found {
+      Some(mut found) => {
+        if swapped {
+          for m in found.iter_mut() {
+            m.swap_side();
+          }
+        }
+        let found: Vec<String> = found.iter().map(|m| m.to_usi()).collect();
+        if found != pv {
+          r.push(format!(
+            "expected pv {}, found pv {}",
+            pv.join(" "),
+            found.join(" ")
+          ));
+        }
+      }
+      None => r.push(format!(
+        "expected pv {}, mate in {} isn't unique",
+        pv.join(" "),
+        res
+      )),
+    }
+  }
+  r
+}
@@ -0,0 +1,157 @@
+use tsumeshogi_check::io::FileIterator;
+use tsumeshogi_check::shogi::csa::{csa_file_iterator, game_to_csa, parse_csa_game, CSASplitter};
+use tsumeshogi_check::shogi::game::{GameResult, MoveTime};
+use tsumeshogi_check::shogi::Position;
+
+fn csa_lines(s: &str) -> Vec<String> {
+  s.lines().map(|l| l.trim().to_owned()).collect()
+}
+
+#[test]
+fn csa_game() {
+  let a = csa_lines(
+    "V2.2
+N+Sente
+N-Gote
+$EVENT:floodgate
+$START_TIME:2024/01/02 10:00:00
+$TIME_LIMIT:00:10+10
+$END_TIME:2024/01/02 10:20:00
+'rating +1500 -1600
+PI
++
++7776FU,T3
+-3334FU
+T5
++8822UM
+T70
+'*bishop exchange
+-3122GI
+T2
++0045KA
+T720
+%TORYO",
+  );
+  let g = parse_csa_game(&a).unwrap();
+  assert_eq!(g.moves.len(), 5);
+  assert_eq!(g.header.get("sente").unwrap(), "Sente");
+  assert_eq!(g.header.get("event").unwrap(), "floodgate");
+  assert_eq!(g.header.get("date").unwrap(), "2024/01/02 10:00:00");
+  assert_eq!(g.header.get("csa:END_TIME").unwrap(), "2024/01/02 10:20:00");
+  assert!(!g.header.contains_key("sfen"));
+  assert_eq!(g.moves[2].to_kif(&None), "２二角成(88)");
+  assert_eq!(g.moves[4].to_kif(&None), "４五角打");
+  assert_eq!(
+    g.times[4],
+    MoveTime {
+      elapsed: 720,
+      total: 793
+    }
+  );
+  assert_eq!(g.base_time(), Some(600));
+  assert_eq!(g.comments(0), vec!["rating +1500 -1600"]);
+  assert_eq!(g.comments(3), vec!["bishop exchange"]);
+  assert!(matches!(g.result(), GameResult::BlackWon));
+  assert!(g.header.contains_key("resignation"));
+  let s = game_to_csa(&g, None);
+  assert!(s.contains("$TIME_LIMIT:00:10+10\n"), "{}", s);
+  assert!(s.contains("+8822UM\nT70\n'*bishop exchange\n"), "{}", s);
+  assert!(s.ends_with("T720\n%TORYO\n"), "{}", s);
+  let h = parse_csa_game(&csa_lines(&s)).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert_eq!(h.times, g.times);
+  assert_eq!(h.header, g.header);
+  assert_eq!(h.annotations, g.annotations);
+  //KIF control which isn't parsed is kept verbatim
+  let mut g = g;
+  g.set_header(String::from("control"), String::from("十分切れ負け"));
+  let s = game_to_csa(&g, None);
+  assert!(s.contains("$TIME_LIMIT:十分切れ負け\n"), "{}", s);
+  let h = parse_csa_game(&csa_lines(&s)).unwrap();
+  assert_eq!(h.header.get("control").unwrap(), "十分切れ負け");
+  //illegal move
+  let mut a = a.clone();
+  a[16] = "-3122KI".to_owned();
+  assert!(parse_csa_game(&a).is_err());
+}
+
+#[test]
+fn csa_position() {
+  let a = csa_lines(
+    "P1 *  *  *  *  *  *  * -KE-KY
+P2 *  *  *  *  * +KA * -OU *
+P3 *  *  *  *  *  * +FU-FU-FU
+P4 *  *  *  *  *  *  *  *  *
+P5 *  *  *  *  *  *  *  *  *
+P6 *  *  *  *  *  *  *  *  *
+P7 *  *  *  *  *  *  *  *  *
+P8 *  *  *  *  *  *  *  *  *
+P9 *  *  *  *  *  *  *  *  *
+P+00HI00KI
+P-00AL
++
++4231UM
+%TORYO",
+  );
+  let g = parse_csa_game(&a).unwrap();
+  let pos = Position::parse_sfen("7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l15p 1").unwrap();
+  assert_eq!(g.start_position().unwrap().to_string(), pos.to_string());
+  assert_eq!(g.moves.len(), 1);
+  assert_eq!(g.moves[0].to_kif(&None), "３一角成(42)");
+  let s = game_to_csa(&g, None);
+  assert!(s.contains("P2 *  *  *  *  * +KA * -OU * \n"), "{}", s);
+  let h = parse_csa_game(&csa_lines(&s)).unwrap();
+  assert_eq!(h.header, g.header);
+  assert_eq!(h.moves, g.moves);
+  //handicap as removed pieces
+  let g = parse_csa_game(&csa_lines("PI82HI22KA\n-\n-3334FU\n%SENNICHITE")).unwrap();
+  assert_eq!(
+    g.header.get("sfen").unwrap(),
+    &Position::parse_sfen("lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1")
+      .unwrap()
+      .to_string()
+  );
+  assert_eq!(g.header.get("termination").unwrap(), "千日手");
+  assert!(game_to_csa(&g, None).ends_with("-3334FU\n%SENNICHITE\n"));
+}
+
+#[test]
+fn csa_terminations() {
+  for (t, result, termination) in [
+    ("%TIME_UP", "3", "切れ負け"),
+    ("%KACHI", "2", "入玉勝ち"),
+    ("%ILLEGAL_MOVE", "3", "反則負け"),
+    ("%-ILLEGAL_ACTION", "2", "反則勝ち"),
+  ] {
+    let g = parse_csa_game(&csa_lines(&format!("PI\n+\n+7776FU\n-3334FU\n{}", t))).unwrap();
+    assert_eq!(g.header.get("result").unwrap(), result, "{}", t);
+    assert_eq!(g.header.get("termination").unwrap(), termination, "{}", t);
+    let s = game_to_csa(&g, None);
+    assert!(s.ends_with(&format!("{}\n", t)), "{}", s);
+  }
+}
+
+#[test]
+fn csa_file_splitting() {
+  let filename = std::env::temp_dir().join(format!("csa_splitting{}.csa", std::process::id()));
+  std::fs::write(
+    &filename,
+    "V2.2\nN+A\nPI\n+\n+7776FU\n%CHUDAN\n/\nV2.2\nN+B\nPI\n+\n+2726FU\n",
+  )
+  .unwrap();
+  let games: Vec<_> = csa_file_iterator(filename.to_str().unwrap())
+    .unwrap()
+    .map(|a| parse_csa_game(&a.unwrap()).unwrap())
+    .collect();
+  std::fs::remove_file(&filename).unwrap();
+  assert_eq!(games.len(), 2);
+  assert_eq!(games[0].header.get("termination").unwrap(), "中断");
+  assert_eq!(games[1].header.get("sente").unwrap(), "B");
+  //empty games are skipped
+  let it = FileIterator::from_string(
+    String::from("/\n\nV2.2\nPI\n+\n/\n/\n"),
+    Box::new(CSASplitter),
+  );
+  let games: Vec<_> = it.map(|a| a.unwrap()).collect();
+  assert_eq!(games, vec![vec!["V2.2", "PI", "+"]]);
+}
@@ -0,0 +1,56 @@
+use tsumeshogi_check::dataset::{label, Label};
+use tsumeshogi_check::search::Search;
+use tsumeshogi_check::shogi::alloc::PositionMovesAllocator;
+use tsumeshogi_check::shogi::packed::DM_ABORTED;
+use tsumeshogi_check::shogi::Position;
+
+#[test]
+fn dataset_labels() {
+  let mut s = Search::new(1 << 20);
+  let mut allocator = PositionMovesAllocator::default();
+  //white to move, the move label is for the side to move
+  let mut pos = Position::parse_sfen("4k4/9/9/9/9/9/4p4/9/4K4 w 2R2B3G4S4N4L17Pg 1").unwrap();
+  let m = pos.parse_usi_move(&mut allocator, "G*5h").unwrap();
+  let l = label(&mut s, &pos, 7, 0);
+  assert_eq!(l, Label::Mate(1, Some(m.clone())));
+  assert_eq!(
+    l.to_json(&pos, 2, 1).to_string(),
+    r#"{"sfen":"4k4/9/9/9/9/9/4p4/9/4K4 w 17P4L4N4S3G2B2Rg 1","game":2,"ply":1,"label":"mate","dm":1,"move":"G*5h"}"#
+  );
+  let payload = l.to_payload(1);
+  assert_eq!((payload.best_move, payload.dm), (u32::from(&m), 1));
+  let pos = Position::default();
+  let l = label(&mut s, &pos, 3, 0);
+  assert_eq!(l, Label::NoMate(3));
+  assert_eq!(l.to_payload(1).dm, 0);
+  assert!(l
+    .to_json(&pos, 1, 1)
+    .to_string()
+    .ends_with(r#""label":"nomate","depth":3}"#));
+  //side to move is in check
+  let pos = Position::parse_sfen("4k4/4G4/4G4/4P4/9/9/9/9/9 w 17p4l4n4s2b2r 4").unwrap();
+  let l = label(&mut s, &pos, 3, 0);
+  assert!(matches!(l, Label::Aborted(_)));
+  let payload = l.to_payload(4);
+  assert_eq!(
+    (payload.best_move, payload.dm, payload.move_no),
+    (0, DM_ABORTED, 4)
+  );
+  //node limit is checked during the search (history tables of the search reorder moves,
+  //so every search starts afresh)
+  let pos = Position::parse_sfen("3sks3/9/4S4/9/9/8B/9/9/9 b S 1").unwrap();
+  let mut s = Search::new(1 << 20);
+  assert!(matches!(label(&mut s, &pos, 3, 0), Label::Mate(3, Some(_))));
+  let mate_nodes = s.nodes;
+  let mut s = Search::new(1 << 20);
+  assert!(matches!(
+    label(&mut s, &pos, 3, mate_nodes / 2),
+    Label::Aborted(_)
+  ));
+  assert!(s.nodes <= mate_nodes / 2 + 1);
+  let mut s = Search::new(1 << 20);
+  assert!(matches!(
+    label(&mut s, &pos, 3, mate_nodes),
+    Label::Mate(3, Some(_))
+  ));
+}
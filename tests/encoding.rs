@@ -0,0 +1,67 @@
+use tsumeshogi_check::encoding::{self, Encoding};
+use tsumeshogi_check::shogi::kif;
+
+//KIF game in Shift_JIS
+const SJIS_KIF: &[u8] = b"\x90\xe6\x8e\xe8\x81F\x89H\x90\xb6\x91P\x8e\xa1\n\x8c\xe3\x8e\xe8\x81F\xb1\xb2\xb3\n\x8e\xe8\x90\x94----\x8ew\x8e\xe8---------\x8f\xc1\x94\xef\x8e\x9e\x8a\xd4--\n   1 \x82V\x98Z\x95\xe0(77)\n   2 \x82R\x8el\x95\xe0(33)\n   3 \x93\x8a\x97\xb9\n";
+
+#[test]
+fn cp932() {
+  assert_eq!(
+    encoding::decode_cp932(b"\x82\xa0\x8a\xbf\x8e\x9a abc\xb1\x87\x40\x81\x5f"),
+    "あ漢字 abcｱ①＼"
+  );
+  //truncated and unmapped codes
+  assert_eq!(encoding::decode_cp932(b"a\x82"), "a\u{fffd}");
+  assert_eq!(encoding::decode_cp932(b"\x85\x40"), "\u{fffd}");
+}
+
+#[test]
+fn detect() {
+  assert_eq!(encoding::detect("a.kif", SJIS_KIF), Encoding::ShiftJis);
+  assert_eq!(
+    encoding::detect("a.kif", "先手：羽生".as_bytes()),
+    Encoding::Utf8
+  );
+  assert_eq!(encoding::detect("a.kifu", SJIS_KIF), Encoding::Utf8);
+  assert_eq!(
+    encoding::detect("a.kif", b"#KIF version=2.0 encoding=Shift_JIS\n"),
+    Encoding::ShiftJis
+  );
+  assert_eq!(
+    encoding::detect("a.kif", b"\xef\xbb\xbf\x82\xa0"),
+    Encoding::Utf8
+  );
+}
+
+#[test]
+fn sjis_kif_file() {
+  let filename = std::env::temp_dir().join(format!("sjis{}.kif", std::process::id()));
+  let filename = filename.to_str().unwrap();
+  std::fs::write(filename, SJIS_KIF).unwrap();
+  let games: Vec<_> = kif::kif_file_iterator(filename).unwrap().collect();
+  std::fs::remove_file(filename).unwrap();
+  assert_eq!(games.len(), 1);
+  let mut kb = kif::KIFBuilder::default();
+  let g = kb.parse_kif_game(games[0].as_ref().unwrap()).unwrap();
+  assert_eq!(g.get_header(&"sente".to_owned()).unwrap(), "羽生善治");
+  assert_eq!(g.get_header(&"gote".to_owned()).unwrap(), "ｱｲｳ");
+  assert_eq!(g.moves.len(), 2);
+}
+
+#[test]
+fn sjis_kif_collection() {
+  //collection is larger than the beginning of the file used for encoding detection
+  let filename = std::env::temp_dir().join(format!("sjis_collection{}.kif", std::process::id()));
+  let filename = filename.to_str().unwrap();
+  std::fs::write(filename, SJIS_KIF.repeat(1000)).unwrap();
+  let games: Vec<_> = kif::kif_file_iterator(filename)
+    .unwrap()
+    .map(|a| a.unwrap())
+    .collect();
+  std::fs::remove_file(filename).unwrap();
+  assert_eq!(games.len(), 1000);
+  let mut kb = kif::KIFBuilder::default();
+  let g = kb.parse_kif_game(&games[999]).unwrap();
+  assert_eq!(g.get_header(&"sente".to_owned()).unwrap(), "羽生善治");
+  assert_eq!(g.moves.len(), 2);
+}
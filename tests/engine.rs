@@ -0,0 +1,74 @@
+use tsumeshogi_check::engine::Engine;
+use tsumeshogi_check::search::Search;
+
+fn run(input: &str) -> Vec<String> {
+  let mut e = Engine::new(7, 1 << 20, |bytes| Ok(Search::new(bytes)));
+  let mut out = Vec::new();
+  e.run(std::io::Cursor::new(input.to_owned()), &mut out)
+    .unwrap();
+  String::from_utf8(out)
+    .unwrap()
+    .lines()
+    .map(|s| s.to_owned())
+    .collect()
+}
+
+#[test]
+fn usi_go_mate() {
+  let out = run(
+    "usi
+setoption name USI_Hash value 4
+isready
+position sfen 4k4/9/4P4/9/9/9/9/9/9 b G2r2b3g4s4n4l17p 1
+go mate 10000
+position sfen 4k4/9/9/9/9/9/4p4/9/4K4 w 2R2B3G4S4N4L17Pg 1
+go mate infinite
+position sfen 7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l15p 1 moves G*3b 2b1b
+go mate
+position startpos
+go mate 10000
+position sfen 8k/9/7GP/9/9/9/9/9/9 b G2r2b2g4s4n4l17p 1
+go mate 10000
+position sfen 4k4/9/9/9/4r4/9/9/9/4K4 b Gr2b3g4s4n4l18p 1
+go mate 10000
+go
+quit
+isready
+",
+  );
+  assert_eq!(
+    out[0],
+    format!("id name tsumeshogi-check {}", env!("CARGO_PKG_VERSION"))
+  );
+  assert_eq!(
+    &out[2..],
+    &[
+      "option name USI_Hash type spin default 1 min 1 max 65536",
+      "usiok",
+      "readyok",
+      "checkmate G*5b",
+      "checkmate G*5h",
+      "checkmate R*2b",
+      "checkmate nomate",
+      //mate isn't unique (1c1b+ and G*1b)
+      "checkmate 1c1b+",
+      //side to move is in check
+      "checkmate nomate",
+      "bestmove resign",
+    ]
+  );
+}
+
+#[test]
+fn usi_stop() {
+  //stop interrupts the running search, the next search waits for it
+  let out = run(
+    "position sfen 9/9/9/9/4k4/9/9/9/9 b RBrb4g4s4n4l18p 1
+go mate infinite
+stop
+position sfen 4k4/9/4P4/9/9/9/9/9/9 b G2r2b3g4s4n4l17p 1
+go mate
+",
+  );
+  assert_eq!(out, ["checkmate timeout", "checkmate G*5b"]);
+}
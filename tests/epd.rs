@@ -0,0 +1,83 @@
+use tsumeshogi_check::search::Search;
+use tsumeshogi_check::shogi::alloc::PositionMovesAllocator;
+use tsumeshogi_check::shogi::epd::EPD;
+use tsumeshogi_check::shogi::packed::Payload;
+use tsumeshogi_check::verify::verify;
+
+#[test]
+fn epd_operations() {
+  let epd = EPD::parse(
+    "3sks3/9/4S4/9/9/8B/9/9/9 b S 1 dm 3; c0 \"☗５二角成(16) ☖同　銀(41); ☗４二銀打\"; pv 1f5b+ 4a5b S*4b; acn 116; id \"tsume-1\";",
+  )
+  .unwrap();
+  assert_eq!(epd.pos.to_string(), "3sks3/9/4S4/9/9/8B/9/9/9 b S 1");
+  assert_eq!(epd.dm(), Some(3));
+  assert_eq!(epd.comment(), Some("☗５二角成(16) ☖同　銀(41); ☗４二銀打"));
+  assert_eq!(epd.pv().unwrap(), ["1f5b+", "4a5b", "S*4b"]);
+  assert_eq!(epd.acn(), Some(116));
+  assert_eq!(epd.id(), Some("tsume-1"));
+  assert!(epd.operands("bm").is_none());
+  //bare SFEN and SFEN without move number
+  let epd = EPD::parse("9/4k4/9/4P4/9/9/9/9/9 b 2G2r2b4s4n4l17p 1").unwrap();
+  assert!(epd.operations.is_empty());
+  let epd = EPD::parse("9/4k4/9/4P4/9/9/9/9/9 b 2G2r2b4s4n4l17p dm 3;").unwrap();
+  assert_eq!(epd.pos.move_no, 1);
+  assert_eq!(epd.dm(), Some(3));
+  for s in [
+    "9/4k4/9/4P4/9/9/9/9/9 b 2G2r2b4s4n4l17p 1 dm 3",
+    "9/4k4/9/4P4/9/9/9/9/9 b 2G2r2b4s4n4l17p 1 c0 \"abc;",
+    "9/4k4/9/4P4/9/9/9 b 2G2r2b4s4n4l17p 1 dm 3;",
+    "9/4k4/9",
+  ] {
+    assert!(EPD::parse(s).is_err(), "{}", s);
+  }
+}
+
+#[test]
+fn epd_verify() {
+  let mut s = Search::new(16 << 20);
+  for (i, line) in include_str!("data/tsume.sfen").lines().enumerate() {
+    let epd = EPD::parse(line).unwrap();
+    assert!(epd.dm().is_some(), "line {}", i + 1);
+    assert_eq!(verify(&mut s, &epd), Vec::<String>::new(), "line {}", i + 1);
+  }
+  let epd = EPD::parse("7nl/7k1/6Ppp/9/9/9/9/9/9 b 2G2r2b2g4s3n3l15p 1 dm 5;").unwrap();
+  assert_eq!(
+    verify(&mut s, &epd),
+    vec!["expected mate in 5, found mate in 3"]
+  );
+  let epd = EPD::parse("7nl/7k1/6Ppp/9/9/9/9/9/9 b 2G2r2b2g4s3n3l15p 1 pv G*2b;").unwrap();
+  assert_eq!(verify(&mut s, &epd), vec!["mate in 1 moves isn't found"]);
+  let epd =
+    EPD::parse("7nl/7k1/6Ppp/9/9/9/9/9/9 b 2G2r2b2g4s3n3l15p 1 dm 3; pv G*2b 3a2b G*3b;").unwrap();
+  assert_eq!(
+    verify(&mut s, &epd),
+    vec!["expected pv G*2b 3a2b G*3b, found pv G*3b 2b1b G*2b"]
+  );
+  //no expectations
+  let epd = EPD::parse("7nl/7k1/6Ppp/9/9/9/9/9/9 b 2G2r2b2g4s3n3l15p 1 id \"x\";").unwrap();
+  assert!(verify(&mut s, &epd).is_empty());
+}
+
+#[test]
+fn epd_display() {
+  let line = "3sks3/9/4S4/9/9/8B/9/9/9 b S 1 dm 3; c0 \"☗５二角成(16); ☖同　銀(41)\"; pv 1f5b+ 4a5b S*4b; id x;";
+  let mut epd = EPD::parse(line).unwrap();
+  assert_eq!(epd.to_string(), line);
+  let payload = Payload {
+    best_move: u32::from(
+      &epd
+        .pos
+        .parse_usi_move(&mut PositionMovesAllocator::default(), "1f5b+")
+        .unwrap(),
+    ),
+    dm: 3,
+    move_no: 1,
+  };
+  let epd = EPD::from_packed(epd.pos, Some(&payload));
+  assert_eq!(
+    epd.to_string(),
+    "3sks3/9/4S4/9/9/8B/9/9/9 b S 1 dm 3; bm 1f5b+;"
+  );
+  assert!(EPD::from_packed(epd.pos, None).operations.is_empty());
+}
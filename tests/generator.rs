@@ -0,0 +1,57 @@
+use std::str::FromStr;
+use tsumeshogi_check::generator::{Generator, GeneratorOptions, Material};
+use tsumeshogi_check::search::Search;
+use tsumeshogi_check::shogi::Position;
+
+#[test]
+fn material() {
+  let m = Material::from_str("R2gs").unwrap();
+  assert_eq!(m.to_string(), "R2GS");
+  assert_eq!(m.len(), 4);
+  assert!(Material::from_str("-").unwrap().is_empty());
+  assert!(Material::from_str("K").is_err());
+  assert!(Material::from_str("0G").is_err());
+  assert!(Material::from_str("300P").is_err());
+  assert!(Material::from_str("200P100P").is_err());
+  assert_eq!(Material::from_str("10p").unwrap().to_string(), "10P");
+  let opts = GeneratorOptions {
+    attacker_hand: Material::from_str("4G").unwrap(),
+    ..GeneratorOptions::default()
+  };
+  assert!(opts.validate().is_err());
+  assert!(Generator::new(1, opts).is_err());
+}
+
+#[test]
+fn random_positions() {
+  let mut g1 = Generator::new(123, GeneratorOptions::default()).unwrap();
+  let mut g2 = Generator::new(123, GeneratorOptions::default()).unwrap();
+  for _ in 0..1000 {
+    let p1 = g1.random_position();
+    let p2 = g2.random_position();
+    assert_eq!(p1.is_some(), p2.is_some());
+    if let Some(p) = p1 {
+      let sfen = p.to_string();
+      assert_eq!(sfen, p2.unwrap().to_string());
+      //nifu, dead squares and piece counts are validated by SFEN parser
+      let q = Position::parse_sfen(&sfen);
+      assert!(q.is_ok(), "{:?}", q.err());
+    }
+  }
+}
+
+#[test]
+fn generated_puzzles() {
+  let mut s = Search::new(16 << 20);
+  let mut g = Generator::new(7, GeneratorOptions::default()).unwrap();
+  let mut puzzles = 0;
+  while puzzles < 3 {
+    if let Some(p) = g.try_next(&mut s, 3) {
+      assert_eq!(p.pv.len(), 3);
+      let mut pos = Position::parse_sfen(&p.pos.to_string()).unwrap();
+      let mut t = Search::new(16 << 20);
+      assert_eq!(t.search(&mut pos, 3), (Some(3), Some(p.pv)));
+      puzzles += 1;
+    }
+  }
+}
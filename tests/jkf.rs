@@ -0,0 +1,169 @@
+use tsumeshogi_check::json::{self, Value};
+use tsumeshogi_check::shogi::alloc::PositionMovesAllocator;
+use tsumeshogi_check::shogi::game::{Game, GameResult, MoveTime};
+use tsumeshogi_check::shogi::kif::KIFBuilder;
+use tsumeshogi_check::shogi::usi::parse_usi_position;
+use tsumeshogi_check::shogi::Position;
+
+#[test]
+fn json_values() {
+  let v = json::parse(
+    " {\"a\": [1, -2.5, true, null], \"b\": \"x\\\"\\u00e9\\ud83d\\ude00\\n\", \"c\": {}} ",
+  )
+  .unwrap();
+  assert_eq!(v.get("a").unwrap().as_array().unwrap().len(), 4);
+  assert_eq!(v.get("a").unwrap().as_array().unwrap()[0].as_i64(), Some(1));
+  assert_eq!(v.get("a").unwrap().as_array().unwrap()[1].as_i64(), None);
+  assert_eq!(v.get("b").unwrap().as_str(), Some("x\"é😀\n"));
+  assert_eq!(
+    v.to_string(),
+    "{\"a\":[1,-2.5,true,null],\"b\":\"x\\\"é😀\\n\",\"c\":{}}"
+  );
+  assert_eq!(json::parse(&v.to_string()).unwrap(), v);
+  assert_eq!(json::parse_values("{} [1]\n\"s\"").unwrap().len(), 3);
+  let err = json::parse("{\"a\":\n[1,]}").unwrap_err();
+  assert_eq!(err.line, 2);
+  for s in ["", "[1", "{\"a\" 1}", "tru", "[1] 2", "\"abc"] {
+    assert!(json::parse(s).is_err(), "{}", s);
+  }
+  assert_eq!(Value::from("日本"), Value::String(String::from("日本")));
+}
+
+#[test]
+fn jkf_game() {
+  let kb = KIFBuilder::default();
+  let s = r#"{
+  "header": {"先手": "Sente", "後手": "Gote", "棋戦": "test", "備考": "note"},
+  "initial": {"preset": "HIRATE"},
+  "moves": [
+    {"comments": ["opening"]},
+    {"move": {"from": {"x": 7, "y": 7}, "to": {"x": 7, "y": 6}, "color": 0, "piece": "FU"},
+     "time": {"now": {"m": 0, "s": 3}, "total": {"h": 0, "m": 0, "s": 3}}},
+    {"move": {"from": {"x": 3, "y": 3}, "to": {"x": 3, "y": 4}, "color": 1, "piece": "FU"},
+     "time": {"now": {"m": 0, "s": 5}, "total": {"h": 0, "m": 0, "s": 5}},
+     "forks": [[
+       {"move": {"from": {"x": 8, "y": 3}, "to": {"x": 8, "y": 4}, "color": 1, "piece": "FU"},
+        "comments": ["side pawn"]},
+       {"move": {"from": {"x": 2, "y": 7}, "to": {"x": 2, "y": 6}, "color": 0, "piece": "FU"}}
+     ]]},
+    {"move": {"from": {"x": 8, "y": 8}, "to": {"x": 2, "y": 2}, "color": 0, "piece": "KA",
+              "promote": true, "capture": "KA"},
+     "time": {"now": {"m": 1, "s": 10}, "total": {"h": 0, "m": 1, "s": 13}},
+     "comments": ["bishop exchange"]},
+    {"move": {"to": {"x": 2, "y": 2}, "same": true, "color": 1, "piece": "GI"},
+     "time": {"now": {"m": 0, "s": 2}, "total": {"h": 0, "m": 0, "s": 7}}},
+    {"move": {"to": {"x": 4, "y": 5}, "color": 0, "piece": "KA"},
+     "time": {"now": {"m": 12, "s": 0}, "total": {"h": 0, "m": 13, "s": 13}}},
+    {"special": "TORYO"}
+  ]
+}"#;
+  let g = kb.parse_jkf_game(s).unwrap();
+  let (_, moves) = parse_usi_position("startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e").unwrap();
+  assert_eq!(g.moves, moves);
+  assert_eq!(g.header.get("sente").unwrap(), "Sente");
+  assert_eq!(g.header.get("event").unwrap(), "test");
+  assert_eq!(g.header.get("kif:備考").unwrap(), "note");
+  assert!(!g.header.contains_key("sfen"));
+  assert_eq!(
+    g.times[4],
+    MoveTime {
+      elapsed: 720,
+      total: 793
+    }
+  );
+  assert_eq!(g.comments(0), vec!["opening"]);
+  assert_eq!(g.comments(3), vec!["bishop exchange"]);
+  assert_eq!(g.variations.len(), 1);
+  assert_eq!(g.variations[0].start, 1);
+  assert_eq!(g.variations[0].moves.len(), 2);
+  assert_eq!(g.variations[0].moves[0].to_kif(&None), "８四歩(83)");
+  assert_eq!(g.lines()[1].len(), 3);
+  assert!(matches!(g.result(), GameResult::BlackWon));
+  assert!(g.header.contains_key("resignation"));
+  let t = kb.game_to_jkf(&g, None);
+  assert!(t.contains("\"initial\":{\"preset\":\"HIRATE\"}"), "{}", t);
+  assert!(
+    t.contains("\"move\":{\"color\":1,\"from\":{\"x\":3,\"y\":1},\"to\":{\"x\":2,\"y\":2},\"piece\":\"GI\",\"same\":true,\"capture\":\"UM\"}"),
+    "{}",
+    t
+  );
+  assert!(t.ends_with("{\"special\":\"TORYO\"}]}"), "{}", t);
+  let h = kb.parse_jkf_game(&t).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert_eq!(h.times, g.times);
+  assert_eq!(h.header, g.header);
+  assert_eq!(h.annotations, g.annotations);
+  assert_eq!(h.variations, g.variations);
+  //illegal move
+  let t = s.replace("\"piece\": \"GI\"", "\"piece\": \"KI\"");
+  assert!(kb.parse_jkf_game(&t).is_err());
+}
+
+#[test]
+fn jkf_moves() {
+  let mut allocator = PositionMovesAllocator::default();
+  let mut pos = Position::default();
+  let last_move = None;
+  //both golds could go to 58
+  let m = pos.parse_usi_move(&mut allocator, "4i5h").unwrap();
+  let v = pos.move_to_jkf(&mut allocator, &m, &last_move);
+  assert_eq!(
+    v.to_string(),
+    "{\"color\":0,\"from\":{\"x\":4,\"y\":9},\"to\":{\"x\":5,\"y\":8},\"piece\":\"KI\",\"relative\":\"R\"}"
+  );
+  let v = json::parse("{\"to\":{\"x\":5,\"y\":8},\"piece\":\"KI\",\"relative\":\"L\"}").unwrap();
+  let m = pos.parse_jkf_move(&mut allocator, &v, &last_move).unwrap();
+  assert_eq!(m.to_usi(), "6i5h");
+  let v = json::parse("{\"to\":{\"x\":5,\"y\":8},\"piece\":\"KI\"}").unwrap();
+  assert!(pos.parse_jkf_move(&mut allocator, &v, &last_move).is_none());
+  let v = json::parse(
+    "{\"color\":1,\"from\":{\"x\":7,\"y\":7},\"to\":{\"x\":7,\"y\":6},\"piece\":\"FU\"}",
+  )
+  .unwrap();
+  assert!(pos.parse_jkf_move(&mut allocator, &v, &last_move).is_none());
+  //promotion choice
+  let mut pos = Position::parse_sfen("4k4/9/9/9/4P4/9/9/9/4K4 b B 1").unwrap();
+  let m = pos.parse_usi_move(&mut allocator, "B*5d").unwrap();
+  pos.do_move(&m);
+  let _ = pos
+    .parse_usi_move(&mut allocator, "5a4a")
+    .map(|m| pos.do_move(&m));
+  let m = pos.parse_usi_move(&mut allocator, "5d2a").unwrap();
+  let v = pos.move_to_jkf(&mut allocator, &m, &None);
+  assert!(v.to_string().contains("\"promote\":false"), "{}", v);
+}
+
+#[test]
+fn jkf_initial() {
+  let kb = KIFBuilder::default();
+  let sfen = "7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l15p 1";
+  let (pos, moves) = parse_usi_position(&format!("sfen {} moves G*3b", sfen)).unwrap();
+  let g = Game {
+    moves,
+    ..Game::default()
+  };
+  let t = kb.game_to_jkf(&g, Some(&pos));
+  assert!(t.contains("\"preset\":\"OTHER\""), "{}", t);
+  assert!(
+    t.contains("\"hands\":[{\"FU\":0,\"KY\":0,\"KE\":0,\"GI\":0,\"KI\":1,\"KA\":0,\"HI\":1},"),
+    "{}",
+    t
+  );
+  let h = kb.parse_jkf_game(&t).unwrap();
+  assert_eq!(h.header.get("sfen").unwrap(), &pos.to_string());
+  assert_eq!(h.moves, g.moves);
+  //handicap preset
+  let mut g = Game::default();
+  g.set_header(
+    String::from("sfen"),
+    String::from("lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+  );
+  let t = kb.game_to_jkf(&g, None);
+  assert!(t.contains("\"initial\":{\"preset\":\"KA\"}"), "{}", t);
+  let h = kb.parse_jkf_game(&t).unwrap();
+  assert_eq!(h.header.get("handicap").unwrap(), "角落ち");
+  assert_eq!(h.start_position().unwrap().side, -1);
+  assert!(kb
+    .parse_jkf_game("{\"initial\":{\"preset\":\"UNKNOWN\"}}")
+    .is_err());
+}
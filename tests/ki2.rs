@@ -0,0 +1,121 @@
+use tsumeshogi_check::shogi::alloc::PositionMovesAllocator;
+use tsumeshogi_check::shogi::{perft, Position};
+
+//KI2 move to KIF (with origin square) and back to KI2
+fn check_ki2(sfen: &str, ki2: &str, kif: &str) {
+  let mut pos = Position::parse_sfen(sfen).unwrap();
+  let mut allocator = PositionMovesAllocator::default();
+  let m = pos
+    .parse_ki2_move(&mut allocator, ki2, &None)
+    .unwrap_or_else(|| panic!("fail to parse {} in {}", ki2, sfen));
+  assert_eq!(m.to_kif(&None), kif, "{} in {}", ki2, sfen);
+  assert_eq!(pos.move_to_ki2(&mut allocator, &m, &None), ki2, "{}", sfen);
+}
+
+#[test]
+fn ki2_disambiguation() {
+  for (sfen, ki2, kif) in [
+    ("4k4/9/9/9/9/9/9/9/3G1G2K b - 1", "５八金右", "５八金(49)"),
+    ("4k4/9/9/9/9/9/9/9/3G1G2K b - 1", "５八金左", "５八金(69)"),
+    ("4k4/9/9/9/9/9/9/9/3GGG2K b - 1", "５八金直", "５八金(59)"),
+    ("4k4/9/9/9/9/9/9/9/3GGG2K b - 1", "５八金右", "５八金(49)"),
+    ("4k4/9/9/9/9/9/9/3G5/4G2K1 b - 1", "５八金寄", "５八金(68)"),
+    ("4k4/9/9/9/9/9/9/3G5/4G2K1 b - 1", "５八金上", "５八金(59)"),
+    //gote's right side is the 9th file
+    ("3g1g2k/9/9/9/9/9/9/9/4K4 w - 1", "５二金右", "５二金(61)"),
+    ("3g1g2k/9/9/9/9/9/9/9/4K4 w - 1", "５二金左", "５二金(41)"),
+    ("+R7+R/9/9/9/4k4/9/9/9/4K4 b - 1", "５一龍左", "５一龍(91)"),
+    ("8k/9/9/9/9/9/9/4+R4/+R7K b - 1", "５九龍寄", "５九龍(99)"),
+    ("8k/9/9/9/9/9/9/4+R4/+R7K b - 1", "５九龍引", "５九龍(58)"),
+    //golds on 4九 and 6九 both go up, 4八 one goes sideways
+    ("4k4/9/9/9/9/9/9/5G3/3G1G2K b - 1", "５八金左", "５八金(69)"),
+    (
+      "4k4/9/9/9/9/9/9/5G3/3G1G2K b - 1",
+      "５八金右上",
+      "５八金(49)",
+    ),
+    ("4k4/9/9/9/9/9/9/5G3/3G1G2K b - 1", "５八金寄", "５八金(48)"),
+    ("4k4/9/9/9/9/9/9/9/5S2K b S 1", "５八銀打", "５八銀打"),
+    ("4k4/9/9/9/9/9/9/9/5S2K b S 1", "１五銀", "１五銀打"),
+    ("4k4/9/9/5S3/9/9/9/9/7K1 b - 1", "３三銀成", "３三銀成(44)"),
+    ("4k4/9/9/5S3/9/9/9/9/7K1 b - 1", "３三銀不成", "３三銀(44)"),
+    ("4k4/9/9/9/9/9/9/9/2N1N2K1 b - 1", "６七桂左", "６七桂(79)"),
+  ] {
+    check_ki2(sfen, ki2, kif);
+  }
+  let mut pos = Position::parse_sfen("4k4/9/9/9/9/9/9/9/3G1G2K b - 1").unwrap();
+  let mut allocator = PositionMovesAllocator::default();
+  //ambiguous without modifier
+  assert!(pos
+    .parse_ki2_move(&mut allocator, "５八金", &None)
+    .is_none());
+  assert!(pos
+    .parse_ki2_move(&mut allocator, "５八金打", &None)
+    .is_none());
+}
+
+#[test]
+fn ki2_round_trip() {
+  let mut allocator = PositionMovesAllocator::default();
+  for sfen in [
+    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+    "G1+R4nl/2l+B1+N3/7pp/pgkpp1s2/1P1n1Pp2/g3P2RP/5+p3/2p3s1K/+b6NL b G2sl6p 1",
+    "ln1g3nl/1r1s1kg2/p1pppp1pp/6p2/1p5P1/2P1S4/PPBPPPP1P/2G4R1/LNS1KG1NL w Bs 1",
+    "4k4/9/2+P1+P1+P2/2G3G2/9/2G3G2/2+P1+P1+P2/9/4K4 b S 1",
+    "4k4/9/2+p1+p1+p2/2g3g2/9/2g3g2/2+p1+p1+p2/9/4K4 w s 1",
+    "k8/9/2S3S2/9/2S3S2/9/+B3+B3+R/9/4K3+R b - 1",
+  ] {
+    let mut pos = Position::parse_sfen(sfen).unwrap();
+    for m in perft::legal_moves(&mut pos, &mut allocator) {
+      let ki2 = pos.move_to_ki2(&mut allocator, &m, &None);
+      assert_eq!(
+        pos.parse_ki2_move(&mut allocator, &ki2, &None),
+        Some(m.clone()),
+        "{} in {}",
+        ki2,
+        pos
+      );
+    }
+  }
+}
+
+#[test]
+fn ki2_game() {
+  use tsumeshogi_check::shogi::game::GameResult;
+  use tsumeshogi_check::shogi::ki2::moves_to_ki2;
+  use tsumeshogi_check::shogi::kif::KIFBuilder;
+  let mut kb = KIFBuilder::default();
+  let s = "開始日時：2024/01/02
+先手：A
+後手：B
+▲７六歩 △３四歩 ▲２二角成 △同　銀 ▲４五角 △５二金右
+*両取り
+▲６三角成 △同　金
+まで8手で後手の勝ち
+";
+  let a: Vec<String> = s.lines().map(|l| l.to_owned()).collect();
+  let g = kb.parse_ki2_game(&a).unwrap();
+  assert_eq!(g.moves.len(), 8);
+  assert_eq!(g.moves[5].to_kif(&None), "５二金(61)");
+  assert!(matches!(g.result(), GameResult::WhiteWon));
+  assert_eq!(g.comments(6), vec!["両取り"]);
+  assert_eq!(kb.game_to_ki2(&g, None), s);
+  //KIF and KI2 of the same game
+  let t = kb.game_to_kif(&g, None);
+  let lines: Vec<String> = t.lines().skip(1).map(|l| l.to_owned()).collect();
+  let h = kb.parse_kif_game(&lines).unwrap();
+  assert_eq!(h.moves, g.moves);
+  //solution of tsume problem
+  let pos = Position::parse_sfen("7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l15p 1").unwrap();
+  let mut p = pos.clone();
+  let mut allocator = PositionMovesAllocator::default();
+  let mut pv = Vec::new();
+  let mut last_move = None;
+  for ki2 in ["３一角成", "同　玉", "３二金"] {
+    let m = p.parse_ki2_move(&mut allocator, ki2, &last_move).unwrap();
+    p.do_move(&m);
+    last_move = Some(m.clone());
+    pv.push(m);
+  }
+  assert_eq!(moves_to_ki2(&pos, &pv), "▲３一角成 △同　玉 ▲３二金");
+}
@@ -86,3 +86,406 @@ fn kif_moves() {
     last_move = Some(m);
   }
 }
+
+fn kif_lines(s: &str) -> Vec<String> {
+  s.lines().map(|l| l.to_owned()).collect()
+}
+
+#[test]
+fn kif_handicap() {
+  use tsumeshogi_check::shogi::game::GameResult;
+  use tsumeshogi_check::shogi::kif::{handicap_sfen, KIFBuilder};
+  let mut kb = KIFBuilder::default();
+  let a = kif_lines(
+    "手合割：二枚落ち
+先手：下手
+後手：上手
+手数----指手---------消費時間--
+   1 ６二銀(71)
+   2 ７六歩(77)
+   3 ５四歩(53)
+   4 投了",
+  );
+  let g = kb.parse_kif_game(&a).unwrap();
+  assert_eq!(g.moves.len(), 3);
+  assert!(matches!(g.result(), GameResult::WhiteWon));
+  let mut pos = g.start_position().unwrap();
+  assert_eq!(pos.side, -1);
+  assert_eq!(
+    pos.to_string(),
+    handicap_sfen("二枚落ち").unwrap().to_owned()
+  );
+  for m in &g.moves {
+    pos.do_move(m);
+    assert!(pos.is_legal());
+  }
+  let s = kb.game_to_kif(&g, None);
+  //skip #KIF line as kif_file_iterator() does
+  let h = kb.parse_kif_game(&kif_lines(&s)[1..].to_vec()).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert_eq!(
+    h.get_header(&"sfen".to_owned()),
+    g.get_header(&"sfen".to_owned())
+  );
+  let a = kif_lines("手合割：平手\n手数----指手---------消費時間--\n   1 ７六歩(77)");
+  let g = kb.parse_kif_game(&a).unwrap();
+  assert_eq!(g.moves.len(), 1);
+  assert!(g.get_header(&"sfen".to_owned()).is_none());
+  assert!(kb
+    .parse_kif_game(&kif_lines(
+      "手合割：九枚落ち\n手数----指手---------消費時間--"
+    ))
+    .is_err());
+  for name in [
+    "香落ち",
+    "右香落ち",
+    "角落ち",
+    "飛車落ち",
+    "飛香落ち",
+    "二枚落ち",
+    "三枚落ち",
+    "四枚落ち",
+    "五枚落ち",
+    "六枚落ち",
+    "七枚落ち",
+    "八枚落ち",
+    "十枚落ち",
+  ] {
+    let pos = Position::parse_sfen(handicap_sfen(name).unwrap()).unwrap();
+    assert!(pos.validate().is_empty(), "{}", name);
+  }
+}
+
+#[test]
+fn kif_board_diagram() {
+  use tsumeshogi_check::shogi::game::Game;
+  use tsumeshogi_check::shogi::kif::KIFBuilder;
+  let mut kb = KIFBuilder::default();
+  for sfen in [
+    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    "G1+R4nl/2l+B1+N3/7pp/pgkpp1s2/1P1n1Pp2/g3P2RP/5+p3/2p3s1K/+b6NL b G2sl6p 1",
+    "7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l10p 1",
+    "4k4/9/9/9/9/9/9/9/9 b 2r2b4g4s4n4l18p 1",
+  ] {
+    let pos = Position::parse_sfen(sfen).unwrap();
+    let mut g = Game::default();
+    g.set_header("sente".to_owned(), "A".to_owned());
+    let s = kb.game_to_kif(&g, Some(&pos));
+    let h = kb.parse_kif_game(&kif_lines(&s)[1..].to_vec()).unwrap();
+    assert_eq!(
+      h.start_position().unwrap().to_string(),
+      pos.to_string(),
+      "{}",
+      s
+    );
+    assert_eq!(h.get_header(&"sente".to_owned()).unwrap(), "A");
+    //header sfen is written back as board diagram
+    let t = kb.game_to_kif(&h, None);
+    assert_eq!(t, s);
+  }
+  //standard diagram of tsume collections: spaced empty cells, full-width hand separators
+  let a = kif_lines(
+    "後手の持駒：残り全部
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+| ・ ・ ・ ・ ・ ・ ・v桂v香|一
+| ・ ・ ・ ・ ・ 角 ・v玉 ・|二
+| ・ ・ ・ ・ ・ ・ 歩v歩v歩|三
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|四
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|五
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|六
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|七
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|八
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|九
++---------------------------+
+先手の持駒：飛　金
+手数----指手---------消費時間--
+   1 ３一角成(42)",
+  );
+  let g = kb.parse_kif_game(&a).unwrap();
+  let pos = Position::parse_sfen("7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l15p 1").unwrap();
+  assert_eq!(g.start_position().unwrap().to_string(), pos.to_string());
+  assert_eq!(g.moves.len(), 1);
+  let mut a = a.clone();
+  a[3] = "| ・ ・ ・ ・ ・ ・ ・v桂|一".to_owned();
+  assert!(kb.parse_kif_game(&a).is_err());
+  //mid-game diagram, moves are numbered from the move after the diagram
+  let pos =
+    Position::parse_sfen("lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 3")
+      .unwrap();
+  let s = kb
+    .game_to_kif(&Game::default(), Some(&pos))
+    .replace("手数＝2\n", "手数＝2  △３四歩(33)  まで\n");
+  let s = format!("{}   3 ２二角成(88)\n   4 同　銀(31)\n   5 投了\n", s);
+  let g = kb.parse_kif_game(&kif_lines(&s)[1..].to_vec()).unwrap();
+  assert_eq!(g.start_position().unwrap().to_string(), pos.to_string());
+  assert_eq!(g.moves.len(), 2);
+  assert!(matches!(
+    g.result(),
+    tsumeshogi_check::shogi::game::GameResult::WhiteWon
+  ));
+  let t = kb.game_to_kif(&g, None);
+  assert!(
+    t.contains("手数＝2\n") && t.contains("   5 投了\n"),
+    "{}",
+    t
+  );
+  let h = kb.parse_kif_game(&kif_lines(&t)[1..].to_vec()).unwrap();
+  assert_eq!(h.moves, g.moves);
+  //moves numbered from 1 don't follow the diagram
+  let s = s.replace("   3 ", "   1 ");
+  assert!(kb.parse_kif_game(&kif_lines(&s)[1..].to_vec()).is_err());
+}
+
+#[test]
+fn kif_move_times() {
+  use tsumeshogi_check::shogi::game::MoveTime;
+  use tsumeshogi_check::shogi::kif::KIFBuilder;
+  let mut kb = KIFBuilder::default();
+  let a = kif_lines(
+    "持ち時間：15分+60秒
+手数----指手---------消費時間--
+   1 ７六歩(77)   ( 0:01/00:00:01)
+   2 ３四歩(33)   ( 0:05/00:00:05)
+   3 ２二角成(88) ( 1:10/00:01:11)
+   4 同　銀(31)   ( 0:02/00:00:07)
+   5 ４五角打     (12:00/00:13:11)
+   6 投了         ( 0:03/00:00:10)",
+  );
+  let g = kb.parse_kif_game(&a).unwrap();
+  assert_eq!(g.moves.len(), 5);
+  assert_eq!(
+    g.times[2],
+    MoveTime {
+      elapsed: 70,
+      total: 71
+    }
+  );
+  assert_eq!(g.times[4].total, 791);
+  assert_eq!(g.base_time(), Some(900));
+  assert_eq!(g.time_used_before(4), Some(71));
+  assert_eq!(g.time_left_before(4), Some(829));
+  let s = kb.game_to_kif(&g, None);
+  assert!(s.contains("   3 ２二角成(88) ( 1:10/00:01:11)\n"), "{}", s);
+  assert!(s.contains("   5 ４五角打     (12:00/00:13:11)\n"), "{}", s);
+  let h = kb.parse_kif_game(&kif_lines(&s)[1..].to_vec()).unwrap();
+  assert_eq!(h.times, g.times);
+  assert_eq!(h.moves, g.moves);
+  //times are dropped if some moves don't have them
+  let mut a = a.clone();
+  a[3] = "   2 ３四歩(33)".to_owned();
+  let g = kb.parse_kif_game(&a).unwrap();
+  assert_eq!(g.moves.len(), 5);
+  assert!(g.times.is_empty());
+  assert_eq!(g.time_used_before(0), None);
+}
+
+#[test]
+fn kif_variations() {
+  use tsumeshogi_check::shogi::kif::KIFBuilder;
+  let mut kb = KIFBuilder::default();
+  let s = "手数----指手---------消費時間--
+   1 ７六歩(77)
+   2 ３四歩(33)+
+   3 ２六歩(27)+
+   4 ８四歩(83)
+   5 投了
+
+変化：3手
+   3 ６六歩(67)
+   4 ８四歩(83)+
+   5 ６八銀(79)
+
+変化：4手
+   4 ４四歩(43)
+
+変化：2手
+   2 ８四歩(83)
+   3 ２六歩(27)
+";
+  let g = kb.parse_kif_game(&kif_lines(s)).unwrap();
+  assert_eq!(g.moves.len(), 4);
+  assert_eq!(g.variations.len(), 2);
+  let v = &g.variations[0];
+  assert_eq!((v.start, v.moves.len(), v.variations.len()), (2, 3, 1));
+  assert_eq!(v.variations[0].start, 3);
+  assert_eq!(v.variations[0].moves.len(), 1);
+  assert_eq!(g.variations[1].start, 1);
+  let lines = g.lines();
+  assert_eq!(lines.len(), 4);
+  assert_eq!(lines[0], g.moves);
+  let mut last_move = None;
+  let kif: Vec<String> = lines[2]
+    .iter()
+    .map(|m| {
+      let s = m.to_kif(&last_move);
+      last_move = Some(m.clone());
+      s
+    })
+    .collect();
+  assert_eq!(
+    kif,
+    vec!["７六歩(77)", "３四歩(33)", "６六歩(67)", "４四歩(43)"]
+  );
+  assert_eq!(lines[3].len(), 3);
+  let t = kb.game_to_kif(&g, None);
+  assert_eq!(t, format!("#KIF version=2.0 encoding=UTF-8\n{}", s));
+  //variation of the first move
+  let g = kb
+    .parse_kif_game(&kif_lines(
+      "手数----指手---------消費時間--\n   1 ７六歩(77)+\n変化：1手\n   1 ２六歩(27)",
+    ))
+    .unwrap();
+  assert_eq!(g.lines()[1].len(), 1);
+  assert!(kb
+    .parse_kif_game(&kif_lines(
+      "手数----指手---------消費時間--\n   1 ７六歩(77)\n変化：3手\n   3 ２六歩(27)"
+    ))
+    .is_err());
+}
+
+#[test]
+fn kif_lossless() {
+  use tsumeshogi_check::shogi::game::Annotation;
+  use tsumeshogi_check::shogi::kif::KIFBuilder;
+  let mut kb = KIFBuilder::default();
+  let s = "# ---- Kifu for Windows V7 V7.70 棋譜ファイル ----
+開始日時：2024/01/02 10:00:00
+終了日時：2024/01/02 11:00:00
+棋戦：練習対局
+手合割：平手
+先手：A
+後手：B
+*対局前のコメント
+手数----指手---------消費時間--
+   1 ７六歩(77)   ( 0:01/00:00:01)
+*角道を開ける
+&序盤
+   2 ３四歩(33)   ( 0:02/00:00:02)+
+   3 投了
+*まで2手で後手の勝ち
+
+変化：2手
+   2 ８四歩(83)   ( 0:05/00:00:05)
+*居飛車
+";
+  let mut g = kb.parse_kif_game(&kif_lines(s)).unwrap();
+  assert_eq!(
+    g.get_header(&"kif:終了日時".to_owned()).unwrap(),
+    "2024/01/02 11:00:00"
+  );
+  assert_eq!(g.get_header(&"event".to_owned()).unwrap(), "練習対局");
+  assert_eq!(g.comments(0), vec!["対局前のコメント"]);
+  assert_eq!(g.comments(1), vec!["角道を開ける"]);
+  assert_eq!(
+    g.annotations[&1][1],
+    Annotation::Bookmark("序盤".to_owned())
+  );
+  assert_eq!(g.comments(3), vec!["まで2手で後手の勝ち"]);
+  assert_eq!(g.variations[0].annotations[&2].len(), 1);
+  assert_eq!(
+    kb.game_to_kif(&g, None),
+    format!("#KIF version=2.0 encoding=UTF-8\n{}", s)
+  );
+  g.add_comment(2, "added".to_owned());
+  let t = kb.game_to_kif(&g, None);
+  assert!(
+    t.contains("( 0:02/00:00:02)+\n*added\n   3 投了\n"),
+    "{}",
+    t
+  );
+  let h = kb.parse_kif_game(&kif_lines(&t)[1..].to_vec()).unwrap();
+  assert_eq!(h.annotations, g.annotations);
+  assert_eq!(h.header, g.header);
+  //timeout line after the last move
+  let s = "手合割：平手
+手数----指手---------消費時間--
+   1 ７六歩(77)
+*時間切れにて終局
+";
+  let g = kb.parse_kif_game(&kif_lines(s)).unwrap();
+  assert!(matches!(
+    g.result(),
+    tsumeshogi_check::shogi::game::GameResult::BlackWon
+  ));
+  assert_eq!(
+    kb.game_to_kif(&g, None),
+    format!("#KIF version=2.0 encoding=UTF-8\n{}", s)
+  );
+}
+
+#[test]
+fn kif_file_splitting() {
+  use tsumeshogi_check::shogi::game::GameResult;
+  use tsumeshogi_check::shogi::kif::{kif_file_iterator, KIFBuilder};
+  let dir = std::env::temp_dir().join(format!("kif_splitting{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  std::fs::write(
+    dir.join("a.kif"),
+    "# ----  Kifu for Windows V7 棋譜ファイル  ----
+開始日時：2024/01/02
+手合割：平手
+先手：A
+後手：B
+手数----指手---------消費時間--
+   1 ７六歩(77)
+   2 ３四歩(33)+
+   3 中断
+まで2手で中断
+
+変化：2手
+   2 ８四歩(83)
+開始日時：2024/01/03
+先手：C
+後手：D
+手数----指手---------消費時間--
+   1 ２六歩(27)
+   2 投了
+まで1手で先手の勝ち
+",
+  )
+  .unwrap();
+  std::fs::write(
+    dir.join("b.kifu"),
+    "#KIF version=2.0 encoding=UTF-8
+先手：E
+手数----指手---------消費時間--
+   1 ７六歩(77)
+   2 切れ負け
+#KIF version=2.0 encoding=UTF-8
+先手：F
+手数----指手---------消費時間--
+   1 ５六歩(57)
+   2 ５四歩(53)
+   3 千日手
+",
+  )
+  .unwrap();
+  std::fs::write(dir.join("c.txt"), "not a game").unwrap();
+  let games: Vec<_> = kif_file_iterator(dir.to_str().unwrap())
+    .unwrap()
+    .map(|a| a.unwrap())
+    .collect();
+  std::fs::remove_dir_all(&dir).unwrap();
+  assert_eq!(games.len(), 4, "{:?}", games);
+  let mut kb = KIFBuilder::default();
+  let games: Vec<_> = games
+    .iter()
+    .map(|a| kb.parse_kif_game(a).unwrap())
+    .collect();
+  let sente: Vec<_> = games
+    .iter()
+    .map(|g| g.get_header(&"sente".to_owned()).unwrap().as_str())
+    .collect();
+  assert_eq!(sente, vec!["A", "C", "E", "F"]);
+  assert_eq!(games[0].moves.len(), 2);
+  assert_eq!(games[0].variations.len(), 1);
+  assert!(matches!(games[0].result(), GameResult::Unknown));
+  assert!(matches!(games[1].result(), GameResult::BlackWon));
+  assert!(matches!(games[2].result(), GameResult::BlackWon));
+  assert_eq!(
+    games[3].get_header(&"termination".to_owned()).unwrap(),
+    "千日手"
+  );
+  assert!(kb.game_to_kif(&games[3], None).ends_with("   3 千日手\n"));
+}
@@ -0,0 +1,92 @@
+use tsumeshogi_check::shogi::moves::Move;
+use tsumeshogi_check::shogi::packed::{PackedSFENReader, PackedSFENWriter, Payload};
+use tsumeshogi_check::shogi::usi::parse_usi_position;
+use tsumeshogi_check::shogi::Position;
+
+#[test]
+fn packed_sfen() {
+  let pos = Position::default();
+  let data = pos.to_packed_sfen().unwrap();
+  //black to move, king on 5i (square 44), white king on 5a (square 36), white lance on 1a
+  assert_eq!(data[..2], [0x58, 0xa4]);
+  let p = Position::from_packed_sfen(&data).unwrap();
+  assert_eq!(p.to_string(), pos.to_string());
+  for sfen in [
+    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    "8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 1",
+    "9/4k4/9/4P4/9/9/9/9/9 b 2G2r2b2g4s4n4l17p 1",
+    //without kings
+    "9/9/9/9/9/9/9/9/9 b 2R2B4G4S4N4L18P 1",
+  ] {
+    let pos = Position::parse_sfen(sfen).unwrap();
+    let p = Position::from_packed_sfen(&pos.to_packed_sfen().unwrap()).unwrap();
+    assert_eq!(p.to_string(), pos.to_string());
+    assert_eq!(p.hash, pos.hash);
+  }
+  //missing pieces are in the hand of the side which isn't to move (tsume)
+  for (sfen, expected) in [
+    (
+      "3sks3/9/4S4/9/9/8B/9/9/9 b S 1",
+      "3sks3/9/4S4/9/9/8B/9/9/9 b S2rb4g4n4l18p 1",
+    ),
+    (
+      "4k4/9/9/9/9/9/4p4/9/4K4 w g 1",
+      "4k4/9/9/9/9/9/4p4/9/4K4 w 2R2B3G4S4N4L17Pg 1",
+    ),
+  ] {
+    let pos = Position::parse_sfen(sfen).unwrap();
+    let p = Position::from_packed_sfen(&pos.to_packed_sfen().unwrap()).unwrap();
+    assert_eq!(
+      p.to_string(),
+      Position::parse_sfen(expected).unwrap().to_string()
+    );
+  }
+  let mut data = [0xff; 32];
+  assert!(Position::from_packed_sfen(&data).is_err());
+  data[0] = 0;
+  assert!(Position::from_packed_sfen(&data).is_err());
+}
+
+#[test]
+fn packed_sfen_streams() {
+  let (pos, moves) = parse_usi_position("startpos moves 7g7f 3c3d 8h2b+").unwrap();
+  let mut a = Vec::new();
+  let mut w = PackedSFENWriter::new(&mut a, true);
+  let payload = Payload {
+    best_move: u32::from(&moves[2]),
+    dm: 0,
+    move_no: 3,
+  };
+  let mut p = pos.clone();
+  p.do_move(&moves[0]);
+  p.do_move(&moves[1]);
+  w.write(&p, Some(&payload)).unwrap();
+  w.write(&pos, None).unwrap();
+  w.flush().unwrap();
+  assert_eq!(a.len(), 80);
+  //corrupted record is skipped
+  a.extend_from_slice(&[0xff; 40]);
+  a.extend_from_within(..40);
+  let r: Vec<_> = PackedSFENReader::new(a.as_slice(), true).collect();
+  assert_eq!(r.len(), 4);
+  let (q, x) = r[0].as_ref().unwrap();
+  assert_eq!(q.to_string(), p.to_string().replace(" 1", " 3"));
+  assert_eq!(Move::from(x.unwrap().best_move), moves[2]);
+  assert_eq!(r[1].as_ref().unwrap().1, Some(Payload::default()));
+  assert_eq!(r[1].as_ref().unwrap().0.move_no, 1);
+  assert_eq!(
+    r[2].as_ref().err().unwrap().kind(),
+    std::io::ErrorKind::InvalidData
+  );
+  assert!(r[3].is_ok());
+  //records without payload, truncated record
+  let mut a = Vec::new();
+  let mut w = PackedSFENWriter::new(&mut a, false);
+  w.write(&pos, Some(&payload)).unwrap();
+  assert_eq!(a.len(), 32);
+  a.push(0);
+  let r: Vec<_> = PackedSFENReader::new(a.as_slice(), false).collect();
+  assert_eq!(r.len(), 2);
+  assert!(r[0].as_ref().unwrap().1.is_none());
+  assert!(r[1].is_err());
+}
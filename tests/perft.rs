@@ -1,4 +1,4 @@
-use tsumeshogi_check::shogi::{alloc::PositionMovesAllocator, Position};
+use tsumeshogi_check::shogi::{alloc::PositionMovesAllocator, perft, Position};
 
 fn rec(pos: &mut Position, allocator: &mut PositionMovesAllocator, depth: usize) -> u32 {
   if depth == 0 {
@@ -24,3 +24,48 @@ fn perft() {
   let mut allocator = PositionMovesAllocator::default();
   assert_eq!(rec(&mut pos, &mut allocator, 4), 719731);
 }
+
+#[test]
+fn perft_counts() {
+  let mut pos = Position::default();
+  let c = perft::perft(&mut pos, 3);
+  assert_eq!(
+    c,
+    perft::PerftCounts {
+      nodes: 25470,
+      moves: 25470,
+      drops: 0,
+      captures: 59,
+      promotions: 30,
+      checks: 48,
+    }
+  );
+  let mut total = perft::PerftCounts::default();
+  let divide = perft::divide(&mut pos, 3);
+  assert_eq!(divide.len(), 30);
+  for (_, c) in &divide {
+    total += *c;
+  }
+  assert_eq!(total, c);
+  let mut pos = Position::parse_sfen("4k4/9/9/9/9/9/9/9/4K4 b G 1").unwrap();
+  let c = perft::perft(&mut pos, 1);
+  assert_eq!((c.moves, c.drops, c.checks), (5, 79, 5));
+}
+
+#[test]
+fn perft_expected() {
+  let mut pos = Position::default();
+  let divide = perft::divide(&mut pos, 2);
+  let mut s = String::from("# startpos, depth 2\n\n");
+  for (m, c) in &divide {
+    s.push_str(&format!("{} {}\n", m, c.nodes));
+  }
+  let expected = perft::parse_expected(&s).unwrap();
+  assert!(perft::compare(&divide, &expected).is_empty());
+  let (m, _) = &divide[0];
+  let s = s.replace(&format!("{} 30", m), &format!("{} 31", m)) + "P1a-1b 1\n";
+  let expected = perft::parse_expected(&s).unwrap();
+  assert_eq!(perft::compare(&divide, &expected).len(), 2);
+  assert!(perft::parse_expected("P7g-7f").is_err());
+  assert!(perft::parse_expected("P7g-7f x").is_err());
+}
@@ -1,3 +1,5 @@
+use tsumeshogi_check::shogi::game::{Annotation, Game};
+use tsumeshogi_check::shogi::pgn::{game_to_pgn, parse_pgn_game};
 use tsumeshogi_check::shogi::Position;
 
 #[test]
@@ -11,3 +13,122 @@ fn pgn_moves() {
     assert!(pos.is_legal());
   }
 }
+
+#[test]
+fn pgn_game() {
+  let a: Vec<String> = [
+    "[Event \"test\"]",
+    "[P1 \"Black\"]",
+    "[P2 \"White\"]",
+    "[P1Elo \"1800\"]",
+    "[Result \"1-0\"]",
+    "",
+    "1. d1d2 b7b6 2. c3c4 g7g6 3. f1f2 b6b5 4. b2c3 c9d8 5. e3e4 c7c6 6. c1c2 d8c7",
+    "7. h3h4 a7a6 8. f2e3 c7d6 9. h4h5 1-0",
+  ]
+  .iter()
+  .map(|s| s.to_string())
+  .collect();
+  let g = parse_pgn_game(&a).unwrap();
+  assert_eq!(g.header.get("sente").unwrap(), "Black");
+  assert_eq!(g.header.get("senteelo").unwrap(), "1800");
+  let s = game_to_pgn(&g, None);
+  assert_eq!(
+    s,
+    [
+      "[Event \"test\"]",
+      "[P1 \"Black\"]",
+      "[P2 \"White\"]",
+      "[Result \"1-0\"]",
+      "[P1Elo \"1800\"]",
+      "",
+      "1. d1d2 b7b6 2. c3c4 g7g6 3. f1f2 b6b5 4. b2c3 c9d8 5. e3e4 c7c6 6. c1c2 d8c7",
+      "7. h3h4 a7a6 8. f2e3 c7d6 9. h4h5 1-0",
+      "",
+      "",
+    ]
+    .join("\n")
+  );
+  let a: Vec<String> = s.lines().map(|s| s.to_string()).collect();
+  let h = parse_pgn_game(&a).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert_eq!(h.header, g.header);
+  //unfinished game from white move
+  let mut pos = Position::default();
+  let m = pos.parse_pgn_move("d1d2").unwrap();
+  pos.do_move(&m);
+  let mut g = Game::default();
+  g.set_header(String::from("sfen"), pos.to_string());
+  g.moves.push(pos.parse_pgn_move("b7b6").unwrap());
+  let s = game_to_pgn(&g, None);
+  assert!(
+    s.contains(&format!("[FEN \"{}\"]\n\n1... b7b6 *\n\n", pos)),
+    "{}",
+    s
+  );
+  let a: Vec<String> = s.lines().map(|s| s.to_string()).collect();
+  let h = parse_pgn_game(&a).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert_eq!(h.header.get("sfen"), g.header.get("sfen"));
+}
+
+#[test]
+fn pgn_annotations() {
+  let a: Vec<String> = [
+    "[Event \"annotated\"]",
+    "",
+    "{Opening} 1. d1d2 $1 {Good move,",
+    "solid} (1. c3c4 b7b6 (1... g7g6 $2) 2. h3h4) 1... b7b6 ; side pawn",
+    "% escaped line",
+    "2.c3c4 g7g6 $14 3. f1f2 (3. b2c3 {bishop}) 1-0",
+  ]
+  .iter()
+  .map(|s| s.to_string())
+  .collect();
+  let g = parse_pgn_game(&a).unwrap();
+  assert_eq!(g.moves.len(), 5);
+  assert_eq!(g.comments(0), vec!["Opening"]);
+  assert_eq!(g.annotations.get(&1).unwrap()[0], Annotation::Nag(1));
+  assert_eq!(g.comments(1), vec!["Good move, solid"]);
+  assert_eq!(g.comments(2), vec!["side pawn"]);
+  assert_eq!(g.annotations.get(&4).unwrap(), &vec![Annotation::Nag(14)]);
+  assert_eq!(g.variations.len(), 2);
+  let v = &g.variations[0];
+  assert_eq!(v.start, 0);
+  assert_eq!(v.moves.len(), 3);
+  assert_eq!(v.variations[0].start, 1);
+  assert_eq!(
+    v.variations[0].annotations.get(&2).unwrap(),
+    &vec![Annotation::Nag(2)]
+  );
+  assert_eq!(g.variations[1].start, 4);
+  assert_eq!(
+    g.variations[1].annotations.get(&5).unwrap(),
+    &vec![Annotation::Comment(String::from("bishop"))]
+  );
+  assert_eq!(g.lines().len(), 4);
+  let s = game_to_pgn(&g, None);
+  assert!(s.contains(
+    "{Opening} 1. d1d2 $1 {Good move, solid} (1. c3c4 b7b6 (1... g7g6 $2) 2. h3h4)\n1... b7b6 {side pawn} 2. c3c4 g7g6 $14 3. f1f2 (3. b2c3 {bishop}) 1-0\n"
+  ), "{}", s);
+  let a: Vec<String> = s.lines().map(|s| s.to_string()).collect();
+  let h = parse_pgn_game(&a).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert_eq!(h.annotations, g.annotations);
+  assert_eq!(h.variations, g.variations);
+  for t in [
+    "1. d1d2 {open",
+    "(1. d1d2) b7b6",
+    "1. d1d2 (1. c3c4",
+    "1. d1d2 b7b6)",
+    "1. d1d2 $x",
+    "1. d1d2 1-0 b7b6",
+  ] {
+    let a = vec![
+      String::from("[SFEN \"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1\"]"),
+      String::new(),
+      String::from(t),
+    ];
+    assert!(parse_pgn_game(&a).is_err(), "{}", t);
+  }
+}
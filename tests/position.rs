@@ -30,3 +30,54 @@ fn unpromoted_pieces_in_parse_sfen() {
     );
   }
 }
+
+#[test]
+fn validate() {
+  use tsumeshogi_check::shogi::validation::Problem;
+  let pos = Position::parse_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1")
+    .unwrap();
+  assert!(pos.validate().is_empty());
+  let pos = Position::parse_sfen("9/9/9/9/4k4/9/9/9/9 w G 1").unwrap();
+  assert_eq!(pos.validate(), vec![Problem::MissingKing { side: 1 }]);
+  let pos = Position::parse_sfen("4k4/9/9/9/9/9/9/9/4K4 b - 1").unwrap();
+  assert!(pos.validate().is_empty());
+  let pos = Position::parse_sfen("k8/9/9/9/9/9/9/1+R+B6/4K4 b - 3").unwrap();
+  assert_eq!(
+    pos.validate(),
+    vec![Problem::TooManyPromotedPieces {
+      side: 1,
+      count: 2,
+      moves: 1
+    }]
+  );
+  for (sfen, problem) in [
+    (
+      "4k4/9/4P4/4P4/9/9/9/9/9 b - 1",
+      Problem::Nifu { side: 1, col: 5 },
+    ),
+    (
+      "4k3P/9/9/9/9/9/9/9/9 b - 1",
+      Problem::DeadSquare { piece: 1, cell: 0 },
+    ),
+    (
+      "4k4/9/9/9/9/9/9/9/9 b 19P 1",
+      Problem::TooManyPieces {
+        piece: 1,
+        count: 19,
+        expected: 18,
+      },
+    ),
+    (
+      "4k4/9/9/9/9/9/9/9/k8 b - 1",
+      Problem::TooManyKings { side: -1 },
+    ),
+    (
+      "4k4/4G4/9/9/9/9/9/9/9 b - 1",
+      Problem::KingInCheck { side: -1 },
+    ),
+  ] {
+    let e = Position::parse_sfen(sfen).err().unwrap();
+    assert_eq!(e.problems, vec![problem.clone()], "sfen: {}", sfen);
+    assert_eq!(e.message, problem.to_string());
+  }
+}
@@ -0,0 +1,72 @@
+use tsumeshogi_check::search::Search;
+use tsumeshogi_check::shogi::game::{Game, GameResult};
+use tsumeshogi_check::shogi::psn::{game_to_psn, parse_psn_game, PSNFileIterator};
+use tsumeshogi_check::shogi::usi::parse_usi_position;
+
+#[test]
+fn psn_game() {
+  //bishop exchange and drop
+  let (_, moves) = parse_usi_position("startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e").unwrap();
+  let mut g = Game {
+    moves,
+    ..Game::default()
+  };
+  g.set_header(String::from("sente"), String::from("Black"));
+  g.set_header(String::from("gote"), String::from("White \"W\""));
+  g.set_header(String::from("senteelo"), String::from("1800"));
+  g.set_header(String::from("result"), String::from("2"));
+  g.set_header(String::from("resignation"), String::from("true"));
+  let s = game_to_psn(&g, None);
+  let a: Vec<String> = s.lines().map(|s| s.to_string()).collect();
+  assert_eq!(a[0], "[Sente \"Black\"]");
+  assert_eq!(a[1], "[Gote \"White 'W'\"]");
+  assert_eq!(a[2], "[Result \"1-0\"]");
+  assert_eq!(a[3], "[SenteElo \"1800\"]");
+  assert_eq!(a[8], "5.B'4e");
+  assert_eq!(a[9], "--Resigns--");
+  let h = parse_psn_game(&a).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert!(matches!(h.result(), GameResult::BlackWon));
+  assert_eq!(h.header.get("termination").unwrap(), "Resigns");
+}
+
+#[test]
+fn psn_problems() {
+  let it = PSNFileIterator::new("tests/data/problems.psn").unwrap();
+  let games: Vec<Game> = it.map(|a| parse_psn_game(&a.unwrap()).unwrap()).collect();
+  assert_eq!(games.len(), 3);
+  assert!(games[0].moves.is_empty());
+  assert_eq!(games[1].start_position().unwrap().side, -1);
+  assert_eq!(games[2].header.get("sente").unwrap(), "Solver");
+  assert_eq!(games[2].moves.len(), 3);
+  assert_eq!(games[2].header.get("termination").unwrap(), "Checkmate");
+  let mut s = Search::new(16 << 20);
+  let mut pos = games[0].start_position().unwrap();
+  let (res, pv) = s.search(&mut pos, 3);
+  assert_eq!(res, Some(3));
+  //puzzle output keeps the start position
+  let pos = games[0].start_position().unwrap();
+  let g = Game {
+    moves: pv.unwrap(),
+    ..Game::default()
+  };
+  let t = game_to_psn(&g, Some(&pos));
+  assert!(
+    t.starts_with("[SFEN \"3sks3/9/4S4/9/9/8B/9/9/9 b S 1\"]\n1.B1f-5b+\n"),
+    "{}",
+    t
+  );
+  let a: Vec<String> = t.lines().map(|s| s.to_string()).collect();
+  let h = parse_psn_game(&a).unwrap();
+  assert_eq!(h.moves, g.moves);
+  assert_eq!(h.start_position().unwrap().to_string(), pos.to_string());
+  //move numbers of the SFEN
+  let t = game_to_psn(&games[2], None)
+    .replace(" 1\"]", " 5\"]")
+    .replace("1.G", "5.G");
+  let t = t.replace("2.K", "6.K").replace("3.G", "7.G");
+  let a: Vec<String> = t.lines().map(|s| s.to_string()).collect();
+  assert_eq!(parse_psn_game(&a).unwrap().moves, games[2].moves);
+  let a = vec![String::from("[SFEN \"9/9 b - 1\"]")];
+  assert!(parse_psn_game(&a).is_err());
+}
@@ -0,0 +1,58 @@
+use tsumeshogi_check::differential::{shrink, Harness};
+use tsumeshogi_check::shogi::{piece, Position};
+
+fn check(sfen: &str, seed: u64, games: usize, plies: usize) {
+  let mut h = Harness::new(seed);
+  let pos = Position::parse_sfen(sfen).unwrap();
+  for _ in 0..games {
+    if let Some((p, e)) = h.walk(&pos, plies) {
+      let m = h.shrink(&p);
+      panic!(
+        "generators disagree in {}: {:?}, minimal sfen: {}, {:?}",
+        p,
+        e,
+        m,
+        h.compare(&mut m.clone())
+      );
+    }
+  }
+}
+
+#[test]
+fn reference_startpos() {
+  check(
+    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+    1,
+    1,
+    24,
+  );
+}
+
+#[test]
+fn reference_tsume() {
+  for (seed, sfen) in [
+    "G1+R4nl/2l+B1+N3/7pp/pgkpp1s2/1P1n1Pp2/g3P2RP/5+p3/2p3s1K/+b6NL b G2sl6p 1",
+    "lnn5l/2g1S1+Bp1/bp1pk3p/pP1g2p2/4s4/P1R2PP1P/2KP2g2/2S2+r3/LN1G4L b P5pns 1",
+    "7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l15p 1",
+  ]
+  .iter()
+  .enumerate()
+  {
+    check(sfen, seed as u64 + 1, 1, 8);
+  }
+}
+
+#[test]
+fn reference_shrink() {
+  let mut h = Harness::new(1);
+  let pos = Position::parse_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1")
+    .unwrap();
+  assert!(h.compare(&mut pos.clone()).is_empty());
+  //no disagreement, nothing to remove
+  assert_eq!(h.shrink(&pos).to_string(), pos.to_string());
+  //injected disagreement in positions with black rook: every other piece except kings is removed
+  let has_rook = |q: &mut Position| (0..81).any(|k| q.piece_at(k) == piece::ROOK);
+  let m = shrink(&pos, has_rook);
+  assert_eq!(m.to_string(), "4k4/9/9/9/9/9/9/7R1/4K4 b - 1");
+  assert!(has_rook(&mut m.clone()));
+}
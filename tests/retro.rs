@@ -0,0 +1,85 @@
+use shogi::{alloc::PositionMovesAllocator, moves::Move, Position};
+use tsumeshogi_check::shogi;
+
+fn legal_moves(pos: &mut Position, allocator: &mut PositionMovesAllocator) -> Vec<Move> {
+  let checks = pos.compute_checks();
+  let moves = pos.compute_moves(&checks);
+  let drops = pos.compute_drops(allocator, &checks);
+  let mut r = Vec::new();
+  for m in moves.into_iter().chain(drops) {
+    let u = pos.do_move(&m);
+    if pos.is_legal() {
+      r.push(m.clone());
+    }
+    pos.undo_move(&m, &u);
+  }
+  r
+}
+
+fn without_move_no(pos: &Position) -> String {
+  let s = pos.to_string();
+  s.rsplit_once(' ').unwrap().0.to_owned()
+}
+
+#[test]
+fn retro_moves() {
+  let mut allocator = PositionMovesAllocator::default();
+  for sfen in [
+    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+    "G1+R4nl/2l+B1+N3/7pp/pgkpp1s2/1P1n1Pp2/g3P2RP/5+p3/2p3s1K/+b6NL b G2sl6p 1",
+    "lnn5l/2g1S1+Bp1/bp1pk3p/pP1g2p2/4s4/P1R2PP1P/2KP2g2/2S2+r3/LN1G4L b P5pns 1",
+    "l2+R4+P/1+B3g3/3Bks2n/p1pppppSp/3n3p1/2s2PP1P/P2PP4/1+n2KG1R1/L5sNL b 2PLGpg 1",
+    "9/2B6/3G2r2/3pkL1R1/3gsg3/3S2B2/3g5/4N4/4L4 b 2s3n2l17p 1",
+  ] {
+    let mut pos = Position::parse_sfen(sfen).unwrap();
+    for _ in 0..2 {
+      for m in legal_moves(&mut pos, &mut allocator) {
+        let u = pos.do_move(&m);
+        let retro = pos.compute_retro_moves(true);
+        assert!(
+          retro
+            .iter()
+            .any(|(r, p)| *r == m && p.to_string() == pos_before(&pos, &m, &u)),
+          "move {:?} isn't found in retro moves, sfen: {}",
+          m,
+          pos
+        );
+        for (r, mut p) in retro {
+          assert!(p.validate_move(&r), "{:?}, {}", r, p);
+          let _ = p.do_move(&r);
+          assert!(p.is_legal());
+          assert_eq!(without_move_no(&p), without_move_no(&pos));
+          assert_eq!(p.hash, pos.hash);
+        }
+        pos.undo_move(&m, &u);
+      }
+      pos.swap_sides();
+    }
+  }
+}
+
+fn pos_before(pos: &Position, m: &Move, u: &shogi::moves::UndoMove) -> String {
+  let mut p = pos.clone();
+  p.undo_move(m, u);
+  p.to_string()
+}
+
+#[test]
+fn retro_generator() {
+  use tsumeshogi_check::generator::retro::RetroGenerator;
+  use tsumeshogi_check::search::Search;
+  let mut s = Search::new(16 << 20);
+  let mut gen = RetroGenerator::new(true);
+  let mated = Position::parse_sfen("k8/1G7/1K7/9/9/9/9/9/9 w 2r2b3g4s4n4l18p 1").unwrap();
+  let v = gen.search(&mut s, &mated, 1);
+  assert_eq!(v.len(), 2);
+  let mut puzzles = 0;
+  for p in v {
+    for q in gen.search(&mut s, &p.pos, 3) {
+      let mut pos = q.pos.clone();
+      assert_eq!(s.search(&mut pos, 3), (Some(3), Some(q.pv)));
+      puzzles += 1;
+    }
+  }
+  assert!(puzzles > 0);
+}
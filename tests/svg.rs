@@ -0,0 +1,30 @@
+use tsumeshogi_check::shogi::alloc::PositionMovesAllocator;
+use tsumeshogi_check::shogi::svg::{mate_squares, position_to_svg};
+use tsumeshogi_check::shogi::Position;
+
+#[test]
+fn svg_diagram() {
+  let pos = Position::default();
+  let s = position_to_svg(&pos, None, &[]);
+  assert!(s.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+  assert!(s.ends_with("</svg>\n"));
+  //40 pieces, 18 coordinates and 2 hands
+  assert_eq!(s.matches("<text").count(), 60);
+  assert_eq!(s.matches("rotate(180").count(), 20);
+  assert!(s.contains(">１</text>") && s.contains(">九</text>"));
+  assert!(s.contains("☖後手 持駒 なし"));
+  assert!(s.contains(r#"font-weight="bold" text-anchor="end">☗先手 持駒 なし"#));
+  //last move (drop) and mate squares
+  let mut allocator = PositionMovesAllocator::default();
+  let mut pos = Position::parse_sfen("4k4/9/4P4/9/9/9/9/9/9 b 2G2r2b2g4s4n4l17p 1").unwrap();
+  let m = pos.parse_usi_move(&mut allocator, "G*5b").unwrap();
+  assert!(mate_squares(&pos).is_empty());
+  pos.do_move(&m);
+  let marks = mate_squares(&pos);
+  assert_eq!(marks.len(), 2);
+  let s = position_to_svg(&pos, Some(&m), &marks);
+  assert_eq!(s.matches("fill=\"#f7e07b\"").count(), 1);
+  assert_eq!(s.matches("fill=\"#f2a0a0\"").count(), 2);
+  assert!(s.contains("☗先手 持駒 金"));
+  assert!(s.contains("☖後手 持駒 飛二 角二 金二 銀四 桂四 香四 歩十七"));
+}
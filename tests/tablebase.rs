@@ -0,0 +1,81 @@
+use std::str::FromStr;
+use tsumeshogi_check::generator::Material;
+use tsumeshogi_check::search::Search;
+use tsumeshogi_check::tablebase::{Probe, Tablebase};
+
+fn build(pieces: &str, hand: &str, radius: u8, depth: u8) -> Tablebase {
+  let pieces = Material::from_str(pieces).unwrap();
+  let hand = Material::from_str(hand).unwrap();
+  let mut tb = Tablebase::new(&pieces, &hand, radius, depth).unwrap();
+  let mut s = Search::new(1 << 20);
+  assert!(tb.generate(&mut s) > 0);
+  tb
+}
+
+#[test]
+fn invalid_material() {
+  let m = |s| Material::from_str(s).unwrap();
+  assert!(Tablebase::new(&m("-"), &m("G"), 1, 3).is_err());
+  assert!(Tablebase::new(&m("RBGS"), &m("-"), 1, 3).is_err());
+  assert!(Tablebase::new(&m("3G"), &m("2G"), 1, 3).is_err());
+  assert!(Tablebase::new(&m("G"), &m("G"), 1, 4).is_err());
+}
+
+#[test]
+fn index_and_probe() {
+  let tb = build("G", "G", 1, 3);
+  let mut s = Search::new(1 << 20);
+  let mut mates = 0;
+  for i in 0..tb.len() {
+    let Some(mut pos) = tb.position(i) else {
+      continue;
+    };
+    assert_eq!(tb.index(&pos), Some(i), "sfen: {}", pos);
+    let (res, pv) = s.search(&mut pos, tb.depth);
+    s.hashes_clear();
+    let expected = match res {
+      Some(d) => {
+        mates += 1;
+        Probe::Mate(d, pv.is_some())
+      }
+      None => Probe::NoMate,
+    };
+    assert_eq!(tb.probe(&pos), Some(expected), "sfen: {}", pos);
+  }
+  assert!(mates > 0);
+}
+
+#[test]
+fn save_load() {
+  let tb = build("G", "G", 1, 3);
+  let mut buf = Vec::new();
+  tb.save(&mut buf).unwrap();
+  let tb2 = Tablebase::load(&mut buf.as_slice()).unwrap();
+  assert_eq!(tb2.depth, tb.depth);
+  assert_eq!(tb2.len(), tb.len());
+  for i in 0..tb.len() {
+    if let Some(pos) = tb.position(i) {
+      assert_eq!(tb2.probe(&pos), tb.probe(&pos));
+    }
+  }
+  buf[0] = b'X';
+  assert!(Tablebase::load(&mut buf.as_slice()).is_err());
+}
+
+#[test]
+fn search_with_tablebase() {
+  let tb = build("G", "G", 1, 3);
+  let positions: Vec<_> = (0..tb.len()).filter_map(|i| tb.position(i)).collect();
+  let mut s = Search::new(1 << 20);
+  let mut t = Search::new(1 << 20);
+  t.add_tablebase(tb);
+  for pos in positions {
+    let (mut p, mut q) = (pos.clone(), pos.clone());
+    let a = s.search(&mut p, 5);
+    s.hashes_clear();
+    let b = t.search(&mut q, 5);
+    t.hashes_clear();
+    assert_eq!(a.0, b.0, "sfen: {}", pos);
+    assert_eq!(a.1.is_some(), b.1.is_some(), "sfen: {}", pos);
+  }
+}
@@ -0,0 +1,68 @@
+use tsumeshogi_check::shogi::alloc::PositionMovesAllocator;
+use tsumeshogi_check::shogi::usi::{moves_to_usi, parse_usi_position, usi_position};
+use tsumeshogi_check::shogi::{perft, Position};
+
+#[test]
+fn usi_moves() {
+  let mut allocator = PositionMovesAllocator::default();
+  for sfen in [
+    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+    "G1+R4nl/2l+B1+N3/7pp/pgkpp1s2/1P1n1Pp2/g3P2RP/5+p3/2p3s1K/+b6NL b G2sl6p 1",
+    "lr5nl/3g1kg2/2n1ppsp1/p1pps1p1p/1p5P1/P1PPSPP1P/1PSG1G3/2K4R1/LN5NL w Bb 1",
+  ] {
+    let mut pos = Position::parse_sfen(sfen).unwrap();
+    for m in perft::legal_moves(&mut pos, &mut allocator) {
+      let usi = m.to_usi();
+      assert_eq!(
+        pos.parse_usi_move(&mut allocator, &usi),
+        Some(m.clone()),
+        "{} in {}",
+        usi,
+        sfen
+      );
+    }
+  }
+  let mut pos = Position::default();
+  assert!(pos.parse_usi_move(&mut allocator, "7g7e").is_none());
+  assert!(pos.parse_usi_move(&mut allocator, "P*5e").is_none());
+}
+
+#[test]
+fn usi_position_strings() {
+  let (pos, moves) =
+    parse_usi_position("position startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e").unwrap();
+  assert_eq!(pos.to_string(), Position::default().to_string());
+  let kif: Vec<_> = moves.iter().map(|m| m.to_kif(&None)).collect();
+  assert_eq!(
+    kif,
+    vec![
+      "７六歩(77)",
+      "３四歩(33)",
+      "２二角成(88)",
+      "２二銀(31)",
+      "４五角打"
+    ]
+  );
+  assert_eq!(moves_to_usi(&moves), "7g7f 3c3d 8h2b+ 3a2b B*4e");
+  assert_eq!(
+    usi_position(&pos, &moves),
+    "position startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e"
+  );
+  let s = "position sfen 7nl/5B1k1/6Ppp/9/9/9/9/9/9 b RGrb3g4s3n3l15p 1 moves G*3b 2b1b";
+  let (pos, moves) = parse_usi_position(s).unwrap();
+  assert_eq!(moves.len(), 2);
+  let (pos2, moves2) = parse_usi_position(&usi_position(&pos, &moves)).unwrap();
+  assert_eq!(pos2.to_string(), pos.to_string());
+  assert_eq!(moves2, moves);
+  //move number is optional, "position" too
+  let (pos, moves) = parse_usi_position("sfen 4k4/9/4P4/9/9/9/9/9/9 b G").unwrap();
+  assert_eq!(pos.move_no, 1);
+  assert!(moves.is_empty());
+  for s in [
+    "position startpos moves 7g7f 7g7f",
+    "position startpos 7g7f",
+    "position 4k4/9/4P4/9/9/9/9/9/9 b G 1",
+  ] {
+    assert!(parse_usi_position(s).is_err(), "{}", s);
+  }
+}